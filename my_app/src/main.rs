@@ -1,6 +1,19 @@
 extern crate my_lib;
 
+use std::env;
+use std::process;
+
 fn main() {
+    // "my_app serve [options]"는 튜토리얼 없이 웹서버만 띄운다
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("serve") {
+        if let Err(e) = my_lib::webserver::serve(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+        return;
+    }
+
     my_lib::guess_game::sample();               // 2. 추리 게임 튜토리얼
     my_lib::common::sample();                   // 3. 보편적인 프로그래밍 개념
     my_lib::ownership::sample();                // 4. 소유권 이해하기
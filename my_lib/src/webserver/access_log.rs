@@ -0,0 +1,272 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use super::date::DateTime;
+use super::request::Request;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    // 127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326 1520
+    Common,
+    // Common 뒤에 "Referer" "User-Agent"가 붙는다
+    Combined,
+    // 한 줄에 JSON 객체 하나. 로그 수집기에서 따로 파싱 규칙을 만들 필요가 없다
+    Json,
+}
+
+// 로그 한 줄에 들어갈 정보
+pub struct LogEntry<'a> {
+    pub remote_addr: Option<SocketAddr>,
+    pub request: Option<&'a Request>,  // 요청을 파싱하지 못해 바로 거절한 경우 None
+    pub status: u16,
+    pub bytes: u64,                    // 보낸 본문 바이트 수 (헤더 제외)
+    pub duration: Duration,            // 요청을 다 읽은 뒤부터 응답을 다 보낼 때까지
+    pub time: SystemTime,
+}
+
+/*
+요청 하나가 끝날 때마다 한 줄씩 기록하는 access log
+여러 worker가 동시에 쓰므로 sink는 Mutex로 보호하고, 줄이 섞이지 않게 한 줄을 한 번에 쓴다
+Common/Combined 형식의 마지막 필드는 처리 시간(마이크로초, Apache의 %D)이다
+*/
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn new<W: Write + Send + 'static>(format: LogFormat, sink: W) -> AccessLog {
+        AccessLog { format, sink: Mutex::new(Box::new(sink)) }
+    }
+
+    pub fn stdout(format: LogFormat) -> AccessLog {
+        AccessLog::new(format, io::stdout())
+    }
+
+    // 파일에 기록하고, 파일이 max_size 바이트를 넘으면 path.1, path.2 ... 로 돌린다
+    pub fn file<P: AsRef<Path>>(format: LogFormat, path: P, max_size: u64, max_files: usize) -> io::Result<AccessLog> {
+        Ok(AccessLog::new(format, RotatingFile::open(path, max_size, max_files)?))
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    pub fn log(&self, entry: &LogEntry) {
+        let mut line = format_entry(self.format, entry);
+        line.push('\n');
+
+        // 다른 worker가 쓰다가 panic했더라도 로그는 계속 남긴다
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = sink.write_all(line.as_bytes()).and_then(|_| sink.flush()) {
+            println!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+fn format_entry(format: LogFormat, entry: &LogEntry) -> String {
+    let remote = entry.remote_addr.map(|a| a.ip().to_string()).unwrap_or_else(|| String::from("-"));
+    let header = |name: &str| entry.request.and_then(|r| r.header(name));
+
+    match format {
+        LogFormat::Common | LogFormat::Combined => {
+            let request_line = match entry.request {
+                Some(r) => escape_clf(&format!("{} {} {}", r.method, r.target, r.version)),
+                None => String::from("-"),
+            };
+            let bytes = if entry.bytes == 0 { String::from("-") } else { entry.bytes.to_string() };
+            let mut line = format!(
+                "{} - - [{}] \"{}\" {} {}",
+                remote, clf_time(entry.time), request_line, entry.status, bytes
+            );
+            if format == LogFormat::Combined {
+                let quoted = |v: Option<&str>| v.map(escape_clf).unwrap_or_else(|| String::from("-"));
+                line.push_str(&format!(" \"{}\" \"{}\"", quoted(header("Referer")), quoted(header("User-Agent"))));
+            }
+            line.push_str(&format!(" {}", entry.duration.as_micros()));
+            line
+        },
+        LogFormat::Json => {
+            let string = |v: Option<&str>| v.map(json_string).unwrap_or_else(|| String::from("null"));
+            format!(
+                "{{\"time\":\"{}\",\"remote_addr\":{},\"method\":{},\"path\":{},\"version\":{},\"status\":{},\"bytes\":{},\"duration_ms\":{:.3},\"referer\":{},\"user_agent\":{}}}",
+                iso_time(entry.time),
+                string(entry.remote_addr.map(|_| remote.as_str())),
+                string(entry.request.map(|r| r.method.as_str())),
+                string(entry.request.map(|r| r.target.as_str())),
+                string(entry.request.map(|r| r.version.to_string()).as_deref()),
+                entry.status,
+                entry.bytes,
+                entry.duration.as_secs_f64() * 1000.0,
+                string(header("Referer")),
+                string(header("User-Agent")),
+            )
+        },
+    }
+}
+
+// [10/Oct/2000:13:55:36 +0000]
+fn clf_time(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    format!("{:02}/{}/{}:{:02}:{:02}:{:02} +0000", t.day, t.month_name(), t.year, t.hour, t.minute, t.second)
+}
+
+// 2000-10-10T13:55:36Z
+fn iso_time(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", t.year, t.month, t.day, t.hour, t.minute, t.second)
+}
+
+// 클라이언트가 보낸 값에 따옴표나 줄바꿈을 넣어 로그 줄을 위조하지 못하게 한다
+fn escape_clf(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/*
+크기 기준으로 돌아가는 로그 파일
+파일이 max_size를 넘기 전에 path -> path.1, path.1 -> path.2 ... 로 이름을 바꾸고 새 파일을 연다
+max_files개보다 오래된 파일은 지운다. max_files가 0이면 이전 내용을 남기지 않는다
+*/
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn open<P: AsRef<Path>>(path: P, max_size: u64, max_files: usize) -> io::Result<RotatingFile> {
+        assert!(max_size > 0);
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path, max_size, max_files, file, size })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files > 0 {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    // AccessLog는 한 줄을 write 한 번으로 쓰므로 한 줄이 두 파일로 나뉘지 않는다
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::request::Method;
+    use crate::webserver::test_util::TempDir;
+    use std::time::UNIX_EPOCH;
+
+    fn entry(request: &Request) -> LogEntry<'_> {
+        LogEntry {
+            remote_addr: Some("10.0.0.1:5000".parse().unwrap()),
+            request: Some(request),
+            status: 200,
+            bytes: 2326,
+            duration: Duration::from_micros(1520),
+            time: UNIX_EPOCH + Duration::from_secs(971186136),
+        }
+    }
+
+    #[test]
+    fn formats_common_combined_and_json() {
+        let mut request = Request::new(Method::Get, "/index.html");
+        request.headers.insert("User-Agent", "curl/8.0 \"test\"");
+
+        assert_eq!(
+            "10.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /index.html HTTP/1.1\" 200 2326 1520",
+            format_entry(LogFormat::Common, &entry(&request))
+        );
+        assert_eq!(
+            "10.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /index.html HTTP/1.1\" 200 2326 \"-\" \"curl/8.0 \\\"test\\\"\" 1520",
+            format_entry(LogFormat::Combined, &entry(&request))
+        );
+        assert_eq!(
+            "{\"time\":\"2000-10-10T13:55:36Z\",\"remote_addr\":\"10.0.0.1\",\"method\":\"GET\",\"path\":\"/index.html\",\"version\":\"HTTP/1.1\",\"status\":200,\"bytes\":2326,\"duration_ms\":1.520,\"referer\":null,\"user_agent\":\"curl/8.0 \\\"test\\\"\"}",
+            format_entry(LogFormat::Json, &entry(&request))
+        );
+    }
+
+    #[test]
+    fn rotates_file_by_size() {
+        let dir = TempDir::new("access_log");
+        let path = dir.join("access.log");
+
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!("dddddd\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("cccccc\n", fs::read_to_string(dir.join("access.log.1")).unwrap());
+        assert_eq!("bbbbbb\n", fs::read_to_string(dir.join("access.log.2")).unwrap());
+        assert!(!dir.join("access.log.3").exists());
+    }
+}
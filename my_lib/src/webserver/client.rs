@@ -0,0 +1,727 @@
+// blocking HTTP/1.1 클라이언트. 서버 테스트와 reverse proxy에서 쓴다 (https는 지원하지 않는다)
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::headers::Headers;
+use super::response::ChunkedWriter;
+use super::request::{
+    content_length, is_chunked, read_chunk_size, read_headers, read_line, Method, ParseError, Request, Version,
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+// 호스트마다 쉬고 있는 연결을 몇 개까지 남겨 둘지
+const MAX_IDLE_PER_HOST: usize = 4;
+// bytes()로 메모리에 모을 수 있는 최대 본문 크기
+const MAX_RESPONSE_LEN: u64 = 64 * 1024 * 1024;
+// redirect를 따라가기 전에 이전 응답의 본문을 이만큼까지는 읽어 버리고 연결을 재사용한다
+const MAX_DRAIN_LEN: u64 = 64 * 1024;
+// 최종 응답 앞에 받아 줄 100 Continue 같은 중간 응답 수
+const MAX_INTERIM_RESPONSES: usize = 10;
+// send_reader()가 본문을 한 번에 읽어 보내는 크기
+const BODY_BUFFER_LEN: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidUrl(String),
+    Io(io::Error),
+    Body(io::Error),    // send_reader()에 준 본문을 읽지 못함
+    InvalidResponse(ParseError),
+    TooManyRedirects,
+    TooLarge,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "invalid url: {}", url),
+            ClientError::Io(e) => write!(f, "i/o error: {}", e),
+            ClientError::Body(e) => write!(f, "cannot read request body: {}", e),
+            ClientError::InvalidResponse(e) => write!(f, "invalid response: {}", e),
+            ClientError::TooManyRedirects => f.write_str("too many redirects"),
+            ClientError::TooLarge => f.write_str("response body too large"),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> ClientError {
+        ClientError::Io(e)
+    }
+}
+
+impl From<ParseError> for ClientError {
+    fn from(e: ParseError) -> ClientError {
+        match e {
+            ParseError::Io(e) => ClientError::Io(e),
+            e => ClientError::InvalidResponse(e),
+        }
+    }
+}
+
+// "http://host:port/path?query"를 나눈 것
+#[derive(Debug, Clone, PartialEq)]
+struct Url {
+    host: String,   // IPv6 주소는 [] 없이
+    port: u16,
+    target: String, // 경로 + 쿼리. '#' 뒤는 서버로 보내지 않는다
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, ClientError> {
+        let invalid = || ClientError::InvalidUrl(url.to_string());
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let rest = rest.split('#').next().unwrap_or("");
+        let (authority, target) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() || authority.contains('@') {
+            return Err(invalid());
+        }
+
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                (&authority[..i], authority[i + 1..].parse().map_err(|_| invalid())?)
+            },
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let target = if target.starts_with('?') { format!("/{}", target) } else { target.to_string() };
+        Ok(Url { host: host.to_string(), port, target })
+    }
+
+    // Host 헤더 값. 기본 포트는 생략한다
+    fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        if self.port == 80 {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+
+    // Location 헤더를 이 URL 기준으로 해석한다
+    fn join(&self, location: &str) -> Result<Url, ClientError> {
+        if location.starts_with("http://") {
+            return Url::parse(location);
+        }
+        if let Some(rest) = location.strip_prefix("//") {
+            return Url::parse(&format!("http://{}", rest));
+        }
+        if location.contains("://") {
+            return Err(ClientError::InvalidUrl(location.to_string()));
+        }
+
+        let target = if location.starts_with('/') {
+            location.to_string()
+        } else if location.is_empty() || location.starts_with('#') {
+            self.target.clone()
+        } else if location.starts_with('?') {
+            // 쿼리만 있으면 경로는 그대로 두고 쿼리만 바꾼다 (RFC 3986 5.2.2)
+            format!("{}{}", self.target.split('?').next().unwrap_or("/"), location)
+        } else {
+            // 상대 경로는 현재 경로의 마지막 '/'까지를 기준으로 한다
+            let path = self.target.split('?').next().unwrap_or("/");
+            let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", dir, location)
+        };
+        Ok(Url { host: self.host.clone(), port: self.port, target: target.split('#').next().unwrap_or("").to_string() })
+    }
+}
+
+type Connection = BufReader<TcpStream>;
+
+// keep-alive로 다시 쓸 수 있는 연결들. 응답 본문을 끝까지 읽으면 연결이 여기로 돌아온다
+struct Pool {
+    idle: Mutex<HashMap<(String, u16), Vec<Connection>>>,
+}
+
+impl Pool {
+    fn take(&self, key: &(String, u16)) -> Option<Connection> {
+        self.idle.lock().unwrap().get_mut(key).and_then(|conns| conns.pop())
+    }
+
+    fn put(&self, key: (String, u16), conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(key).or_default();
+        if conns.len() < MAX_IDLE_PER_HOST {
+            conns.push(conn);
+        }
+    }
+}
+
+/*
+요청을 보내고 응답을 받는 클라이언트. 여러 쓰레드에서 함께 써도 된다
+    let client = Client::new();
+    let mut response = client.get("http://127.0.0.1:7878/users/7")?;
+    assert_eq!(200, response.status);
+    let body = response.text()?;
+같은 호스트로 가는 요청은 keep-alive 연결을 재사용하고, 3xx 응답은 Location을 따라간다
+*/
+pub struct Client {
+    pool: Arc<Pool>,
+    timeout: Duration,
+    max_redirects: usize,
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client {
+            pool: Arc::new(Pool { idle: Mutex::new(HashMap::new()) }),
+            timeout: DEFAULT_TIMEOUT,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    // 연결, 읽기, 쓰기 각각에 걸리는 시간 제한
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    // 따라갈 redirect의 최대 횟수. 0이면 3xx 응답을 그대로 돌려준다
+    pub fn set_max_redirects(&mut self, max: usize) {
+        self.max_redirects = max;
+    }
+
+    pub fn get(&self, url: &str) -> Result<ClientResponse, ClientError> {
+        self.send(url, Request::new(Method::Get, "/"))
+    }
+
+    pub fn post<B: Into<Vec<u8>>>(&self, url: &str, content_type: &str, body: B) -> Result<ClientResponse, ClientError> {
+        let mut request = Request::new(Method::Post, "/");
+        request.headers.insert("Content-Type", content_type);
+        request.body = body.into();
+        self.send(url, request)
+    }
+
+    /*
+    request의 메소드, 헤더, 본문으로 url에 요청한다. target과 Host 헤더는 url로 채운다
+    301/302(POST일 때)와 303은 GET으로 바꿔서, 307/308은 메소드와 본문을 그대로 다시 보낸다
+    다른 호스트로 redirect되면 Authorization, Cookie 헤더는 보내지 않는다
+    */
+    pub fn send(&self, url: &str, mut request: Request) -> Result<ClientResponse, ClientError> {
+        let mut url = Url::parse(url)?;
+        let mut redirects = 0;
+
+        loop {
+            let mut response = self.execute(&url, &mut request)?;
+            let location = match response.header("Location") {
+                Some(location) if is_redirect(response.status) && self.max_redirects > 0 => location.to_string(),
+                _ => return Ok(response),
+            };
+            if redirects == self.max_redirects {
+                return Err(ClientError::TooManyRedirects);
+            }
+            redirects += 1;
+
+            let next = url.join(&location)?;
+            let status = response.status;
+            // 본문이 짧으면 끝까지 읽어 연결을 pool로 돌려보낸다
+            let _ = io::copy(&mut (&mut response).take(MAX_DRAIN_LEN), &mut io::sink());
+            drop(response);
+
+            if status == 303 || ((status == 301 || status == 302) && request.method == Method::Post) {
+                request.method = Method::Get;
+                request.body.clear();
+                request.headers.remove("Content-Type");
+            }
+            if (&next.host, next.port) != (&url.host, url.port) {
+                request.headers.remove("Authorization");
+                request.headers.remove("Cookie");
+            }
+            url = next;
+        }
+    }
+
+    /*
+    send()와 같지만 본문을 메모리에 모으지 않고 body에서 읽는 대로 보낸다 (request.body는 쓰지 않는다)
+    len을 알면 Content-Length로, None이면 chunked로 보낸다. 본문이 len보다 짧으면 Body 에러
+    본문을 다시 읽을 수 없으므로 redirect는 따라가지 않고, 이미 끊어졌을 수 있는 keep-alive 연결 대신 항상 새로 연결한다
+    */
+    pub fn send_reader<R: Read>(&self, url: &str, mut request: Request, mut body: R, len: Option<u64>) -> Result<ClientResponse, ClientError> {
+        let url = Url::parse(url)?;
+        prepare(&url, &mut request);
+        let mut conn = self.connect(&url)?;
+
+        let stream = conn.get_mut();
+        request.write_head(stream, len)?;
+        match len {
+            Some(len) => {
+                if copy_body(&mut (&mut body).take(len), stream)? < len {
+                    return Err(ClientError::Body(io::Error::new(io::ErrorKind::UnexpectedEof, "body ended before its length")));
+                }
+            },
+            None => {
+                let mut chunked = ChunkedWriter::new(stream, true);
+                copy_body(&mut body, &mut chunked)?;
+                chunked.finish()?;
+            },
+        }
+        stream.flush()?;
+        self.receive(conn, (url.host.clone(), url.port), &request)
+    }
+
+    /*
+    요청 하나를 보내고 응답 헤더까지 읽는다
+    pool에서 꺼낸 연결은 서버가 keep-alive timeout으로 이미 닫았을 수 있다. 응답을 한 바이트도 받지 못했으면
+    다시 보내도 안전한 메소드에 한해 새 연결로 한 번 더 보낸다
+    */
+    fn execute(&self, url: &Url, request: &mut Request) -> Result<ClientResponse, ClientError> {
+        prepare(url, request);
+        let key = (url.host.clone(), url.port);
+        if let Some(conn) = self.pool.take(&key) {
+            match self.exchange(conn, key.clone(), request) {
+                Err(ref e) if is_stale(e) && is_idempotent(&request.method) => {},
+                result => return result,
+            }
+        }
+        let conn = self.connect(url)?;
+        self.exchange(conn, key, request)
+    }
+
+    fn connect(&self, url: &Url) -> Result<Connection, ClientError> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host has no address");
+        for addr in (url.host.as_str(), url.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    // 요청은 한 번에 쓰므로 Nagle 알고리즘으로 기다릴 이유가 없다
+                    stream.set_nodelay(true)?;
+                    return Ok(BufReader::new(stream));
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        Err(ClientError::Io(last_error))
+    }
+
+    fn exchange(&self, mut conn: Connection, key: (String, u16), request: &Request) -> Result<ClientResponse, ClientError> {
+        request.write_to(conn.get_mut())?;
+        self.receive(conn, key, request)
+    }
+
+    // 요청을 다 보낸 연결에서 응답 헤더를 읽는다
+    fn receive(&self, mut conn: Connection, key: (String, u16), request: &Request) -> Result<ClientResponse, ClientError> {
+        // 100 Continue 같은 중간 응답은 건너뛴다. 끝없이 보내는 서버에 묶이지 않도록 수를 제한한다
+        let mut interim = 0;
+        let (version, status, headers) = loop {
+            let line = read_line(&mut conn)?.ok_or(ParseError::Eof)?;
+            let (version, status) = parse_status_line(&line)?;
+            let headers = read_headers(&mut conn)?;
+            if !(100..200).contains(&status) || status == 101 {
+                break (version, status, headers);
+            }
+            interim += 1;
+            if interim > MAX_INTERIM_RESPONSES {
+                return Err(ClientError::InvalidResponse(ParseError::Malformed("too many interim responses")));
+            }
+        };
+
+        // 본문 길이를 정하는 순서 (RFC 7230 3.3.3)
+        let no_body = request.method == Method::Head || (100..200).contains(&status) || status == 204 || status == 304;
+        let framing = if no_body {
+            Framing::Length(0)
+        } else if is_chunked(&headers)? {
+            Framing::Chunked { remaining: 0, started: false }
+        } else if let Some(len) = content_length(&headers)? {
+            Framing::Length(len)
+        } else {
+            Framing::UntilClose
+        };
+        let reusable = status != 101
+            && !matches!(framing, Framing::UntilClose)
+            && match version {
+                Version::Http11 => !headers.has_token("Connection", "close"),
+                Version::Http10 => headers.has_token("Connection", "keep-alive"),
+            };
+
+        let mut body = ResponseBody {
+            conn: Some(conn),
+            framing,
+            pool: Arc::clone(&self.pool),
+            key,
+            reusable,
+            trailers: Headers::new(),
+        };
+        body.release_if_done();
+        Ok(ClientResponse { status, version, headers, body })
+    }
+}
+
+// target과 Host 헤더를 url로 채운다
+fn prepare(url: &Url, request: &mut Request) {
+    request.target = url.target.clone();
+    request.version = Version::Http11;
+    request.headers.insert("Host", &url.authority());
+}
+
+// 본문을 읽다 난 에러는 Body로, 연결에 쓰다 난 에러는 Io로 구분한다
+fn copy_body<R: Read, W: Write>(body: &mut R, writer: &mut W) -> Result<u64, ClientError> {
+    let mut buffer = [0; BODY_BUFFER_LEN];
+    let mut copied = 0;
+    loop {
+        let n = match body.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ClientError::Body(e)),
+        };
+        writer.write_all(&buffer[..n])?;
+        copied += n as u64;
+    }
+}
+
+fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(method, Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options | Method::Trace)
+}
+
+// 재사용한 연결이 끊어져 있어서 난 에러인지
+fn is_stale(e: &ClientError) -> bool {
+    match e {
+        ClientError::InvalidResponse(ParseError::Eof) => true,
+        ClientError::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+// "HTTP/1.1 404 Not Found". reason phrase는 비어 있을 수 있다
+fn parse_status_line(line: &str) -> Result<(Version, u16), ParseError> {
+    let (version, rest) = line.split_once(' ').ok_or(ParseError::Malformed("invalid status line"))?;
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        v if v.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+        _ => return Err(ParseError::Malformed("invalid status line")),
+    };
+    let code = rest.split(' ').next().unwrap_or("");
+    if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::Malformed("invalid status line"));
+    }
+    Ok((version, code.parse().map_err(|_| ParseError::Malformed("invalid status line"))?))
+}
+
+enum Framing {
+    Length(u64),                                // 남은 바이트 수
+    Chunked { remaining: u64, started: bool },  // 현재 chunk에 남은 바이트 수
+    UntilClose,                                 // 서버가 연결을 닫을 때까지
+    Done,
+}
+
+/*
+응답 본문을 연결에서 읽어 오는 reader
+끝까지 읽으면 keep-alive 연결을 pool로 돌려보낸다. 중간에 버리면 연결도 함께 닫힌다
+*/
+struct ResponseBody {
+    conn: Option<Connection>,
+    framing: Framing,
+    pool: Arc<Pool>,
+    key: (String, u16),
+    reusable: bool,
+    trailers: Headers,
+}
+
+impl ResponseBody {
+    fn release_if_done(&mut self) {
+        if matches!(self.framing, Framing::Length(0)) {
+            self.framing = Framing::Done;
+        }
+        if matches!(self.framing, Framing::Done) {
+            if let Some(conn) = self.conn.take() {
+                if self.reusable {
+                    self.pool.put(self.key.clone(), conn);
+                }
+            }
+        }
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, ParseError> {
+        let conn = match self.conn.as_mut() {
+            Some(conn) if !buf.is_empty() => conn,
+            _ => return Ok(0),
+        };
+
+        let n = match &mut self.framing {
+            Framing::Done => 0,
+            Framing::UntilClose => {
+                let n = conn.read(buf)?;
+                if n == 0 {
+                    self.framing = Framing::Done;
+                }
+                n
+            },
+            Framing::Length(remaining) => {
+                let len = (*remaining).min(buf.len() as u64) as usize;
+                let n = conn.read(&mut buf[..len])?;
+                if n == 0 {
+                    return Err(ParseError::Malformed("incomplete body"));
+                }
+                *remaining -= n as u64;
+                n
+            },
+            Framing::Chunked { remaining, started } => {
+                if *remaining == 0 {
+                    if *started {
+                        let mut crlf = [0; 2];
+                        conn.read_exact(&mut crlf)?;
+                        if &crlf != b"\r\n" {
+                            return Err(ParseError::Malformed("missing CRLF after chunk"));
+                        }
+                    }
+                    *started = true;
+                    *remaining = read_chunk_size(conn)?;
+                    if *remaining == 0 {
+                        self.trailers = read_headers(conn)?;
+                        self.framing = Framing::Done;
+                        self.release_if_done();
+                        return Ok(0);
+                    }
+                }
+                let len = (*remaining).min(buf.len() as u64) as usize;
+                let n = conn.read(&mut buf[..len])?;
+                if n == 0 {
+                    return Err(ParseError::Malformed("incomplete chunk"));
+                }
+                *remaining -= n as u64;
+                n
+            },
+        };
+        self.release_if_done();
+        Ok(n)
+    }
+}
+
+impl Read for ResponseBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_body(buf).map_err(|e| match e {
+            ParseError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })
+    }
+}
+
+/*
+받은 응답. 상태와 헤더는 바로 볼 수 있고, 본문은 Read로 조금씩 읽거나 bytes()/text()로 한 번에 읽는다
+본문을 끝까지 읽어야 연결이 재사용된다
+*/
+pub struct ClientResponse {
+    pub status: u16,
+    pub version: Version,
+    pub headers: Headers,
+    body: ResponseBody,
+}
+
+impl ClientResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    // chunked 본문 뒤에 온 trailer. 본문을 끝까지 읽은 뒤에 채워진다
+    pub fn trailers(&self) -> &Headers {
+        &self.body.trailers
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, ClientError> {
+        let mut body = Vec::new();
+        self.take(MAX_RESPONSE_LEN + 1).read_to_end(&mut body)?;
+        if body.len() as u64 > MAX_RESPONSE_LEN {
+            return Err(ClientError::TooLarge);
+        }
+        Ok(body)
+    }
+
+    // UTF-8이 아닌 바이트는 U+FFFD로 바꾼다
+    pub fn text(&mut self) -> Result<String, ClientError> {
+        Ok(String::from_utf8_lossy(&self.bytes()?).into_owned())
+    }
+}
+
+impl Read for ClientResponse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl fmt::Debug for ClientResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientResponse").field("status", &self.status).field("headers", &self.headers).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Response, Router, Server};
+    use std::io::Write;
+    use std::thread;
+
+    fn start(router: Router) -> String {
+        let server = Server::bind("127.0.0.1:0", 4, router).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        format!("http://{}", addr)
+    }
+
+    fn test_router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/hello", |_, _| Response::text(200, "hello"))
+            .get("/stream", |_, _| {
+                Response::text(200, "").with_stream(|writer| {
+                    for i in 0..3 {
+                        write!(writer, "part {};", i)?;
+                        writer.flush()?;
+                    }
+                    writer.trailer("X-Parts", "3");
+                    Ok(())
+                })
+            })
+            // 연결을 재사용하는지 확인할 수 있게 클라이언트 쪽 포트를 돌려준다
+            .get("/port", |request, _| Response::text(200, request.remote_addr.unwrap().port().to_string()))
+            .post("/echo", |request, _| Response::text(200, request.body.clone()))
+            .post("/submit", |_, _| Response::redirect(303, "/hello"))
+            .get("/docs/old", |_, _| Response::redirect(301, "new?x=1"))
+            .get("/docs/new", |request, _| Response::text(200, request.target.clone()))
+            .get("/docs/list", |request, _| {
+                if request.target.contains('?') {
+                    Response::text(200, request.target.clone())
+                } else {
+                    Response::redirect(302, "?page=2")
+                }
+            })
+            .get("/loop", |_, _| Response::redirect(302, "/loop"));
+        router
+    }
+
+    #[test]
+    fn parses_urls() {
+        let url = Url::parse("http://example.com:8080/a/b?q=1#frag").unwrap();
+        assert_eq!(("example.com", 8080, "/a/b?q=1"), (url.host.as_str(), url.port, url.target.as_str()));
+        assert_eq!("example.com:8080", url.authority());
+        assert_eq!("/a/c", url.join("c").unwrap().target);
+        assert_eq!("/x", url.join("/x").unwrap().target);
+        assert_eq!("other", url.join("//other/").unwrap().host);
+        // 쿼리만 있는 참조는 현재 경로에 붙는다
+        assert_eq!("/a/b?page=2", url.join("?page=2").unwrap().target);
+        assert_eq!("/a/b?q=1", url.join("#top").unwrap().target);
+        assert_eq!("/a/b?q=1", url.join("").unwrap().target);
+
+        let url = Url::parse("http://[::1]?q").unwrap();
+        assert_eq!(("::1", 80, "/?q"), (url.host.as_str(), url.port, url.target.as_str()));
+        assert_eq!("[::1]", url.authority());
+        assert!(Url::parse("https://example.com/").is_err());
+        assert!(Url::parse("http://user@example.com/").is_err());
+    }
+
+    #[test]
+    fn reads_length_and_chunked_bodies() {
+        let base = start(test_router());
+        let client = Client::new();
+
+        let mut response = client.get(&format!("{}/hello", base)).unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(Some("text/plain; charset=utf-8"), response.header("Content-Type"));
+        assert_eq!("hello", response.text().unwrap());
+
+        let mut response = client.get(&format!("{}/stream", base)).unwrap();
+        assert_eq!(Some("chunked"), response.header("Transfer-Encoding"));
+        assert_eq!("part 0;part 1;part 2;", response.text().unwrap());
+        assert_eq!(Some("3"), response.trailers().get("X-Parts"));
+
+        let mut response = client.post(&format!("{}/echo", base), "text/plain", "ping").unwrap();
+        assert_eq!("ping", response.text().unwrap());
+
+        let mut response = client.send(&format!("{}/hello", base), Request::new(Method::Head, "/")).unwrap();
+        assert_eq!(Some("5"), response.header("Content-Length"));
+        assert_eq!("", response.text().unwrap());
+        assert_eq!(404, client.get(&format!("{}/missing", base)).unwrap().status);
+    }
+
+    #[test]
+    fn reuses_keep_alive_connections() {
+        let base = start(test_router());
+        let client = Client::new();
+
+        let first = client.get(&format!("{}/port", base)).unwrap().text().unwrap();
+        let second = client.get(&format!("{}/port", base)).unwrap().text().unwrap();
+        assert_eq!(first, second);
+
+        // 본문을 다 읽지 않은 응답의 연결은 재사용하지 않는다
+        let unread = client.get(&format!("{}/port", base)).unwrap();
+        let third = client.get(&format!("{}/port", base)).unwrap().text().unwrap();
+        assert_ne!(first, third);
+        drop(unread);
+    }
+
+    #[test]
+    fn follows_redirects() {
+        let base = start(test_router());
+        let mut client = Client::new();
+
+        let mut response = client.get(&format!("{}/docs/old", base)).unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!("/docs/new?x=1", response.text().unwrap());
+
+        // 303은 POST를 GET으로 바꾼다
+        let mut response = client.post(&format!("{}/submit", base), "text/plain", "data").unwrap();
+        assert_eq!("hello", response.text().unwrap());
+
+        assert!(matches!(client.get(&format!("{}/loop", base)), Err(ClientError::TooManyRedirects)));
+
+        // 쿼리만 바꾸는 redirect는 같은 경로로 돌아온다
+        let mut response = client.get(&format!("{}/docs/list", base)).unwrap();
+        assert_eq!("/docs/list?page=2", response.text().unwrap());
+
+        client.set_max_redirects(0);
+        let response = client.get(&format!("{}/docs/old", base)).unwrap();
+        assert_eq!(301, response.status);
+        assert_eq!(Some("new?x=1"), response.header("Location"));
+    }
+
+    #[test]
+    fn limits_interim_responses() {
+        // 요청 하나에 100 Continue를 count번 보낸 뒤 최종 응답을 보내는 서버
+        let serve = |count: usize| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while read_line(&mut reader).unwrap().is_some_and(|line| !line.is_empty()) {}
+                let mut response = "HTTP/1.1 100 Continue\r\n\r\n".repeat(count);
+                response.push_str("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+                let _ = stream.write_all(response.as_bytes());
+            });
+            format!("http://{}/", addr)
+        };
+        let client = Client::new();
+
+        let mut response = client.get(&serve(MAX_INTERIM_RESPONSES)).unwrap();
+        assert_eq!((200, String::from("ok")), (response.status, response.text().unwrap()));
+        assert!(matches!(
+            client.get(&serve(MAX_INTERIM_RESPONSES + 1)),
+            Err(ClientError::InvalidResponse(ParseError::Malformed(_)))
+        ));
+    }
+}
@@ -0,0 +1,354 @@
+// 응답 본문 압축 (Content-Encoding: gzip, deflate)
+use std::io::{self, Read};
+
+use super::deflate::{deflate, inflate};
+use super::middleware::Middleware;
+use super::request::Request;
+use super::response::{Body, Response};
+
+// 이보다 작은 본문은 압축해도 헤더 오버헤드 때문에 이득이 거의 없다
+const DEFAULT_MIN_SIZE: u64 = 1024;
+// 파일 등 Reader 본문은 메모리로 읽어 압축하므로 너무 큰 것은 그대로 보낸다
+const MAX_BUFFERED: u64 = 8 * 1024 * 1024;
+
+// CRC-32 (gzip trailer). 다항식 0xEDB88320의 바이트별 표를 컴파일할 때 만든다
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, b| CRC_TABLE[((c ^ *b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+// Adler-32 (zlib trailer)
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552바이트마다 나머지를 구해도 u32가 넘치지 않는다 (zlib의 NMAX)
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+// gzip 형식 (RFC 1952): 10바이트 헤더 + DEFLATE + CRC-32 + 원래 길이
+pub fn gzip_encode(data: &[u8]) -> Vec<u8> {
+    // magic, CM=8(deflate), FLG=0, MTIME=0, XFL=0, OS=255(unknown)
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+pub fn gzip_decode(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+    if data.len() < 18 || data[..3] != [0x1f, 0x8b, 8] {
+        return Err(invalid("not a gzip stream"));
+    }
+
+    // 선택 필드들(FEXTRA, FNAME, FCOMMENT, FHCRC)을 건너뛴다
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 4 != 0 {
+        let len = data.get(pos..pos + 2).ok_or_else(|| invalid("truncated gzip header"))?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            let end = data.get(pos..).and_then(|d| d.iter().position(|b| *b == 0));
+            pos += end.ok_or_else(|| invalid("truncated gzip header"))? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        pos += 2;
+    }
+
+    let (out, used) = inflate(data.get(pos..).ok_or_else(|| invalid("truncated gzip header"))?, max_len)?;
+    let trailer = data.get(pos + used..pos + used + 8).ok_or_else(|| invalid("truncated gzip trailer"))?;
+    if u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != crc32(&out)
+        || u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) != out.len() as u32
+    {
+        return Err(invalid("gzip checksum mismatch"));
+    }
+    Ok(out)
+}
+
+// HTTP의 "deflate" coding은 raw DEFLATE가 아니라 zlib 형식 (RFC 1950)이다
+pub fn zlib_encode(data: &[u8]) -> Vec<u8> {
+    // CMF=0x78 (deflate, 32K window), FLG=0x9c: (CMF * 256 + FLG)가 31의 배수
+    let mut out = vec![0x78, 0x9c];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn zlib_decode(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+    if data.len() < 6 || data[0] & 0x0f != 8 || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) || data[1] & 0x20 != 0 {
+        return Err(invalid("not a zlib stream"));
+    }
+    let (out, used) = inflate(&data[2..], max_len)?;
+    let trailer = data.get(2 + used..6 + used).ok_or_else(|| invalid("truncated zlib trailer"))?;
+    if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&out) {
+        return Err(invalid("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Encoding::Gzip => gzip_encode(data),
+            Encoding::Deflate => zlib_encode(data),
+        }
+    }
+}
+
+/*
+Accept-Encoding에서 쓸 coding을 고른다
+    Accept-Encoding: gzip;q=0.8, deflate, br
+q 값이 가장 큰 것을 고르고 같으면 gzip을 먼저 쓴다. q=0은 거부, "*"는 나열되지 않은 나머지 전부
+*/
+pub fn negotiate_encoding(accept: &str) -> Option<Encoding> {
+    let mut gzip = None;
+    let mut deflate = None;
+    let mut any = None;
+
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q=").or_else(|| p.trim().strip_prefix("Q=")))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match coding.as_str() {
+            "gzip" | "x-gzip" => gzip = Some(q),
+            "deflate" => deflate = Some(q),
+            "*" => any = Some(q),
+            _ => {},
+        }
+    }
+
+    let gzip = gzip.or(any).unwrap_or(0.0);
+    let deflate = deflate.or(any).unwrap_or(0.0);
+    if gzip <= 0.0 && deflate <= 0.0 {
+        None
+    } else if gzip >= deflate {
+        Some(Encoding::Gzip)
+    } else {
+        Some(Encoding::Deflate)
+    }
+}
+
+// 압축해서 이득이 있는 텍스트 형식인지. 이미지/동영상/zip 등은 이미 압축되어 있다
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json" | "application/javascript" | "application/xml" | "application/wasm" | "image/svg+xml"
+        )
+}
+
+/*
+Accept-Encoding을 보고 응답 본문을 gzip 또는 deflate로 압축하는 middleware
+    router.wrap(Compression::new());
+텍스트 형식이고 min_size 이상인 본문만 압축한다. 부분 응답(206), 이미 Content-Encoding이 있는 응답,
+길이를 모르는 Stream 본문은 건드리지 않는다
+압축할 수 있는 형식이면 클라이언트가 압축을 받지 않더라도 Vary: Accept-Encoding을 붙여 캐시가 둘을 섞지 않게 한다
+*/
+pub struct Compression {
+    min_size: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::new()
+    }
+}
+
+impl Compression {
+    pub fn new() -> Compression {
+        Compression { min_size: DEFAULT_MIN_SIZE }
+    }
+
+    pub fn with_min_size(mut self, min_size: u64) -> Compression {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl Middleware for Compression {
+    fn after(&self, request: &Request, response: &mut Response) {
+        let status = response.status;
+        if status < 200 || status == 204 || status == 206 || status == 304 || response.upgrade.is_some() {
+            return;
+        }
+        if response.headers.contains("Content-Encoding") || response.body.is_stream() {
+            return;
+        }
+        if !response.headers.get("Content-Type").is_some_and(is_compressible) {
+            return;
+        }
+        if !response.headers.has_token("Vary", "Accept-Encoding") {
+            response.headers.append("Vary", "Accept-Encoding");
+        }
+
+        let len = response.body.len();
+        if len < self.min_size || len > MAX_BUFFERED {
+            return;
+        }
+        let encoding = match request.header("Accept-Encoding").and_then(negotiate_encoding) {
+            Some(encoding) => encoding,
+            None => return,
+        };
+
+        let body = std::mem::replace(&mut response.body, Body::Bytes(Vec::new()));
+        let data = match body {
+            Body::Bytes(bytes) => bytes,
+            Body::Reader(reader, len) => {
+                let mut data = Vec::with_capacity(len as usize);
+                if let Err(e) = reader.take(len).read_to_end(&mut data) {
+                    println!("Failed to read body for compression: {}", e);
+                    *response = Response::status_page(500);
+                    return;
+                }
+                data
+            },
+            Body::Stream(_) => unreachable!(),
+        };
+
+        // HEAD도 GET과 같은 헤더(Content-Length 포함)를 보내야 하므로 똑같이 압축한다
+        let compressed = encoding.encode(&data);
+        if compressed.len() >= data.len() {
+            response.body = Body::Bytes(data);
+            return;
+        }
+        response.body = Body::Bytes(compressed);
+        response.headers.insert("Content-Encoding", encoding.name());
+
+        // 압축한 표현은 원본과 바이트가 다르므로 strong ETag를 그대로 쓰면 안 된다. weak으로 바꾼다 (nginx와 같은 방식)
+        if let Some(etag) = response.headers.get("ETag") {
+            if !etag.starts_with("W/") {
+                let weak = format!("W/{}", etag);
+                response.headers.insert("ETag", &weak);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::Method;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+        assert_eq!(0x091E01DE, adler32(b"123456789"));
+        assert_eq!(1, adler32(b""));
+    }
+
+    #[test]
+    fn gzip_and_zlib_round_trip() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+        let gzip = gzip_encode(text.as_bytes());
+        assert_eq!(text.as_bytes(), &gzip_decode(&gzip, 1 << 20).unwrap()[..]);
+        let zlib = zlib_encode(text.as_bytes());
+        assert_eq!(text.as_bytes(), &zlib_decode(&zlib, 1 << 20).unwrap()[..]);
+
+        let mut corrupt = gzip.clone();
+        let n = corrupt.len();
+        corrupt[n - 5] ^= 1;
+        assert!(gzip_decode(&corrupt, 1 << 20).is_err());
+    }
+
+    #[test]
+    fn negotiates_accept_encoding() {
+        assert_eq!(Some(Encoding::Gzip), negotiate_encoding("gzip, deflate, br"));
+        assert_eq!(Some(Encoding::Deflate), negotiate_encoding("gzip;q=0.5, deflate"));
+        assert_eq!(Some(Encoding::Deflate), negotiate_encoding("deflate"));
+        assert_eq!(Some(Encoding::Gzip), negotiate_encoding("*"));
+        assert_eq!(None, negotiate_encoding("gzip;q=0, deflate;q=0"));
+        assert_eq!(None, negotiate_encoding("*;q=0"));
+        assert_eq!(None, negotiate_encoding("br, identity"));
+    }
+
+    fn compressed(accept: Option<&str>, response: Response) -> Response {
+        let mut request = Request::new(Method::Get, "/");
+        if let Some(accept) = accept {
+            request.headers.insert("Accept-Encoding", accept);
+        }
+        let mut response = response;
+        Compression::new().after(&request, &mut response);
+        response
+    }
+
+    #[test]
+    fn compresses_text_responses_only() {
+        let html = "<p>log line</p>\n".repeat(200);
+
+        let response = compressed(Some("gzip"), Response::html(200, html.clone()).with_header("ETag", "\"abc\""));
+        assert_eq!(Some("gzip"), response.headers.get("Content-Encoding"));
+        assert_eq!(Some("Accept-Encoding"), response.headers.get("Vary"));
+        assert_eq!(Some("W/\"abc\""), response.headers.get("ETag"));
+        let body = response.body.as_bytes().unwrap();
+        assert!(body.len() < html.len() / 10);
+        assert_eq!(html.as_bytes(), &gzip_decode(body, 1 << 20).unwrap()[..]);
+
+        // 클라이언트가 압축을 받지 않아도 Vary는 붙는다
+        let response = compressed(None, Response::html(200, html.clone()));
+        assert_eq!(None, response.headers.get("Content-Encoding"));
+        assert_eq!(Some("Accept-Encoding"), response.headers.get("Vary"));
+
+        let png = Response::new(200).with_header("Content-Type", "image/png").with_body(vec![0; 4096]);
+        assert_eq!(None, compressed(Some("gzip"), png).headers.get("Content-Encoding"));
+        assert_eq!(None, compressed(Some("gzip"), Response::text(200, "short")).headers.get("Content-Encoding"));
+        let partial = Response::html(206, html.clone());
+        assert_eq!(None, compressed(Some("gzip"), partial).headers.get("Content-Encoding"));
+    }
+
+    #[test]
+    fn compresses_reader_bodies() {
+        let json = "{\"level\":\"info\",\"message\":\"ok\"}\n".repeat(100);
+        let len = json.len() as u64;
+        let response = Response::new(200)
+            .with_header("Content-Type", "application/json")
+            .with_reader(io::Cursor::new(json.clone().into_bytes()), len);
+        let response = compressed(Some("deflate"), response);
+        assert_eq!(Some("deflate"), response.headers.get("Content-Encoding"));
+        assert_eq!(json.as_bytes(), &zlib_decode(response.body.as_bytes().unwrap(), 1 << 20).unwrap()[..]);
+    }
+}
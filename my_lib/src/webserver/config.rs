@@ -0,0 +1,1423 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::Duration;
+
+use super::access_log::{AccessLog, LogFormat};
+use super::proxy::ReverseProxy;
+use super::rate_limit::MIN_RATE;
+use super::response::Response;
+use super::router::Router;
+use super::server::Server;
+use super::static_files::StaticFiles;
+use super::thread_pool::PoolConfig;
+
+// --config를 주지 않았을 때 현재 디렉터리에서 찾아 읽는 설정 파일
+pub const DEFAULT_CONFIG_FILE: &str = "webserver.toml";
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const USAGE: &str = "\
+usage: my_app serve [options]
+
+  -c, --config FILE           read settings from FILE (default: ./webserver.toml if it exists)
+      --listen ADDR           listen on ADDR; repeat to open several listeners
+      --workers N             worker threads kept running
+      --max-workers N         worker threads under load
+      --root DIR              document root
+      --access-log TARGET     stdout, off or a file path
+      --log-format FORMAT     common, combined or json
+      --metrics PATH          serve Prometheus metrics at PATH on the status host (\"\" to disable)
+      --header-timeout D      time to receive request headers (e.g. 10s, 500ms)
+      --body-timeout D        time to receive a request body
+      --write-timeout D       time a single write may block
+      --keep-alive-timeout D  idle time before closing a keep-alive connection
+      --drain-timeout D       time to finish in-flight requests on shutdown
+      --set TABLE.KEY=VALUE   override any setting, e.g. --set server.queue_capacity=100
+      --check                 validate the configuration and exit
+  -h, --help                  show this help";
+
+// 명령행 옵션과 설정 파일 키의 대응
+const OPTIONS: [(&str, &str, &str); 11] = [
+    ("--workers", "server", "workers"),
+    ("--max-workers", "server", "max_workers"),
+    ("--metrics", "server", "metrics"),
+    ("--root", "site", "root"),
+    ("--access-log", "log", "access"),
+    ("--log-format", "log", "format"),
+    ("--header-timeout", "timeouts", "header"),
+    ("--body-timeout", "timeouts", "body"),
+    ("--write-timeout", "timeouts", "write"),
+    ("--keep-alive-timeout", "timeouts", "keep_alive"),
+    ("--drain-timeout", "timeouts", "drain"),
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),                     // 설정 파일이나 로그 파일을 열지 못함
+    Syntax { line: usize, message: String },    // 설정 파일 문법 오류. 처음 만난 것 하나만
+    Invalid(Vec<String>),                       // 값이 잘못됨. 찾은 문제를 모두 모은다
+    Usage(String),                              // 명령행 인자가 잘못됨
+    Bind(String, io::Error),                    // 주소를 열지 못함
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Syntax { line, message } => write!(f, "config line {}: {}", line, message),
+            ConfigError::Invalid(errors) => {
+                f.write_str("invalid configuration:")?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            },
+            ConfigError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            ConfigError::Bind(addr, e) => write!(f, "cannot listen on {}: {}", addr, e),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) | ConfigError::Bind(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/*
+설정 파일은 TOML의 작은 부분집합이다
+    # 주석
+    [server]
+    listen = ["127.0.0.1:7878", "127.0.0.1:7879"]
+    workers = 2
+
+    [[route]]               # 같은 이름의 표를 여러 번 쓸 수 있다
+    path = "/static"
+    static = "public"
+값은 문자열("..." 또는 '...'), 정수, 실수, true/false, 배열이다. 배열은 여러 줄에 걸쳐 쓸 수 있다
+점으로 이어진 키, inline table, 날짜는 지원하지 않는다
+*/
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+// 값이 어디서 왔는지. 에러 메세지에 쓴다
+#[derive(Debug, Clone, PartialEq)]
+enum Origin {
+    Line(usize),
+    CommandLine(String),    // 옵션 이름
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Line(line) => write!(f, "line {}", line),
+            Origin::CommandLine(option) => f.write_str(option),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Value,
+    origin: Origin,
+}
+
+#[derive(Debug)]
+struct Table {
+    name: String,   // 맨 위의 표 이름 없는 부분은 ""
+    label: String,  // 에러 메세지용. [[route]]는 "route[0]", "route[1]" ...
+    array: bool,
+    origin: Origin,
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+}
+
+#[derive(Debug)]
+struct Document {
+    tables: Vec<Table>,
+}
+
+impl Document {
+    fn new() -> Document {
+        Document {
+            tables: vec![Table { name: String::new(), label: String::new(), array: false, origin: Origin::Line(0), entries: Vec::new() }],
+        }
+    }
+
+    fn parse(text: &str) -> Result<Document, ConfigError> {
+        let mut document = Document::new();
+        let mut lines = text.lines().enumerate();
+        while let Some((i, raw)) = lines.next() {
+            let line_no = i + 1;
+            let syntax = |message: String| ConfigError::Syntax { line: line_no, message };
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && !line.contains('=') {
+                let (name, array) = match line.strip_prefix("[[") {
+                    Some(rest) => (rest.strip_suffix("]]").ok_or_else(|| syntax(String::from("expected `]]`")))?, true),
+                    None => (line[1..].strip_suffix(']').ok_or_else(|| syntax(String::from("expected `]`")))?, false),
+                };
+                let name = name.trim();
+                if !is_bare_key(name) {
+                    return Err(syntax(format!("invalid table name `{}`", name)));
+                }
+                let previous = document.tables.iter().filter(|t| t.name == name).count();
+                if previous > 0 && !(array && document.tables.iter().all(|t| t.name != name || t.array)) {
+                    return Err(syntax(format!("table [{}] is defined more than once", name)));
+                }
+                let label = if array { format!("{}[{}]", name, previous) } else { name.to_string() };
+                document.tables.push(Table { name: name.to_string(), label, array, origin: Origin::Line(line_no), entries: Vec::new() });
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| syntax(String::from("expected `key = value`")))?;
+            let key = key.trim();
+            if !is_bare_key(key) {
+                return Err(syntax(format!("invalid key `{}`", key)));
+            }
+            // 배열이 닫히지 않았으면 다음 줄들을 이어 붙인다
+            let mut text = value.trim().to_string();
+            while bracket_depth(&text) > 0 {
+                let (_, next) = lines.next().ok_or_else(|| syntax(String::from("unterminated array")))?;
+                text.push(' ');
+                text.push_str(strip_comment(next).trim());
+            }
+            let value = parse_value(&text).map_err(syntax)?;
+
+            let table = document.tables.last_mut().unwrap();
+            if table.get(key).is_some() {
+                return Err(syntax(format!("duplicate key `{}`", key)));
+            }
+            table.entries.push(Entry { key: key.to_string(), value, origin: Origin::Line(line_no) });
+        }
+        Ok(document)
+    }
+
+    // 명령행에서 준 값으로 덮어쓴다. 표가 없으면 새로 만든다
+    fn set(&mut self, table: &str, key: &str, value: Value, origin: Origin) {
+        let index = match self.tables.iter().position(|t| t.name == table && !t.array) {
+            Some(index) => index,
+            None => {
+                self.tables.push(Table { name: table.to_string(), label: table.to_string(), array: false, origin: origin.clone(), entries: Vec::new() });
+                self.tables.len() - 1
+            },
+        };
+        let entries = &mut self.tables[index].entries;
+        entries.retain(|e| e.key != key);
+        entries.push(Entry { key: key.to_string(), value, origin });
+    }
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+// 문자열 밖의 '#'부터 줄 끝까지 버린다
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {},
+        }
+    }
+    line
+}
+
+// 문자열 밖에서 아직 닫히지 않은 '['의 수
+fn bracket_depth(text: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '[' => depth += 1,
+            None if c == ']' => depth -= 1,
+            None => {},
+        }
+    }
+    depth
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let mut parser = ValueParser { rest: text };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if !parser.rest.is_empty() {
+        return Err(format!("unexpected `{}` after value", parser.rest));
+    }
+    Ok(value)
+}
+
+struct ValueParser<'a> {
+    rest: &'a str,
+}
+
+impl ValueParser<'_> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.rest.chars().next() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            Some('[') => self.array(),
+            Some(_) => self.scalar(),
+            None => Err(String::from("missing value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<Value, String> {
+        let mut s = String::new();
+        let mut chars = self.rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(Value::String(s));
+                },
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        s.push(c.ok_or_else(|| format!("invalid unicode escape `\\u{}`", hex))?);
+                    },
+                    Some(c) => return Err(format!("invalid escape `\\{}`", c)),
+                    None => break,
+                },
+                c => s.push(c),
+            }
+        }
+        Err(String::from("unterminated string"))
+    }
+
+    // '...' 안에서는 escape를 처리하지 않는다 (Windows 경로 등)
+    fn literal_string(&mut self) -> Result<Value, String> {
+        let end = self.rest[1..].find('\'').ok_or_else(|| String::from("unterminated string"))?;
+        let s = self.rest[1..end + 1].to_string();
+        self.rest = &self.rest[end + 2..];
+        Ok(Value::String(s))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.rest = &self.rest[1..];
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if let Some(rest) = self.rest.strip_prefix(']') {
+                self.rest = rest;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if let Some(rest) = self.rest.strip_prefix(',') {
+                self.rest = rest;
+            } else if !self.rest.starts_with(']') {
+                return Err(String::from("expected `,` or `]` in array"));
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, String> {
+        let end = self.rest.find(|c: char| c == ',' || c == ']' || c.is_whitespace()).unwrap_or(self.rest.len());
+        let token = &self.rest[..end];
+        self.rest = &self.rest[end..];
+
+        let number = token.replace('_', "");
+        if token == "true" || token == "false" {
+            Ok(Value::Boolean(token == "true"))
+        } else if let Ok(n) = number.parse::<i64>() {
+            Ok(Value::Integer(n))
+        } else if let Ok(n) = number.parse::<f64>().map_err(|_| ()).and_then(|n| if n.is_finite() { Ok(n) } else { Err(()) }) {
+            Ok(Value::Float(n))
+        } else {
+            Err(format!("invalid value `{}` (strings must be quoted)", token))
+        }
+    }
+}
+
+// "500ms", "10s", "5m", "1h" 또는 초 단위 정수
+fn parse_duration(value: &Value) -> Option<Duration> {
+    let text = match value {
+        Value::Integer(n) => return u64::try_from(*n).ok().map(Duration::from_secs),
+        Value::String(s) => s.trim(),
+        _ => return None,
+    };
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "s" | "" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// "512KB", "10MB", "1GB"(1024 단위) 또는 바이트 단위 정수
+fn parse_size(value: &Value) -> Option<u64> {
+    let text = match value {
+        Value::Integer(n) => return u64::try_from(*n).ok(),
+        Value::String(s) => s.trim(),
+        _ => return None,
+    };
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+// 값을 읽으면서 찾은 문제를 모은다. 잘못된 값은 None으로 돌려주고 기본값을 그대로 둔다
+#[derive(Default)]
+struct Checker {
+    errors: Vec<String>,
+}
+
+impl Checker {
+    fn error(&mut self, origin: &Origin, message: String) {
+        self.errors.push(format!("{}: {}", origin, message));
+    }
+
+    fn invalid(&mut self, table: &Table, entry: &Entry, expected: &str) {
+        let found = entry.value.type_name();
+        self.error(&entry.origin, format!("{}.{}: expected {}, found {}", table.label, entry.key, expected, found));
+    }
+
+    // 모르는 키는 오타일 가능성이 높으므로 조용히 무시하지 않는다
+    fn check_keys(&mut self, table: &Table, allowed: &[&str]) {
+        for entry in &table.entries {
+            if !allowed.contains(&entry.key.as_str()) {
+                let place = if table.name.is_empty() { String::from("top level") } else { format!("[{}]", table.name) };
+                self.error(&entry.origin, format!("unknown key `{}` in {}", entry.key, place));
+            }
+        }
+    }
+
+    fn string(&mut self, table: &Table, key: &str) -> Option<String> {
+        let entry = table.get(key)?;
+        match &entry.value {
+            Value::String(s) => Some(s.clone()),
+            _ => {
+                self.invalid(table, entry, "a string");
+                None
+            },
+        }
+    }
+
+    fn strings(&mut self, table: &Table, key: &str) -> Option<Vec<String>> {
+        let entry = table.get(key)?;
+        match &entry.value {
+            Value::String(s) => Some(vec![s.clone()]),
+            Value::Array(items) if items.iter().all(|v| matches!(v, Value::String(_))) => Some(
+                items.iter().filter_map(|v| if let Value::String(s) = v { Some(s.clone()) } else { None }).collect(),
+            ),
+            _ => {
+                self.invalid(table, entry, "a string or an array of strings");
+                None
+            },
+        }
+    }
+
+    fn usize(&mut self, table: &Table, key: &str) -> Option<usize> {
+        let entry = table.get(key)?;
+        match entry.value {
+            Value::Integer(n) if n >= 0 => usize::try_from(n).ok(),
+            _ => {
+                self.invalid(table, entry, "a non-negative integer");
+                None
+            },
+        }
+    }
+
+    fn float(&mut self, table: &Table, key: &str) -> Option<f64> {
+        let entry = table.get(key)?;
+        match entry.value {
+            Value::Integer(n) if n >= 0 => Some(n as f64),
+            Value::Float(n) if n >= 0.0 => Some(n),
+            _ => {
+                self.invalid(table, entry, "a non-negative number");
+                None
+            },
+        }
+    }
+
+    fn duration(&mut self, table: &Table, key: &str) -> Option<Duration> {
+        let entry = table.get(key)?;
+        let duration = parse_duration(&entry.value);
+        if duration.is_none() {
+            self.invalid(table, entry, "a duration like \"500ms\", \"10s\" or \"5m\"");
+        }
+        duration
+    }
+
+    fn size(&mut self, table: &Table, key: &str) -> Option<u64> {
+        let entry = table.get(key)?;
+        let size = parse_size(&entry.value);
+        if size.is_none() {
+            self.invalid(table, entry, "a size like 1048576 or \"10MB\"");
+        }
+        size
+    }
+
+    // 값은 맞는 타입이지만 뜻이 잘못됐을 때
+    fn reject(&mut self, table: &Table, key: &str, message: &str) {
+        let origin = table.get(key).map_or(&table.origin, |e| &e.origin);
+        self.errors.push(format!("{}: {}.{}: {}", origin, table.label, key, message));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogTarget {
+    Off,
+    Stdout,
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteAction {
+    // root 아래의 파일을 내려준다
+    Static { root: PathBuf, index: String, cache_control: Option<String> },
+    // upstream들로 넘긴다. path는 떼고 보낸다
+    Proxy { upstreams: Vec<String>, health_check: Option<String>, timeout: Duration },
+    Redirect { to: String, status: u16 },
+}
+
+// path로 시작하는 모든 요청을 action으로 처리한다
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteConfig {
+    pub path: String,
+    pub action: RouteAction,
+}
+
+/*
+웹서버 설정. Default는 webserver::sample()이 원래 쓰던 값과 같다
+설정 파일의 표와 키
+  [server]    listen, workers, max_workers, worker_idle, queue_capacity, max_requests,
+              max_connections_per_ip, connection_rate, connection_burst, max_upgraded, metrics
+  [timeouts]  header, body, write, keep_alive, drain
+  [log]       access, format, max_size, max_files
+  [site]      root, index, not_found, rate_limit, rate_burst, rate_limit_header, status_host
+  [[route]]   path와 static/proxy/redirect 중 하나 (+ index, cache_control, health_check, timeout, status)
+0이나 빈 문자열을 주면 끄는 설정들은 Option으로 둔다
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub listen: Vec<String>,
+    pub workers: usize,
+    pub max_workers: usize,
+    pub worker_idle: Duration,
+    pub queue_capacity: Option<usize>,
+    pub max_requests: usize,
+    pub max_connections_per_ip: Option<usize>,
+    pub connection_rate: Option<(f64, u32)>,
+    pub max_upgraded: usize,
+    pub metrics_path: Option<String>,
+
+    pub header_timeout: Duration,
+    pub body_timeout: Duration,
+    pub write_timeout: Duration,
+    pub keep_alive_timeout: Duration,
+    pub drain_timeout: Duration,
+
+    pub access_log: LogTarget,
+    pub log_format: LogFormat,
+    pub log_max_size: u64,
+    pub log_max_files: usize,
+
+    pub root: PathBuf,
+    pub index: String,
+    pub not_found_page: Option<String>,     // root 기준 경로
+    pub rate_limit: Option<(f64, u32)>,
+    pub rate_limit_header: Option<String>,  // None이면 IP로 센다. 헤더 값은 클라이언트가 마음대로 바꿀 수 있으니 인증된 key일 때만 쓴다
+    pub status_host: Option<String>,
+
+    pub routes: Vec<RouteConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            listen: vec![String::from("127.0.0.1:7878"), String::from("127.0.0.1:7879")],
+            workers: 2,
+            max_workers: 8,
+            worker_idle: Duration::from_secs(30),
+            queue_capacity: None,
+            max_requests: 100,
+            max_connections_per_ip: Some(4),
+            connection_rate: Some((20.0, 40)),
+            max_upgraded: 1024,
+            metrics_path: Some(String::from("/metrics")),
+
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
+            drain_timeout: Duration::from_secs(10),
+
+            access_log: LogTarget::Stdout,
+            log_format: LogFormat::Combined,
+            log_max_size: 10 << 20,
+            log_max_files: 5,
+
+            root: PathBuf::from("."),
+            index: String::from("hello.html"),
+            not_found_page: Some(String::from("404.html")),
+            rate_limit: Some((10.0, 30)),
+            rate_limit_header: None,
+            status_host: Some(String::from("status.localhost")),
+
+            routes: vec![RouteConfig {
+                path: String::from("/static"),
+                // 문서 루트 전체가 아니라 공개할 파일만 모아 둔 디렉터리를 내보낸다
+                action: RouteAction::Static {
+                    root: PathBuf::from("./public"),
+                    index: String::from("index.html"),
+                    cache_control: Some(String::from("public, max-age=3600")),
+                },
+            }],
+        }
+    }
+}
+
+// 명령행 인자를 해석한 결과
+#[derive(Debug)]
+pub enum Command {
+    Run(ServerConfig),
+    Check(ServerConfig),    // --check: 설정만 검사하고 끝낸다
+    Help,
+}
+
+impl ServerConfig {
+    pub fn parse(text: &str) -> Result<ServerConfig, ConfigError> {
+        ServerConfig::from_document(&Document::parse(text)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ServerConfig, ConfigError> {
+        ServerConfig::from_document(&read_document(path.as_ref())?)
+    }
+
+    /*
+    "my_app serve" 뒤의 인자를 해석한다. 설정 파일을 읽은 뒤 명령행 옵션으로 덮어쓰고 검사한다
+    "--workers 4"와 "--workers=4"를 모두 받는다
+    */
+    pub fn from_args(args: &[String]) -> Result<Command, ConfigError> {
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+        let mut listen = Vec::new();
+        let mut check = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+                _ => (arg.as_str(), None),
+            };
+            match name {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--check" => check = true,
+                "-c" | "--config" => config_path = Some(PathBuf::from(option_value(name, inline, &mut args)?)),
+                "--listen" => listen.push(Value::String(option_value(name, inline, &mut args)?)),
+                "--set" => {
+                    let setting = option_value(name, inline, &mut args)?;
+                    let (path, raw) = setting
+                        .split_once('=')
+                        .ok_or_else(|| ConfigError::Usage(format!("--set expects TABLE.KEY=VALUE, got `{}`", setting)))?;
+                    let (table, key) = path
+                        .trim()
+                        .split_once('.')
+                        .ok_or_else(|| ConfigError::Usage(format!("--set expects TABLE.KEY=VALUE, got `{}`", setting)))?;
+                    if table == "route" {
+                        return Err(ConfigError::Usage(String::from("[[route]] entries can only be set in the config file")));
+                    }
+                    overrides.push((table.to_string(), key.to_string(), command_line_value(raw.trim()), format!("--set {}", path)));
+                },
+                _ => match OPTIONS.iter().find(|(option, _, _)| *option == name) {
+                    Some((option, table, key)) => {
+                        let raw = option_value(name, inline, &mut args)?;
+                        overrides.push((table.to_string(), key.to_string(), command_line_value(&raw), option.to_string()));
+                    },
+                    None => return Err(ConfigError::Usage(format!("unknown option `{}`", arg))),
+                },
+            }
+        }
+
+        let mut document = match config_path {
+            Some(path) => read_document(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => read_document(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Document::new(),
+        };
+        // 명령행의 --listen은 설정 파일의 listen 목록을 통째로 바꾼다
+        if !listen.is_empty() {
+            document.set("server", "listen", Value::Array(listen), Origin::CommandLine(String::from("--listen")));
+        }
+        for (table, key, value, option) in overrides {
+            document.set(&table, &key, value, Origin::CommandLine(option));
+        }
+
+        let config = ServerConfig::from_document(&document)?;
+        Ok(if check { Command::Check(config) } else { Command::Run(config) })
+    }
+
+    fn from_document(document: &Document) -> Result<ServerConfig, ConfigError> {
+        let mut config = ServerConfig::default();
+        let mut checker = Checker::default();
+        // 설정 파일에 route가 하나라도 있으면 기본 route 대신 그것들만 쓴다
+        if document.tables.iter().any(|t| t.name == "route") {
+            config.routes.clear();
+        }
+
+        for table in &document.tables {
+            match (table.name.as_str(), table.array) {
+                ("", false) => checker.check_keys(table, &[]),
+                ("server", false) => config.read_server(table, &mut checker),
+                ("timeouts", false) => config.read_timeouts(table, &mut checker),
+                ("log", false) => config.read_log(table, &mut checker),
+                ("site", false) => config.read_site(table, &mut checker),
+                ("route", true) => {
+                    if let Some(route) = read_route(table, &mut checker) {
+                        config.routes.push(route);
+                    }
+                },
+                (name, array) => {
+                    let header = if array { format!("[[{}]]", name) } else { format!("[{}]", name) };
+                    checker.error(&table.origin, format!("unknown table {}", header));
+                },
+            }
+        }
+
+        // metrics는 status_host 사이트의 route로 응답하므로 status_host를 끄면 함께 꺼야 한다
+        if config.metrics_path.is_some() && config.status_host.is_none() {
+            if let Some(site) = document.tables.iter().find(|t| t.name == "site") {
+                checker.reject(site, "status_host", "must be set while server.metrics is on");
+            }
+        }
+
+        if checker.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid(checker.errors))
+        }
+    }
+
+    fn read_server(&mut self, table: &Table, checker: &mut Checker) {
+        checker.check_keys(table, &[
+            "listen", "workers", "max_workers", "worker_idle", "queue_capacity", "max_requests",
+            "max_connections_per_ip", "connection_rate", "connection_burst", "max_upgraded", "metrics",
+        ]);
+        if let Some(listen) = checker.strings(table, "listen") {
+            if listen.is_empty() {
+                checker.reject(table, "listen", "at least one address is required");
+            }
+            for addr in &listen {
+                let port = addr.rsplit_once(':').and_then(|(_, port)| port.parse::<u16>().ok());
+                if port.is_none() {
+                    checker.reject(table, "listen", &format!("`{}` is not host:port", addr));
+                }
+            }
+            self.listen = listen;
+        }
+
+        let workers = checker.usize(table, "workers");
+        if let Some(workers) = workers {
+            if workers == 0 {
+                checker.reject(table, "workers", "must be at least 1");
+            }
+            // max_workers를 따로 주지 않으면 worker 수를 고정한다
+            self.workers = workers;
+            self.max_workers = workers;
+        }
+        if let Some(max_workers) = checker.usize(table, "max_workers") {
+            if max_workers < self.workers {
+                checker.reject(table, "max_workers", &format!("must not be less than workers ({})", self.workers));
+            }
+            self.max_workers = max_workers;
+        }
+        if let Some(idle) = checker.duration(table, "worker_idle") {
+            self.worker_idle = idle;
+        }
+        if let Some(capacity) = checker.usize(table, "queue_capacity") {
+            self.queue_capacity = Some(capacity).filter(|&c| c > 0);
+        }
+        if let Some(max_requests) = checker.usize(table, "max_requests") {
+            if max_requests == 0 {
+                checker.reject(table, "max_requests", "must be at least 1");
+            }
+            self.max_requests = max_requests;
+        }
+        if let Some(max) = checker.usize(table, "max_connections_per_ip") {
+            self.max_connections_per_ip = Some(max).filter(|&m| m > 0);
+        }
+        self.connection_rate = read_rate(table, checker, "connection_rate", "connection_burst", self.connection_rate);
+        if let Some(max) = checker.usize(table, "max_upgraded") {
+            if max == 0 {
+                checker.reject(table, "max_upgraded", "must be at least 1");
+            }
+            self.max_upgraded = max;
+        }
+        if let Some(path) = checker.string(table, "metrics") {
+            if !path.is_empty() && !path.starts_with('/') {
+                checker.reject(table, "metrics", "must start with `/`");
+            }
+            self.metrics_path = Some(path).filter(|p| !p.is_empty());
+        }
+    }
+
+    fn read_timeouts(&mut self, table: &Table, checker: &mut Checker) {
+        checker.check_keys(table, &["header", "body", "write", "keep_alive", "drain"]);
+        let fields = [
+            ("header", &mut self.header_timeout),
+            ("body", &mut self.body_timeout),
+            ("write", &mut self.write_timeout),
+            ("keep_alive", &mut self.keep_alive_timeout),
+            ("drain", &mut self.drain_timeout),
+        ];
+        for (key, field) in fields {
+            if let Some(duration) = checker.duration(table, key) {
+                // 0이면 read/write timeout을 걸 수 없다 (set_read_timeout은 0을 받지 않는다)
+                if duration.is_zero() && key != "drain" {
+                    checker.reject(table, key, "must be greater than 0");
+                }
+                *field = duration;
+            }
+        }
+    }
+
+    fn read_log(&mut self, table: &Table, checker: &mut Checker) {
+        checker.check_keys(table, &["access", "format", "max_size", "max_files"]);
+        if let Some(access) = checker.string(table, "access") {
+            self.access_log = match access.as_str() {
+                "off" | "" => LogTarget::Off,
+                "stdout" => LogTarget::Stdout,
+                path => LogTarget::File(PathBuf::from(path)),
+            };
+        }
+        if let Some(format) = checker.string(table, "format") {
+            match format.to_ascii_lowercase().as_str() {
+                "common" => self.log_format = LogFormat::Common,
+                "combined" => self.log_format = LogFormat::Combined,
+                "json" => self.log_format = LogFormat::Json,
+                _ => checker.reject(table, "format", &format!("unknown format `{}` (common, combined or json)", format)),
+            }
+        }
+        if let Some(size) = checker.size(table, "max_size") {
+            self.log_max_size = size;
+        }
+        if let Some(files) = checker.usize(table, "max_files") {
+            self.log_max_files = files;
+        }
+    }
+
+    fn read_site(&mut self, table: &Table, checker: &mut Checker) {
+        checker.check_keys(table, &["root", "index", "not_found", "rate_limit", "rate_burst", "rate_limit_header", "status_host"]);
+        if let Some(root) = checker.string(table, "root") {
+            let root = PathBuf::from(root);
+            if !root.is_dir() {
+                checker.reject(table, "root", &format!("`{}` is not a directory", root.display()));
+            }
+            // 기본 /static route도 새 문서 루트 아래의 public을 쓴다
+            for route in &mut self.routes {
+                if let RouteAction::Static { root: static_root, .. } = &mut route.action {
+                    if *static_root == self.root.join("public") {
+                        *static_root = root.join("public");
+                    }
+                }
+            }
+            self.root = root;
+        }
+        if let Some(index) = checker.string(table, "index") {
+            self.index = index;
+        }
+        if let Some(page) = checker.string(table, "not_found") {
+            self.not_found_page = Some(page).filter(|p| !p.is_empty());
+        }
+        self.rate_limit = read_rate(table, checker, "rate_limit", "rate_burst", self.rate_limit);
+        if let Some(header) = checker.string(table, "rate_limit_header") {
+            self.rate_limit_header = Some(header).filter(|h| !h.is_empty());
+        }
+        if let Some(host) = checker.string(table, "status_host") {
+            self.status_host = Some(host).filter(|h| !h.is_empty());
+        }
+    }
+
+    // 설정대로 listener, pool, 시간 제한, 로그 등을 갖춘 Server를 만든다. router는 기본 사이트다
+    pub fn bind(&self, router: Router) -> Result<Server, ConfigError> {
+        let mut pool = PoolConfig::elastic(self.workers, self.max_workers, self.worker_idle);
+        pool.queue_capacity = self.queue_capacity;
+
+        let first = &self.listen[0];
+        let mut server = Server::bind_with_pool(first.as_str(), pool, router).map_err(|e| ConfigError::Bind(first.clone(), e))?;
+        for addr in &self.listen[1..] {
+            server.listen(addr.as_str()).map_err(|e| ConfigError::Bind(addr.clone(), e))?;
+        }
+        server.set_drain_timeout(self.drain_timeout);
+        server.set_keep_alive(self.keep_alive_timeout, self.max_requests);
+        server.set_request_timeouts(self.header_timeout, self.body_timeout);
+        server.set_write_timeout(self.write_timeout);
+        if let Some(max) = self.max_connections_per_ip {
+            server.set_max_connections_per_ip(max);
+        }
+        if let Some((per_second, burst)) = self.connection_rate {
+            server.set_connection_rate_per_ip(per_second, burst);
+        }
+        server.set_max_upgraded_connections(self.max_upgraded);
+        match &self.access_log {
+            LogTarget::Off => {},
+            LogTarget::Stdout => server.set_access_log(AccessLog::stdout(self.log_format)),
+            LogTarget::File(path) => {
+                let log = AccessLog::file(self.log_format, path, self.log_max_size, self.log_max_files)
+                    .map_err(|e| ConfigError::Io(path.clone(), e))?;
+                server.set_access_log(log);
+            },
+        }
+        Ok(server)
+    }
+
+    // [[route]]로 설정한 route들을 router에 등록한다. 먼저 등록한 route가 우선하므로 직접 만든 route 뒤에 부른다
+    pub fn mount_routes(&self, router: &mut Router) {
+        for route in &self.routes {
+            let prefix = route.path.trim_end_matches('/');
+            let pattern = format!("{}/*path", prefix);
+            match &route.action {
+                RouteAction::Static { root, index, cache_control } => {
+                    let mut files = StaticFiles::new(root).with_index(index);
+                    if let Some(value) = cache_control {
+                        files = files.with_cache_control(value);
+                    }
+                    router.get(&pattern, move |request, params| files.serve(request, &params["path"]));
+                },
+                RouteAction::Proxy { upstreams, health_check, timeout } => {
+                    let upstreams: Vec<&str> = upstreams.iter().map(|s| s.as_str()).collect();
+                    let mut proxy = ReverseProxy::new(&upstreams).with_strip_prefix(prefix).with_timeout(*timeout);
+                    if let Some(path) = health_check {
+                        proxy = proxy.with_health_check(path, HEALTH_CHECK_INTERVAL);
+                    }
+                    // 요청 본문을 모으지 않고 upstream으로 흘려 보낸다
+                    router.any(&pattern, move |request, _| proxy.forward(request)).stream_body();
+                },
+                RouteAction::Redirect { to, status } => {
+                    let (to, status) = (to.clone(), *status);
+                    router.any(&pattern, move |_, _| Response::redirect(status, &to));
+                },
+            }
+        }
+    }
+}
+
+fn read_document(path: &Path) -> Result<Document, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    Document::parse(&text)
+}
+
+fn option_value(name: &str, inline: Option<&str>, args: &mut slice::Iter<String>) -> Result<String, ConfigError> {
+    match inline {
+        Some(value) => Ok(value.to_string()),
+        None => args.next().cloned().ok_or_else(|| ConfigError::Usage(format!("{} needs a value", name))),
+    }
+}
+
+// 명령행에서는 문자열을 따옴표 없이 쓸 수 있게, 값으로 읽히지 않으면 문자열로 본다
+fn command_line_value(raw: &str) -> Value {
+    parse_value(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+// 초당 개수와 burst. 초당 개수를 0으로 주면 끈다
+fn read_rate(table: &Table, checker: &mut Checker, rate_key: &str, burst_key: &str, current: Option<(f64, u32)>) -> Option<(f64, u32)> {
+    let rate = checker.float(table, rate_key);
+    let burst = checker.usize(table, burst_key);
+    let burst = match burst.map(u32::try_from) {
+        Some(Ok(0)) | Some(Err(_)) => {
+            checker.reject(table, burst_key, "must be between 1 and 4294967295");
+            None
+        },
+        Some(Ok(burst)) => Some(burst),
+        None => None,
+    };
+    if rate.is_some_and(|rate| rate > 0.0 && rate < MIN_RATE) {
+        checker.reject(table, rate_key, &format!("must be 0 (off) or at least {}", MIN_RATE));
+        return current;
+    }
+    match (rate, current) {
+        (Some(0.0), _) => None,
+        // burst를 따로 주지 않으면 1초 분량만큼 몰아서 받는다
+        (Some(rate), current) => Some((rate, burst.or(current.map(|(_, b)| b)).unwrap_or(rate.ceil() as u32).max(1))),
+        (None, Some((rate, current_burst))) => Some((rate, burst.unwrap_or(current_burst))),
+        (None, None) => {
+            if burst.is_some() {
+                checker.reject(table, burst_key, &format!("has no effect without {}", rate_key));
+            }
+            None
+        },
+    }
+}
+
+fn read_route(table: &Table, checker: &mut Checker) -> Option<RouteConfig> {
+    checker.check_keys(table, &["path", "static", "index", "cache_control", "proxy", "health_check", "timeout", "redirect", "status"]);
+    let path = checker.string(table, "path");
+    match &path {
+        None if table.get("path").is_none() => checker.reject(table, "path", "is required"),
+        Some(path) if !path.starts_with('/') => checker.reject(table, "path", "must start with `/`"),
+        _ => {},
+    }
+
+    let kinds: Vec<&str> = ["static", "proxy", "redirect"].into_iter().filter(|k| table.get(k).is_some()).collect();
+    if kinds.len() != 1 {
+        let message = format!("needs exactly one of static, proxy or redirect (found {})", kinds.len());
+        checker.error(&table.origin, format!("{}: {}", table.label, message));
+        return None;
+    }
+    let action = match kinds[0] {
+        "static" => {
+            let root = PathBuf::from(checker.string(table, "static")?);
+            if !root.is_dir() {
+                checker.reject(table, "static", &format!("`{}` is not a directory", root.display()));
+            }
+            RouteAction::Static {
+                root,
+                index: checker.string(table, "index").unwrap_or_else(|| String::from("index.html")),
+                cache_control: checker.string(table, "cache_control"),
+            }
+        },
+        "proxy" => {
+            let upstreams = checker.strings(table, "proxy")?;
+            if upstreams.is_empty() {
+                checker.reject(table, "proxy", "at least one upstream is required");
+            }
+            RouteAction::Proxy {
+                upstreams,
+                health_check: checker.string(table, "health_check"),
+                timeout: match checker.duration(table, "timeout") {
+                    Some(timeout) if timeout.is_zero() => {
+                        checker.reject(table, "timeout", "must be greater than 0");
+                        timeout
+                    },
+                    timeout => timeout.unwrap_or(Duration::from_secs(30)),
+                },
+            }
+        },
+        _ => {
+            let to = checker.string(table, "redirect")?;
+            let status = checker.usize(table, "status").unwrap_or(302);
+            if ![301, 302, 303, 307, 308].contains(&status) {
+                checker.reject(table, "status", "must be one of 301, 302, 303, 307 or 308");
+            }
+            RouteAction::Redirect { to, status: status as u16 }
+        },
+    };
+    Some(RouteConfig { path: path?, action })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Method, Request};
+    use crate::webserver::test_util::TempDir;
+    use std::env;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn invalid(text: &str) -> Vec<String> {
+        match ServerConfig::parse(text) {
+            Err(ConfigError::Invalid(errors)) => errors,
+            other => panic!("expected validation errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_config_file() {
+        let config = ServerConfig::parse(
+            r#"
+            # 두 포트에서 듣는다
+            [server]
+            listen = [
+                "0.0.0.0:8080",     # 외부
+                '127.0.0.1:9090',
+            ]
+            workers = 4
+            max_workers = 16
+            queue_capacity = 1_000
+            connection_rate = 0         # 끈다
+            max_upgraded = 64
+            metrics = "/stats"
+
+            [timeouts]
+            header = "2500ms"
+            keep_alive = 15
+            drain = "1m"
+
+            [log]
+            access = "logs/access.log"
+            format = "json"
+            max_size = "1MB"
+
+            [site]
+            rate_limit = 2.5
+            rate_limit_header = "X-Api-Key"
+
+            [[route]]
+            path = "/api"
+            proxy = ["127.0.0.1:9001", "127.0.0.1:9002"]
+            health_check = "/health"
+
+            [[route]]
+            path = "/old"
+            redirect = "/new # not a comment"
+            status = 301
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(vec!["0.0.0.0:8080", "127.0.0.1:9090"], config.listen);
+        assert_eq!((4, 16, Some(1000)), (config.workers, config.max_workers, config.queue_capacity));
+        assert_eq!((None, 64), (config.connection_rate, config.max_upgraded));
+        assert_eq!(Some("/stats"), config.metrics_path.as_deref());
+        assert_eq!(Duration::from_millis(2500), config.header_timeout);
+        assert_eq!(Duration::from_secs(15), config.keep_alive_timeout);
+        assert_eq!(Duration::from_secs(60), config.drain_timeout);
+        assert_eq!(Duration::from_secs(30), config.body_timeout);
+        assert_eq!(LogTarget::File(PathBuf::from("logs/access.log")), config.access_log);
+        assert_eq!((LogFormat::Json, 1 << 20), (config.log_format, config.log_max_size));
+        // burst를 주지 않으면 기존 값을 쓴다
+        assert_eq!((Some((2.5, 30)), Some("X-Api-Key")), (config.rate_limit, config.rate_limit_header.as_deref()));
+        assert_eq!(2, config.routes.len());
+        assert_eq!(
+            RouteAction::Proxy {
+                upstreams: vec![String::from("127.0.0.1:9001"), String::from("127.0.0.1:9002")],
+                health_check: Some(String::from("/health")),
+                timeout: Duration::from_secs(30),
+            },
+            config.routes[0].action
+        );
+        assert_eq!(RouteAction::Redirect { to: String::from("/new # not a comment"), status: 301 }, config.routes[1].action);
+
+        assert_eq!(ServerConfig::default(), ServerConfig::parse("# 빈 설정\n").unwrap());
+        // 저장소의 예제 설정 파일은 기본값을 그대로 적어 둔 것이다
+        let example = ServerConfig::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(DEFAULT_CONFIG_FILE)).unwrap();
+        assert_eq!(ServerConfig::default(), example);
+    }
+
+    #[test]
+    fn default_static_route_serves_only_public_directory() {
+        let static_root = |config: &ServerConfig| match &config.routes[..] {
+            [RouteConfig { path, action: RouteAction::Static { root, .. } }] if path == "/static" => root.clone(),
+            other => panic!("unexpected routes {:?}", other),
+        };
+        assert_eq!(PathBuf::from("./public"), static_root(&ServerConfig::default()));
+        // 문서 루트를 옮기면 그 아래의 public을 쓴다
+        let root = env::temp_dir();
+        let config = ServerConfig::parse(&format!("[site]\nroot = '{}'\n", root.display())).unwrap();
+        assert_eq!(root.join("public"), static_root(&config));
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_line_numbers() {
+        let line_of = |text: &str| match ServerConfig::parse(text) {
+            Err(ConfigError::Syntax { line, message }) => (line, message),
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!((2, String::from("unterminated string")), line_of("[server]\nmetrics = \"/m\n"));
+        assert_eq!((1, String::from("invalid value `localhost` (strings must be quoted)")), line_of("x = localhost"));
+        assert_eq!((3, String::from("duplicate key `workers`")), line_of("[server]\nworkers = 1\nworkers = 2"));
+        assert_eq!((3, String::from("table [server] is defined more than once")), line_of("[server]\n\n[server]"));
+        assert_eq!((2, String::from("unterminated array")), line_of("[server]\nlisten = [\"a:1\","));
+        assert_eq!((1, String::from("expected `key = value`")), line_of("workers"));
+    }
+
+    #[test]
+    fn parses_values() {
+        let string = |s: &str| Ok(Value::String(s.to_string()));
+        assert_eq!(string("a\tb\u{e9}\\\"q"), parse_value(r#""a\tb\u00e9\\\"q""#));
+        assert_eq!(string(r"C:\logs\"), parse_value(r"'C:\logs\'"));
+        assert_eq!(Err(String::from("invalid escape `\\x`")), parse_value(r#""\x""#));
+        assert_eq!(Err(String::from("invalid unicode escape `\\ud800`")), parse_value(r#""\ud800""#));
+        assert_eq!(Err(String::from("unterminated string")), parse_value("'open"));
+
+        assert_eq!(Ok(Value::Integer(1000)), parse_value("1_000"));
+        assert_eq!(Ok(Value::Integer(-5)), parse_value("-5"));
+        assert_eq!(Ok(Value::Float(1000.0)), parse_value("1e3"));
+        assert_eq!(Ok(Value::Boolean(false)), parse_value("false"));
+        assert_eq!(Err(String::from("invalid value `inf` (strings must be quoted)")), parse_value("inf"));
+
+        let nested = Value::Array(vec![Value::Array(vec![Value::Integer(1), Value::Integer(2)]), Value::Array(Vec::new())]);
+        assert_eq!(Ok(nested), parse_value("[ [1, 2,], [] ]"));
+        assert_eq!(Err(String::from("expected `,` or `]` in array")), parse_value("[1 2]"));
+        assert_eq!(Err(String::from("unexpected `2` after value")), parse_value("1 2"));
+        assert_eq!(Err(String::from("missing value")), parse_value(""));
+    }
+
+    #[test]
+    fn parses_durations_and_sizes() {
+        let duration = |s: &str| parse_duration(&Value::String(s.to_string()));
+        assert_eq!(Some(Duration::from_millis(1500)), duration("1.5s"));
+        assert_eq!(Some(Duration::from_millis(250)), duration("250 ms"));
+        assert_eq!(Some(Duration::from_secs(120)), duration("2m"));
+        assert_eq!(Some(Duration::from_secs(3600)), duration("1h"));
+        assert_eq!(None, duration("-1s"));
+        assert_eq!(None, duration("10d"));
+        assert_eq!(Some(Duration::from_secs(7)), parse_duration(&Value::Integer(7)));
+        assert_eq!(None, parse_duration(&Value::Integer(-1)));
+        assert_eq!(None, parse_duration(&Value::Float(1.5)));
+
+        let size = |s: &str| parse_size(&Value::String(s.to_string()));
+        assert_eq!(Some(512 << 10), size("512kb"));
+        assert_eq!(Some(3 << 30), size("3 GB"));
+        assert_eq!(Some(42), size("42B"));
+        assert_eq!(None, size("1.5MB"));
+        assert_eq!(None, size("10TB"));
+        assert_eq!(None, size("99999999999GB"));
+        assert_eq!(None, parse_size(&Value::Integer(-1)));
+    }
+
+    #[test]
+    fn parses_multi_line_arrays_and_comments() {
+        let config = ServerConfig::parse(
+            "[server]\n\
+             listen = [   # 주소들 [\n\
+             \x20   \"127.0.0.1:1\",  # 첫 번째 ]\n\
+             \n\
+             \x20   '127.0.0.1:2', \"#not-a-comment]:3\",\n\
+             ]\n\
+             workers = 3 # 배열 다음 줄도 그대로 읽는다\n",
+        )
+        .unwrap();
+        assert_eq!(vec!["127.0.0.1:1", "127.0.0.1:2", "#not-a-comment]:3"], config.listen);
+        assert_eq!(3, config.workers);
+
+        // 여러 줄 값의 에러는 키가 있는 줄을 가리킨다
+        assert_eq!(
+            vec!["line 2: server.listen: `bad` is not host:port"],
+            invalid("[server]\nlisten = [\n  \"a:1\",\n  \"bad\"]\nworkers = 3\n")
+        );
+        assert_eq!("x = \"a#b\" ", strip_comment("x = \"a#b\" # c"));
+        assert_eq!("x = 'it\\'", strip_comment("x = 'it\\'#c"));
+        assert_eq!(2, bracket_depth("[\"]\", ["));
+
+        // 배열 뒤에서 난 문법 에러도 실제 줄 번호로 알린다
+        let error = ServerConfig::parse("[server]\nlisten = [\n\"a:1\"\n]\nlisten = []\n").unwrap_err();
+        assert!(matches!(error, ConfigError::Syntax { line: 5, .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn rejects_bad_table_headers_and_keys() {
+        let message_of = |text: &str| match ServerConfig::parse(text) {
+            Err(ConfigError::Syntax { message, .. }) => message,
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!("expected `]]`", message_of("[[route]"));
+        assert_eq!("expected `]`", message_of("[server"));
+        assert_eq!("invalid table name `bad name`", message_of("[bad name]"));
+        assert_eq!("table [server] is defined more than once", message_of("[server]\n[[server]]"));
+        assert_eq!("invalid key `site.root`", message_of("site.root = \"/\""));
+        assert_eq!(
+            vec!["line 1: unknown key `workers` in top level", "line 2: unknown table [[listener]]"],
+            invalid("workers = 1\n[[listener]]\n")
+        );
+    }
+
+    #[test]
+    fn reports_command_line_errors() {
+        let usage = |list: &[&str]| match ServerConfig::from_args(&args(list)) {
+            Err(ConfigError::Usage(message)) => message,
+            other => panic!("expected usage error, got {:?}", other),
+        };
+        assert_eq!("--config needs a value", usage(&["--config"]));
+        assert_eq!("--set expects TABLE.KEY=VALUE, got `workers`", usage(&["--set", "workers"]));
+        assert_eq!("--set expects TABLE.KEY=VALUE, got `workers=2`", usage(&["--set=workers=2"]));
+        assert_eq!("[[route]] entries can only be set in the config file", usage(&["--set", "route.path=/x"]));
+        assert_eq!("unknown option `--threads=2`", usage(&["--threads=2"]));
+
+        let dir = TempDir::new("config_cli_errors");
+        let path = dir.join("empty.toml");
+        fs::write(&path, "# 비어 있음\n").unwrap();
+        let config_path = path.to_str().unwrap();
+        let errors = |list: &[&str]| {
+            let list: Vec<&str> = ["--config", config_path].iter().chain(list).copied().collect();
+            match ServerConfig::from_args(&args(&list)) {
+                Err(ConfigError::Invalid(errors)) => errors,
+                other => panic!("expected validation errors, got {:?}", other),
+            }
+        };
+        assert_eq!(vec!["--workers: server.workers: expected a non-negative integer, found string"], errors(&["--workers=many"]));
+        assert_eq!(vec!["--set server.bogus: unknown key `bogus` in [server]"], errors(&["--set", "server.bogus=1"]));
+        assert_eq!(vec!["--set nope.key: unknown table [nope]"], errors(&["--set", "nope.key=1"]));
+        assert_eq!(vec!["--root: site.root: `/nonexistent/dir` is not a directory"], errors(&["--root", "/nonexistent/dir"]));
+        // 따옴표 없는 명령행 값은 문자열로, 숫자로 읽히는 값은 숫자로 본다
+        match ServerConfig::from_args(&args(&["--config", config_path, "--set", "log.format=json", "--workers", "3"])) {
+            Ok(Command::Run(config)) => assert_eq!((LogFormat::Json, 3), (config.log_format, config.workers)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn collects_validation_errors() {
+        let errors = invalid(
+            "[server]\nworkers = 4\nmax_workers = 2\nlisten = \"localhost\"\nthreads = 3\n\
+             [timeouts]\nheader = \"soon\"\nwrite = 0\n\
+             [log]\nformat = \"xml\"\n\
+             [[route]]\npath = \"/x\"\nstatic = \"/nonexistent/dir\"\nproxy = [\"a:1\"]\n\
+             [[route]]\npath = \"y\"\nredirect = \"/z\"\nstatus = 200\n\
+             [extra]\n",
+        );
+        assert_eq!(
+            vec![
+                "line 5: unknown key `threads` in [server]",
+                "line 4: server.listen: `localhost` is not host:port",
+                "line 3: server.max_workers: must not be less than workers (4)",
+                "line 7: timeouts.header: expected a duration like \"500ms\", \"10s\" or \"5m\", found string",
+                "line 8: timeouts.write: must be greater than 0",
+                "line 10: log.format: unknown format `xml` (common, combined or json)",
+                "line 11: route[0]: needs exactly one of static, proxy or redirect (found 2)",
+                "line 16: route[1].path: must start with `/`",
+                "line 18: route[1].status: must be one of 301, 302, 303, 307 or 308",
+                "line 19: unknown table [extra]",
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn rejects_unusable_rates_and_timeouts() {
+        let errors = invalid(
+            "[server]\nconnection_rate = 1e-20\n\
+             [site]\nrate_limit = 0.0001\nrate_burst = 0\n\
+             [[route]]\npath = \"/api\"\nproxy = \"127.0.0.1:9000\"\ntimeout = \"0s\"\n",
+        );
+        assert_eq!(
+            vec![
+                "line 2: server.connection_rate: must be 0 (off) or at least 0.001",
+                "line 5: site.rate_burst: must be between 1 and 4294967295",
+                "line 4: site.rate_limit: must be 0 (off) or at least 0.001",
+                "line 9: route[0].timeout: must be greater than 0",
+            ],
+            errors
+        );
+        let config = ServerConfig::parse("[server]\nconnection_rate = 0.001\n[site]\nrate_limit = 0\n").unwrap();
+        assert_eq!((Some((0.001, 40)), None), (config.connection_rate, config.rate_limit));
+    }
+
+    #[test]
+    fn metrics_require_status_host() {
+        assert_eq!(
+            vec!["line 4: site.status_host: must be set while server.metrics is on"],
+            invalid("[server]\nmetrics = \"/m\"\n[site]\nstatus_host = \"\"\n")
+        );
+        let config = ServerConfig::parse("[server]\nmetrics = \"\"\n[site]\nstatus_host = \"\"\n").unwrap();
+        assert_eq!((None, None), (config.metrics_path, config.status_host));
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let dir = TempDir::new("config_args");
+        let path = dir.join("webserver.toml");
+        fs::write(&path, "[server]\nlisten = \"127.0.0.1:1\"\nworkers = 2\nmax_workers = 4\n[log]\naccess = \"off\"\n").unwrap();
+        let config_path = path.to_str().unwrap();
+
+        let command = ServerConfig::from_args(&args(&[
+            "--config", config_path, "--listen", "127.0.0.1:2", "--listen=127.0.0.1:3", "--max-workers", "6",
+            "--access-log", "stdout", "--set", "timeouts.body=5s", "--metrics=", "--check",
+        ]));
+        let config = match command {
+            Ok(Command::Check(config)) => config,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(vec!["127.0.0.1:2", "127.0.0.1:3"], config.listen);
+        assert_eq!((2, 6), (config.workers, config.max_workers));
+        assert_eq!(LogTarget::Stdout, config.access_log);
+        assert_eq!(Duration::from_secs(5), config.body_timeout);
+        assert_eq!(None, config.metrics_path);
+
+        // 명령행 값도 같은 규칙으로 검사하고, 어느 옵션이 잘못됐는지 알려준다
+        match ServerConfig::from_args(&args(&["--config", config_path, "--max-workers", "1"])) {
+            Err(ConfigError::Invalid(errors)) => {
+                assert_eq!(vec!["--max-workers: server.max_workers: must not be less than workers (2)"], errors)
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(matches!(ServerConfig::from_args(&args(&["--help"])), Ok(Command::Help)));
+        assert!(matches!(ServerConfig::from_args(&args(&["--verbose"])), Err(ConfigError::Usage(_))));
+        assert!(matches!(ServerConfig::from_args(&args(&["--workers"])), Err(ConfigError::Usage(_))));
+        assert!(matches!(ServerConfig::from_args(&args(&["--config", "/nonexistent.toml"])), Err(ConfigError::Io(..))));
+    }
+
+    #[test]
+    fn mounts_configured_routes() {
+        let root = TempDir::new("config_routes");
+        fs::write(root.join("a.txt"), "file a").unwrap();
+        let config = ServerConfig::parse(&format!(
+            "[[route]]\npath = \"/files/\"\nstatic = '{}'\ncache_control = \"no-cache\"\n\
+             [[route]]\npath = \"/old\"\nredirect = \"/files/a.txt\"\nstatus = 308\n",
+            root.path().display()
+        ))
+        .unwrap();
+        let mut router = Router::new();
+        config.mount_routes(&mut router);
+
+        let response = router.handle(&Request::new(Method::Get, "/files/a.txt"));
+        assert_eq!((200, Some("no-cache")), (response.status, response.headers.get("Cache-Control")));
+        assert_eq!(6, response.body.len());
+        let response = router.handle(&Request::new(Method::Post, "/old/anything"));
+        assert_eq!((308, Some("/files/a.txt")), (response.status, response.headers.get("Location")));
+        assert_eq!(404, router.handle(&Request::new(Method::Get, "/other")).status);
+    }
+}
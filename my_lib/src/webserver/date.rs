@@ -0,0 +1,120 @@
+// HTTP에서 사용하는 날짜 형식 (IMF-fixdate, 예: "Sun, 06 Nov 1994 08:49:37 GMT")
+// 외부 crate 없이 UNIX 시간(초)과 그레고리력 날짜를 직접 변환한다
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"]; // 1970-01-01은 목요일
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// 날짜와 시간을 구성하는 값들 (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,     // 1 ~ 12
+    pub day: u32,       // 1 ~ 31
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub weekday: usize, // DAYS의 index
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+        let days = secs.div_euclid(86400);
+        let rem = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            weekday: days.rem_euclid(7) as usize,
+        }
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        MONTHS[self.month as usize - 1]
+    }
+}
+
+pub fn format_http_date(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[t.weekday], t.day, t.month_name(), t.year, t.hour, t.minute, t.second
+    )
+}
+
+// IMF-fixdate만 해석한다. 형식이 다르면 None (조건부 요청에서는 무시하면 된다)
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let mut parts = s.split_whitespace();
+    let _weekday = parts.next()?.strip_suffix(',')?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|v| v.parse::<u32>());
+    let (hour, minute, second) = match (time.next(), time.next(), time.next()) {
+        (Some(Ok(h)), Some(Ok(m)), Some(Ok(s))) if h < 24 && m < 60 && s < 61 => (h, m, s),
+        _ => return None,
+    };
+    // IMF-fixdate의 연도는 4자리다. 큰 연도를 받으면 초로 바꾸다가 넘칠 수 있다
+    if parts.next()? != "GMT" || day == 0 || day > 31 || !(1970..=9999).contains(&year) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+// 1970-01-01부터 지난 일수를 (년, 월, 일)로 바꾼다 (Howard Hinnant의 civil_from_days 알고리즘)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_and_parses_imf_fixdate() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", format_http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!("Thu, 29 Feb 2024 00:00:00 GMT", format_http_date(UNIX_EPOCH + Duration::from_secs(1709164800)));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun Nov  6 08:49:37 1994"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 9999999999999999 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 10000 08:49:37 GMT"));
+        assert!(parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+    }
+}
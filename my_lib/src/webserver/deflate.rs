@@ -0,0 +1,757 @@
+// DEFLATE 압축/해제 (RFC 1951)
+// LZ77로 반복되는 부분을 (길이, 거리) 쌍으로 바꾸고, 그 결과를 Huffman 부호로 기록한다
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::io;
+
+const WINDOW_SIZE: usize = 32768;   // 거리는 최대 32K까지 뒤를 가리킬 수 있다
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 128;       // 같은 hash를 가진 후보를 몇 개까지 비교할지 (속도와 압축률의 균형)
+const LAZY_LIMIT: usize = 32;       // 이보다 짧은 match를 찾으면 다음 위치에서 더 긴 match가 있는지 확인한다
+const BLOCK_TOKENS: usize = 16384;  // 블록 하나에 담을 token 수. 블록마다 Huffman 부호를 새로 만든다
+const MAX_STORED: usize = 65535;    // 압축하지 않은 블록 하나의 최대 길이
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// code length 부호의 길이를 기록하는 순서 (RFC 1951 3.2.7)
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match(u16, u16),    // (길이, 거리)
+}
+
+// 길이/거리 값이 속하는 구간의 index (LENGTH_BASE/DIST_BASE에서 값보다 작거나 같은 마지막 항목)
+fn bucket(base: &[u16], value: u16) -> usize {
+    base.iter().rposition(|b| *b <= value).unwrap()
+}
+
+// 낮은 비트부터 채워 나가는 bit 단위 writer
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { out: Vec::new(), bits: 0, count: 0 }
+    }
+
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+            self.bits = 0;
+            self.count = 0;
+        }
+    }
+}
+
+// 부호 길이만으로 정해지는 canonical Huffman 부호. DEFLATE는 부호를 높은 비트부터 기록하므로 뒤집어 둔다
+struct Encoder {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl Encoder {
+    fn new(lengths: Vec<u8>) -> Encoder {
+        let mut count = [0u16; 16];
+        for len in &lengths {
+            count[*len as usize] += 1;
+        }
+        count[0] = 0;
+        let mut next = [0u16; 16];
+        let mut code = 0;
+        for bits in 1..16 {
+            code = (code + count[bits - 1]) << 1;
+            next[bits] = code;
+        }
+
+        let codes = lengths
+            .iter()
+            .map(|len| {
+                if *len == 0 {
+                    return 0;
+                }
+                let code = next[*len as usize];
+                next[*len as usize] += 1;
+                code.reverse_bits() >> (16 - *len)
+            })
+            .collect();
+        Encoder { lengths, codes }
+    }
+
+    fn write(&self, writer: &mut BitWriter, symbol: usize) {
+        writer.write(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+
+    fn cost(&self, freqs: &[u32]) -> u64 {
+        freqs.iter().zip(&self.lengths).map(|(f, l)| *f as u64 * *l as u64).sum()
+    }
+}
+
+/*
+빈도로 Huffman 부호 길이를 구한다. 부호 길이가 max_bits를 넘으면 긴 부호를 줄이고
+Kraft 부등식이 다시 성립할 때까지 짧은 부호를 늘린다 (miniz의 방식)
+*/
+fn huffman_lengths(freqs: &[u32], max_bits: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+    let mut symbols: Vec<usize> = (0..freqs.len()).filter(|s| freqs[*s] > 0).collect();
+    match symbols.len() {
+        0 => return lengths,
+        1 => {
+            lengths[symbols[0]] = 1;
+            return lengths;
+        },
+        _ => {},
+    }
+
+    // (빈도, node) 중 가장 작은 둘을 계속 합쳐 나무를 만든다. node가 leaf 수보다 작으면 leaf
+    let leaves = symbols.len();
+    let mut parent = vec![0usize; leaves * 2 - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> =
+        symbols.iter().enumerate().map(|(node, s)| Reverse((freqs[*s] as u64, node))).collect();
+    let mut next = leaves;
+    while heap.len() > 1 {
+        let Reverse((a, left)) = heap.pop().unwrap();
+        let Reverse((b, right)) = heap.pop().unwrap();
+        parent[left] = next;
+        parent[right] = next;
+        heap.push(Reverse((a + b, next)));
+        next += 1;
+    }
+
+    // 각 leaf의 깊이를 세어 길이별 개수를 구한다 (root는 마지막 node)
+    let root = next - 1;
+    let mut depth = vec![0usize; next];
+    for node in (0..root).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+    let mut count = vec![0usize; max_bits + 1];
+    for leaf in 0..leaves {
+        count[depth[leaf].min(max_bits)] += 1;
+    }
+    let mut total: usize = (1..=max_bits).map(|bits| count[bits] << (max_bits - bits)).sum();
+    while total != 1 << max_bits {
+        count[max_bits] -= 1;
+        for bits in (1..max_bits).rev() {
+            if count[bits] != 0 {
+                count[bits] -= 1;
+                count[bits + 1] += 2;
+                break;
+            }
+        }
+        total -= 1;
+    }
+
+    // 자주 나오는 symbol일수록 짧은 부호를 준다
+    symbols.sort_by_key(|s| Reverse(freqs[*s]));
+    let mut symbols = symbols.into_iter();
+    for (bits, n) in count.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(*n) {
+            lengths[symbol] = bits as u8;
+        }
+    }
+    lengths
+}
+
+fn fixed_encoders() -> (Encoder, Encoder) {
+    let mut lit = vec![8u8; 288];
+    lit[144..256].fill(9);
+    lit[256..280].fill(7);
+    (Encoder::new(lit), Encoder::new(vec![5; 30]))
+}
+
+// 블록 하나의 literal/length와 거리 symbol 빈도
+fn symbol_freqs(tokens: &[Token]) -> (Vec<u32>, Vec<u32>) {
+    let mut lit = vec![0u32; 286];
+    let mut dist = vec![0u32; 30];
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => lit[b as usize] += 1,
+            Token::Match(len, d) => {
+                lit[257 + bucket(&LENGTH_BASE, len)] += 1;
+                dist[bucket(&DIST_BASE, d)] += 1;
+            },
+        }
+    }
+    lit[256] = 1;
+    (lit, dist)
+}
+
+fn extra_bits(tokens: &[Token]) -> u64 {
+    tokens
+        .iter()
+        .map(|t| match *t {
+            Token::Literal(_) => 0,
+            Token::Match(len, d) => {
+                (LENGTH_EXTRA[bucket(&LENGTH_BASE, len)] + DIST_EXTRA[bucket(&DIST_BASE, d)]) as u64
+            },
+        })
+        .sum()
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], lit: &Encoder, dist: &Encoder) {
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => lit.write(writer, b as usize),
+            Token::Match(len, d) => {
+                let l = bucket(&LENGTH_BASE, len);
+                lit.write(writer, 257 + l);
+                writer.write((len - LENGTH_BASE[l]) as u32, LENGTH_EXTRA[l] as u32);
+                let c = bucket(&DIST_BASE, d);
+                dist.write(writer, c);
+                writer.write((d - DIST_BASE[c]) as u32, DIST_EXTRA[c] as u32);
+            },
+        }
+    }
+    lit.write(writer, 256);
+}
+
+/*
+dynamic Huffman 블록의 헤더
+literal/length와 거리 부호 길이 목록을 이어 붙인 뒤, 같은 값의 반복을 16(앞의 값 반복), 17/18(0 반복)로 줄이고
+그 결과를 다시 Huffman(code length 부호)으로 기록한다
+*/
+struct DynamicHeader {
+    lit: Encoder,
+    dist: Encoder,
+    codes: Encoder,
+    runs: Vec<(u8, u8)>,    // (code length symbol, extra bits 값)
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+}
+
+impl DynamicHeader {
+    fn new(lit_freqs: &[u32], dist_freqs: &[u32]) -> DynamicHeader {
+        let lit_lengths = huffman_lengths(lit_freqs, 15);
+        let mut dist_lengths = huffman_lengths(dist_freqs, 15);
+        // 거리 부호가 하나도 없어도 최소한 하나는 기록해야 한다
+        if dist_lengths.iter().all(|l| *l == 0) {
+            dist_lengths[0] = 1;
+        }
+        let hlit = 257.max(lit_lengths.iter().rposition(|l| *l != 0).unwrap() + 1);
+        let hdist = 1.max(dist_lengths.iter().rposition(|l| *l != 0).unwrap() + 1);
+
+        let all: Vec<u8> = lit_lengths[..hlit].iter().chain(&dist_lengths[..hdist]).copied().collect();
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < all.len() {
+            let value = all[i];
+            let run = all[i..].iter().take_while(|v| **v == value).count();
+            if value == 0 && run >= 11 {
+                let n = run.min(138);
+                runs.push((18, (n - 11) as u8));
+                i += n;
+            } else if value == 0 && run >= 3 {
+                runs.push((17, (run - 3) as u8));
+                i += run;
+            } else if value != 0 && run >= 4 {
+                // 첫 번째 값은 그대로 쓰고, 나머지를 16으로 반복한다
+                runs.push((value, 0));
+                let n = (run - 1).min(6);
+                runs.push((16, (n - 3) as u8));
+                i += 1 + n;
+            } else {
+                runs.push((value, 0));
+                i += 1;
+            }
+        }
+
+        let mut code_freqs = vec![0u32; 19];
+        for (symbol, _) in &runs {
+            code_freqs[*symbol as usize] += 1;
+        }
+        let code_lengths = huffman_lengths(&code_freqs, 7);
+        let hclen = 4.max(CODE_LENGTH_ORDER.iter().rposition(|s| code_lengths[*s] != 0).unwrap() + 1);
+
+        DynamicHeader {
+            lit: Encoder::new(lit_lengths),
+            dist: Encoder::new(dist_lengths),
+            codes: Encoder::new(code_lengths),
+            runs,
+            hlit,
+            hdist,
+            hclen,
+        }
+    }
+
+    fn cost(&self) -> u64 {
+        let runs: u64 = self
+            .runs
+            .iter()
+            .map(|(s, _)| self.codes.lengths[*s as usize] as u64 + [2, 3, 7].get((*s as usize).wrapping_sub(16)).unwrap_or(&0))
+            .sum();
+        5 + 5 + 4 + 3 * self.hclen as u64 + runs
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write((self.hlit - 257) as u32, 5);
+        writer.write((self.hdist - 1) as u32, 5);
+        writer.write((self.hclen - 4) as u32, 4);
+        for symbol in &CODE_LENGTH_ORDER[..self.hclen] {
+            writer.write(self.codes.lengths[*symbol] as u32, 3);
+        }
+        for (symbol, extra) in &self.runs {
+            self.codes.write(writer, *symbol as usize);
+            match symbol {
+                16 => writer.write(*extra as u32, 2),
+                17 => writer.write(*extra as u32, 3),
+                18 => writer.write(*extra as u32, 7),
+                _ => {},
+            }
+        }
+    }
+}
+
+// 블록 하나를 stored, fixed, dynamic 중 가장 짧게 나오는 방식으로 기록한다
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let (lit_freqs, dist_freqs) = symbol_freqs(tokens);
+    let extra = extra_bits(tokens);
+
+    let (fixed_lit, fixed_dist) = fixed_encoders();
+    let fixed_cost = fixed_lit.cost(&lit_freqs) + fixed_dist.cost(&dist_freqs) + extra;
+    let dynamic = DynamicHeader::new(&lit_freqs, &dist_freqs);
+    let dynamic_cost = dynamic.cost() + dynamic.lit.cost(&lit_freqs) + dynamic.dist.cost(&dist_freqs) + extra;
+    let stored_blocks = raw.len().div_ceil(MAX_STORED).max(1) as u64;
+    let stored_cost = 8 * raw.len() as u64 + stored_blocks * (3 + 7 + 32);
+
+    if stored_cost < fixed_cost.min(dynamic_cost) {
+        let mut chunks = raw.chunks(MAX_STORED).peekable();
+        if raw.is_empty() {
+            writer.write(last as u32, 3);
+            writer.align();
+            writer.write(0xffff0000, 32);
+        }
+        while let Some(chunk) = chunks.next() {
+            writer.write((last && chunks.peek().is_none()) as u32, 3);
+            writer.align();
+            writer.write(chunk.len() as u32, 16);
+            writer.write(!chunk.len() as u32 & 0xffff, 16);
+            writer.out.extend_from_slice(chunk);
+        }
+    } else if fixed_cost <= dynamic_cost {
+        writer.write(last as u32 | 1 << 1, 3);
+        write_tokens(writer, tokens, &fixed_lit, &fixed_dist);
+    } else {
+        writer.write(last as u32 | 2 << 1, 3);
+        dynamic.write(writer);
+        write_tokens(writer, tokens, &dynamic.lit, &dynamic.dist);
+    }
+}
+
+// 최근 32K 안에서 같은 3바이트로 시작하는 위치들을 hash chain으로 기억한다
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Matcher<'a> {
+        Matcher { data, head: vec![usize::MAX; 1 << HASH_BITS], prev: vec![usize::MAX; WINDOW_SIZE] }
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        let d = self.data;
+        let key = (d[i] as u32) << 16 | (d[i + 1] as u32) << 8 | d[i + 2] as u32;
+        (key.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.data.len() {
+            let h = self.hash(i);
+            self.prev[i % WINDOW_SIZE] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+
+    // i 위치에서 시작하는 가장 긴 match (길이, 거리)
+    fn find(&self, i: usize) -> Option<(usize, usize)> {
+        if i + MIN_MATCH > self.data.len() {
+            return None;
+        }
+        let max_len = MAX_MATCH.min(self.data.len() - i);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(i)];
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || candidate >= i || i - candidate > WINDOW_SIZE {
+                break;
+            }
+            let len = self.data[candidate..]
+                .iter()
+                .zip(&self.data[i..i + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH && best.is_none_or(|(best_len, _)| len > best_len) {
+                best = Some((len, i - candidate));
+                if len == max_len {
+                    break;
+                }
+            }
+            let next = self.prev[candidate % WINDOW_SIZE];
+            // 덮어써진 오래된 항목을 따라가지 않도록 위치는 항상 줄어들어야 한다
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+}
+
+// raw DEFLATE 형식으로 압축한다 (gzip/zlib 헤더 없음)
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    let mut matcher = Matcher::new(data);
+    let mut tokens = Vec::with_capacity(BLOCK_TOKENS);
+    let mut block_start = 0;
+    let mut i = 0;
+
+    while i < data.len() {
+        match matcher.find(i) {
+            Some((len, dist)) => {
+                matcher.insert(i);
+                // 한 칸 뒤에서 더 긴 match가 나오면 지금 바이트는 literal로 보낸다 (lazy matching)
+                let better = len < LAZY_LIMIT && matcher.find(i + 1).is_some_and(|(next, _)| next > len);
+                if better {
+                    tokens.push(Token::Literal(data[i]));
+                    i += 1;
+                } else {
+                    tokens.push(Token::Match(len as u16, dist as u16));
+                    for j in i + 1..i + len {
+                        matcher.insert(j);
+                    }
+                    i += len;
+                }
+            },
+            None => {
+                matcher.insert(i);
+                tokens.push(Token::Literal(data[i]));
+                i += 1;
+            },
+        }
+
+        if tokens.len() >= BLOCK_TOKENS {
+            write_block(&mut writer, &tokens, &data[block_start..i], i == data.len());
+            tokens.clear();
+            block_start = i;
+        }
+    }
+    if !tokens.is_empty() || data.is_empty() {
+        write_block(&mut writer, &tokens, &data[block_start..], true);
+    }
+
+    writer.align();
+    writer.out
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or_else(|| invalid("unexpected end of deflate stream"))?;
+            self.pos += 1;
+            self.bits |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1u64 << n) - 1) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+// 부호 길이별 개수와, 부호 순서로 정렬한 symbol로 한 비트씩 따라가며 해석한다 (zlib의 puff.c 방식)
+struct Decoder {
+    count: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Decoder {
+    fn new(lengths: &[u8]) -> io::Result<Decoder> {
+        let mut count = [0u16; 16];
+        for len in lengths {
+            count[*len as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for n in &count[1..] {
+            left = (left << 1) - *n as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + count[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, len) in lengths.iter().enumerate() {
+            if *len != 0 {
+                symbols[offsets[*len as usize] as usize] = symbol as u16;
+                offsets[*len as usize] += 1;
+            }
+        }
+        Ok(Decoder { count, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.read(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid huffman code"))
+    }
+}
+
+/*
+raw DEFLATE 데이터를 푼다. 결과가 max_len을 넘으면 에러 (작은 입력이 엄청나게 커지는 압축 폭탄 방지)
+(푼 데이터, 입력에서 사용한 바이트 수)를 돌려준다. gzip 등은 그 뒤에 trailer가 이어진다
+*/
+pub fn inflate(data: &[u8], max_len: usize) -> io::Result<(Vec<u8>, usize)> {
+    let mut reader = BitReader { data, pos: 0, bits: 0, count: 0 };
+    let mut out: Vec<u8> = Vec::new();
+
+    loop {
+        let last = reader.read(1)? == 1;
+        match reader.read(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.pos..reader.pos + 4).ok_or_else(|| invalid("truncated stored block"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if len != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    return Err(invalid("stored block length mismatch"));
+                }
+                let start = reader.pos + 4;
+                let block = data.get(start..start + len).ok_or_else(|| invalid("truncated stored block"))?;
+                if out.len() + len > max_len {
+                    return Err(invalid("inflated data too large"));
+                }
+                out.extend_from_slice(block);
+                reader.pos = start + len;
+            },
+            1 => {
+                let (lit, dist) = fixed_encoders();
+                let lit = Decoder::new(&lit.lengths)?;
+                let dist = Decoder::new(&dist.lengths)?;
+                inflate_block(&mut reader, &mut out, &lit, &dist, max_len)?;
+            },
+            2 => {
+                let (lit, dist) = read_dynamic_header(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &lit, &dist, max_len)?;
+            },
+            _ => return Err(invalid("invalid block type")),
+        }
+        if last {
+            return Ok((out, reader.pos));
+        }
+    }
+}
+
+fn read_dynamic_header(reader: &mut BitReader) -> io::Result<(Decoder, Decoder)> {
+    let hlit = reader.read(5)? as usize + 257;
+    let hdist = reader.read(5)? as usize + 1;
+    let hclen = reader.read(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(invalid("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for symbol in &CODE_LENGTH_ORDER[..hclen] {
+        code_lengths[*symbol] = reader.read(3)? as u8;
+    }
+    let codes = Decoder::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let (value, repeat) = match codes.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid("repeat with no previous length"))?, 3 + reader.read(2)?),
+            17 => (0, 3 + reader.read(3)?),
+            _ => (0, 11 + reader.read(7)?),
+        };
+        if lengths.len() + repeat as usize > hlit + hdist {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+
+    Ok((Decoder::new(&lengths[..hlit])?, Decoder::new(&lengths[hlit..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, lit: &Decoder, dist: &Decoder, max_len: usize) -> io::Result<()> {
+    loop {
+        let symbol = lit.decode(reader)?;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let l = symbol - 257;
+            if l >= LENGTH_BASE.len() {
+                return Err(invalid("invalid length code"));
+            }
+            let len = LENGTH_BASE[l] as usize + reader.read(LENGTH_EXTRA[l] as u32)? as usize;
+            let d = dist.decode(reader)?;
+            if d >= DIST_BASE.len() {
+                return Err(invalid("invalid distance code"));
+            }
+            let distance = DIST_BASE[d] as usize + reader.read(DIST_EXTRA[d] as u32)? as usize;
+            if distance > out.len() {
+                return Err(invalid("distance too far back"));
+            }
+            // 거리가 길이보다 짧으면 방금 복사한 바이트를 다시 복사하게 되므로 한 바이트씩 복사한다
+            let start = out.len() - distance;
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+        if out.len() > max_len {
+            return Err(invalid("inflated data too large"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = deflate(data);
+        let (inflated, used) = inflate(&compressed, usize::MAX).unwrap();
+        assert_eq!(data, &inflated[..]);
+        assert_eq!(compressed.len(), used);
+        compressed
+    }
+
+    // 의사 난수는 거의 압축되지 않는다
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    // 첫 블록 헤더의 BTYPE (0: stored, 1: fixed, 2: dynamic)
+    fn block_type(compressed: &[u8]) -> u8 {
+        compressed[0] >> 1 & 3
+    }
+
+    #[test]
+    fn round_trips_various_inputs() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(b"abcabcabcabcabcabcabc");
+
+        let noise = noise(100_000, 12345);
+        assert!(round_trip(&noise).len() < noise.len() + 100);
+    }
+
+    #[test]
+    fn round_trips_each_block_type() {
+        // 압축되지 않는 데이터는 stored 블록 여러 개로 나뉜다
+        let stored = round_trip(&noise(MAX_STORED + 10, 1));
+        assert_eq!(0, block_type(&stored));
+
+        // 짧은 입력은 부호 표를 싣지 않는 fixed 블록이 더 짧다
+        let fixed = round_trip(b"abcabcabcabcabcabcabc");
+        assert_eq!(1, block_type(&fixed));
+
+        // 글자가 치우친 긴 입력은 dynamic 블록이 된다
+        let text: String = (0..500).map(|i| format!("line {} of the log\n", i % 13)).collect();
+        let dynamic = round_trip(text.as_bytes());
+        assert_eq!(2, block_type(&dynamic));
+    }
+
+    #[test]
+    fn matches_at_maximum_distance() {
+        // 같은 1000바이트가 정확히 WINDOW_SIZE만큼 떨어져 다시 나오면 match로 보낸다
+        let repeated = noise(1000, 7);
+        let mut data = repeated.clone();
+        data.extend(noise(WINDOW_SIZE - repeated.len(), 8));
+        data.extend(&repeated);
+        assert!(round_trip(&data).len() + 900 < data.len());
+
+        // 한 바이트라도 더 멀면 window 밖이라 압축되지 않는다
+        let mut data = repeated.clone();
+        data.extend(noise(WINDOW_SIZE + 1 - repeated.len(), 8));
+        data.extend(&repeated);
+        assert!(round_trip(&data).len() > data.len());
+    }
+
+    #[test]
+    fn compresses_repetitive_text() {
+        let html: String = (0..2000).map(|i| format!("<tr><td>{}</td><td>log line {}</td></tr>\n", i, i % 7)).collect();
+        let compressed = round_trip(html.as_bytes());
+        assert!(compressed.len() * 5 < html.len(), "{} -> {}", html.len(), compressed.len());
+    }
+
+    #[test]
+    fn limits_huffman_code_lengths() {
+        // 피보나치 빈도는 제한 없이 만들면 아주 긴 부호가 생긴다
+        let mut freqs = vec![1u32, 1];
+        while freqs.len() < 30 {
+            let n = freqs.len();
+            freqs.push(freqs[n - 1] + freqs[n - 2]);
+        }
+        let lengths = huffman_lengths(&freqs, 15);
+        assert!(lengths.iter().all(|l| (1..=15).contains(l)));
+        let kraft: f64 = lengths.iter().map(|l| 0.5f64.powi(*l as i32)).sum();
+        assert!((kraft - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_corrupt_input_and_bombs() {
+        assert!(inflate(&[0xff, 0xff], 100).is_err());
+        let compressed = deflate(&[0; 10_000]);
+        assert!(inflate(&compressed, 1000).is_err());
+    }
+}
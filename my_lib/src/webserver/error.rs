@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::form::FormError;
+use super::headers::Headers;
+use super::request::ParseError;
+use super::response::{reason_phrase, Response};
+use super::static_files::mime_type;
+
+/*
+handler가 돌려주는 에러. 상태 코드와 함께 Router의 에러 페이지로 바뀌어 클라이언트에게 간다
+    router.get("/users/:id", |_, params| {
+        let id: u32 = params["id"].parse().map_err(|_| HttpError::new(400, "id must be a number"))?;
+        let user = find_user(id).ok_or_else(HttpError::not_found)?;
+        Ok(Response::text(200, user.name))
+    });
+message는 4xx면 에러 페이지에 보여주고, 5xx면 내부 사정이 드러나지 않도록 로그에만 남긴다
+*/
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    pub status: u16,
+    pub message: String,
+    pub headers: Headers,   // 에러 응답에 붙일 헤더 (405의 Allow, 429의 Retry-After 등)
+}
+
+impl HttpError {
+    pub fn new(status: u16, message: &str) -> HttpError {
+        HttpError { status, message: message.to_string(), headers: Headers::new() }
+    }
+
+    pub fn status(status: u16) -> HttpError {
+        HttpError::new(status, "")
+    }
+
+    pub fn not_found() -> HttpError {
+        HttpError::status(404)
+    }
+
+    pub fn bad_request(message: &str) -> HttpError {
+        HttpError::new(400, message)
+    }
+
+    pub fn internal(message: &str) -> HttpError {
+        HttpError::new(500, message)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpError {
+        self.headers.insert(name, value);
+        self
+    }
+
+    // 클라이언트에게 보여줘도 되는 설명
+    pub fn public_message(&self) -> &str {
+        if self.status >= 500 {
+            ""
+        } else {
+            &self.message
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.status, reason_phrase(self.status))?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for HttpError {}
+
+// 파일을 열다 난 에러를 ?로 바로 돌려줄 수 있게 한다
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> HttpError {
+        let status = match e.kind() {
+            io::ErrorKind::NotFound => 404,
+            io::ErrorKind::PermissionDenied => 403,
+            _ => 500,
+        };
+        HttpError::new(status, &e.to_string())
+    }
+}
+
+impl From<FormError> for HttpError {
+    fn from(e: FormError) -> HttpError {
+        HttpError::new(e.status(), &e.to_string())
+    }
+}
+
+impl From<ParseError> for HttpError {
+    fn from(e: ParseError) -> HttpError {
+        HttpError::new(e.status(), &e.to_string())
+    }
+}
+
+// handler가 Response와 Result<Response, HttpError> 중 어느 쪽을 돌려줘도 route로 등록할 수 있게 한다
+pub trait IntoResponse {
+    fn into_response(self) -> Result<Response, HttpError>;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Result<Response, HttpError> {
+        Ok(self)
+    }
+}
+
+impl IntoResponse for Result<Response, HttpError> {
+    fn into_response(self) -> Result<Response, HttpError> {
+        self
+    }
+}
+
+/*
+에러 페이지
+  - Template: HTML 문자열. {status}, {reason}, {message}를 에러 내용으로 바꾼다 (message는 HTML escape)
+  - File: 파일 내용을 그대로 보낸다. Content-Type은 확장자로 정한다
+파일을 읽지 못하면 기본 에러 페이지로 대신한다
+*/
+#[derive(Debug, Clone)]
+pub enum ErrorPage {
+    Template(String),
+    File(PathBuf),
+}
+
+impl ErrorPage {
+    pub fn template(html: &str) -> ErrorPage {
+        ErrorPage::Template(html.to_string())
+    }
+
+    pub fn file<P: AsRef<Path>>(path: P) -> ErrorPage {
+        ErrorPage::File(path.as_ref().to_path_buf())
+    }
+
+    fn render(&self, error: &HttpError) -> io::Result<Response> {
+        match self {
+            ErrorPage::Template(template) => {
+                let html = template
+                    .replace("{status}", &error.status.to_string())
+                    .replace("{reason}", reason_phrase(error.status))
+                    .replace("{message}", &escape_html(error.public_message()));
+                Ok(Response::html(error.status, html))
+            },
+            ErrorPage::File(path) => {
+                let body = fs::read(path)?;
+                Ok(Response::new(error.status).with_header("Content-Type", mime_type(path)).with_body(body))
+            },
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 상태 코드별 에러 페이지. 등록되지 않은 코드는 fallback, 그것도 없으면 짧은 텍스트 페이지를 쓴다
+#[derive(Debug, Clone, Default)]
+pub struct ErrorPages {
+    pages: HashMap<u16, ErrorPage>,
+    fallback: Option<ErrorPage>,
+}
+
+impl ErrorPages {
+    pub fn new() -> ErrorPages {
+        ErrorPages::default()
+    }
+
+    pub fn set(&mut self, status: u16, page: ErrorPage) {
+        self.pages.insert(status, page);
+    }
+
+    pub fn set_fallback(&mut self, page: ErrorPage) {
+        self.fallback = Some(page);
+    }
+
+    pub fn render(&self, error: &HttpError) -> Response {
+        let page = self.pages.get(&error.status).or(self.fallback.as_ref());
+        let rendered = page.map(|page| page.render(error));
+        let mut response = match rendered {
+            Some(Ok(response)) => response,
+            Some(Err(e)) => {
+                println!("Failed to render error page for {}: {}", error.status, e);
+                default_page(error)
+            },
+            None => default_page(error),
+        };
+        for (name, value) in error.headers.iter() {
+            response.headers.append(name, value);
+        }
+        response
+    }
+}
+
+fn default_page(error: &HttpError) -> Response {
+    let message = error.public_message();
+    if message.is_empty() {
+        Response::status_page(error.status)
+    } else {
+        Response::text(error.status, format!("{} {}\n{}\n", error.status, reason_phrase(error.status), message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn renders_registered_pages_and_falls_back() {
+        let mut pages = ErrorPages::new();
+        pages.set(404, ErrorPage::template("<h1>{status} {reason}</h1><p>{message}</p>"));
+        pages.set(503, ErrorPage::file("/nonexistent/503.html"));
+
+        let response = pages.render(&HttpError::new(404, "no <such> page"));
+        assert_eq!(404, response.status);
+        assert_eq!("<h1>404 Not Found</h1><p>no &lt;such&gt; page</p>", body(&response));
+
+        // 5xx의 message는 클라이언트에게 보이지 않는다
+        let response = pages.render(&HttpError::internal("database password is wrong"));
+        assert_eq!("500 Internal Server Error\n", body(&response));
+
+        // 파일을 읽지 못하면 기본 페이지
+        let response = pages.render(&HttpError::status(503).with_header("Retry-After", "5"));
+        assert_eq!("503 Service Unavailable\n", body(&response));
+        assert_eq!(Some("5"), response.headers.get("Retry-After"));
+
+        let response = pages.render(&HttpError::bad_request("name is required"));
+        assert_eq!("400 Bad Request\nname is required\n", body(&response));
+    }
+
+    #[test]
+    fn converts_io_errors() {
+        assert_eq!(404, HttpError::from(io::Error::from(io::ErrorKind::NotFound)).status);
+        assert_eq!(403, HttpError::from(io::Error::from(io::ErrorKind::PermissionDenied)).status);
+        assert_eq!(500, HttpError::from(io::Error::other("disk on fire")).status);
+    }
+}
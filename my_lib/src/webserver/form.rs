@@ -0,0 +1,431 @@
+// form 본문 파싱 (application/x-www-form-urlencoded, multipart/form-data)
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::request::{read_headers, ParseError};
+use super::url::QueryMap;
+
+// 파일이 아닌 일반 필드는 메모리에 모으므로 크기를 제한한다
+const MAX_FIELD_LEN: u64 = 1024 * 1024;
+const MAX_PARTS: usize = 1000;
+const READ_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum FormError {
+    UnsupportedMediaType,       // Content-Type이 기대한 form 형식이 아님
+    Malformed(&'static str),
+    TooLarge,
+    Io(io::Error),              // 임시 파일을 만들거나 쓰지 못함
+    Body(ParseError),           // 요청 본문을 연결에서 읽지 못함 (끊김, 시간 초과 등)
+}
+
+impl FormError {
+    pub fn status(&self) -> u16 {
+        match self {
+            FormError::UnsupportedMediaType => 415,
+            FormError::Malformed(_) => 400,
+            FormError::TooLarge => 413,
+            FormError::Io(_) => 500,
+            FormError::Body(e) => e.status(),
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType => f.write_str("unsupported form content type"),
+            FormError::Malformed(reason) => write!(f, "malformed form: {}", reason),
+            FormError::TooLarge => f.write_str("form field too large"),
+            FormError::Io(e) => write!(f, "i/o error: {}", e),
+            FormError::Body(e) => write!(f, "cannot read request body: {}", e),
+        }
+    }
+}
+
+impl Error for FormError {}
+
+impl From<io::Error> for FormError {
+    fn from(e: io::Error) -> FormError {
+        FormError::Io(e)
+    }
+}
+
+/*
+"multipart/form-data; boundary=xyz" 같은 헤더 값을 (소문자로 바꾼 본 값, 파라미터 목록)으로 나눈다
+파라미터 이름은 소문자로 바꾸고, 값이 따옴표로 감싸져 있으면 벗겨낸다 (\" 같은 escape도 푼다)
+*/
+pub fn parse_header_params(value: &str) -> (String, Vec<(String, String)>) {
+    let (main, mut rest) = value.split_once(';').unwrap_or((value, ""));
+    let mut params = Vec::new();
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        let (name, after) = match rest.split_once('=') {
+            Some(pair) => pair,
+            None => break,
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    // 옛 IE는 "C:\dir\a.txt"처럼 역슬래시를 escape하지 않고 보내므로 \" 와 \\ 만 escape로 본다
+                    '\\' if quoted[i + 1..].starts_with(['"', '\\']) => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    },
+                    c => value.push(c),
+                }
+            }
+            (value, &quoted[end..])
+        } else {
+            let (value, next) = after.split_once(';').unwrap_or((after, ""));
+            (value.trim().to_string(), next)
+        };
+        params.push((name, value));
+        rest = next;
+    }
+
+    (main.trim().to_ascii_lowercase(), params)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+/*
+multipart로 올라온 파일. 내용은 메모리가 아니라 임시 파일에 있다
+persist()로 옮기지 않으면 drop될 때 임시 파일을 지운다
+*/
+#[derive(Debug)]
+pub struct FilePart {
+    pub name: String,           // form 필드 이름
+    pub filename: String,       // 클라이언트가 보낸 파일 이름 (경로 부분은 뗀다). 저장 경로로 그대로 쓰면 안 된다
+    pub content_type: String,
+    pub size: u64,
+    path: PathBuf,
+    keep: bool,
+}
+
+impl FilePart {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    // 임시 파일을 to로 옮긴다. 다른 파일 시스템이라 rename할 수 없으면 복사한 뒤 지운다
+    pub fn persist<P: AsRef<Path>>(&mut self, to: P) -> io::Result<()> {
+        let to = to.as_ref();
+        if fs::rename(&self.path, to).is_err() {
+            fs::copy(&self.path, to)?;
+            let _ = fs::remove_file(&self.path);
+        }
+        self.path = to.to_path_buf();
+        self.keep = true;
+        Ok(())
+    }
+}
+
+impl Drop for FilePart {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Multipart {
+    pub fields: QueryMap,
+    pub files: Vec<FilePart>,
+}
+
+impl Multipart {
+    pub fn file(&self, name: &str) -> Option<&FilePart> {
+        self.files.iter().find(|f| f.name == name)
+    }
+}
+
+// 다른 요청의 업로드와 겹치지 않는 임시 파일을 만든다
+fn create_temp_file(dir: &Path) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("upload-{}-{}-{:08x}", process::id(), n, nanos));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/*
+multipart 본문을 앞에서부터 조금씩 읽는 reader
+part 내용은 "\r\n--boundary"가 나올 때까지 이어지는데, 구분자가 read() 경계에 걸칠 수 있으므로
+구분자 길이 - 1 바이트는 항상 버퍼에 남겨 두고 나머지만 내보낸다
+part 헤더는 BufRead로 읽어 request의 헤더 파서를 그대로 쓴다
+*/
+struct MultipartReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    start: usize,
+    delimiter: Vec<u8>,
+}
+
+impl<R: Read> MultipartReader<R> {
+    fn new(inner: R, boundary: &str) -> MultipartReader<R> {
+        MultipartReader {
+            inner,
+            // 첫 구분자 앞에는 CRLF가 없으므로 미리 넣어 두어 모든 구분자를 같은 방식으로 찾는다
+            buffer: b"\r\n".to_vec(),
+            start: 0,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+        }
+    }
+
+    // 버퍼에 더 읽어 들인다. EOF면 false
+    fn fill_more(&mut self) -> io::Result<bool> {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_SIZE, 0);
+        let n = loop {
+            match self.inner.read(&mut self.buffer[len..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        self.buffer.truncate(len + n.as_ref().copied().unwrap_or(0));
+        Ok(n? > 0)
+    }
+
+    // 다음 구분자 직전까지를 out에 쓰고 구분자는 건너뛴다. 쓴 바이트 수를 돌려준다
+    fn copy_part(&mut self, out: &mut dyn Write, limit: u64) -> Result<u64, FormError> {
+        let mut written = 0u64;
+        loop {
+            let available = &self.buffer[self.start..];
+            let found = available.windows(self.delimiter.len()).position(|w| w == &self.delimiter[..]);
+            let n = found.unwrap_or_else(|| available.len().saturating_sub(self.delimiter.len() - 1));
+
+            written += n as u64;
+            if written > limit {
+                return Err(FormError::TooLarge);
+            }
+            out.write_all(&available[..n])?;
+            self.start += n;
+
+            if found.is_some() {
+                self.start += self.delimiter.len();
+                return Ok(written);
+            }
+            if !self.fill_more()? {
+                return Err(FormError::Malformed("unexpected end of multipart body"));
+            }
+        }
+    }
+
+    // 구분자 뒤: "--"면 마지막 part, 아니면 (공백 뒤) CRLF가 오고 다음 part의 헤더가 이어진다
+    fn next_part(&mut self) -> Result<bool, FormError> {
+        let mut line = Vec::new();
+        self.by_ref().take(1024).read_until(b'\n', &mut line)?;
+        if line.starts_with(b"--") {
+            return Ok(false);
+        }
+        if !line.ends_with(b"\n") || !line.iter().all(|b| b" \t\r\n".contains(b)) {
+            return Err(FormError::Malformed("invalid multipart boundary line"));
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for MultipartReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for MultipartReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.start == self.buffer.len() {
+            self.fill_more()?;
+        }
+        Ok(&self.buffer[self.start..])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+    }
+}
+
+/*
+multipart/form-data 본문을 읽어 일반 필드는 fields에, 파일은 dir 아래 임시 파일에 저장한다
+파일 내용은 메모리에 모으지 않고 읽는 대로 디스크에 쓴다
+파싱 중에 에러가 나면 그때까지 만든 임시 파일은 지워진다
+*/
+pub fn parse_multipart<R: Read>(reader: R, boundary: &str, dir: &Path) -> Result<Multipart, FormError> {
+    // RFC 2046: boundary는 1~70자
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(FormError::Malformed("invalid multipart boundary"));
+    }
+    let mut reader = MultipartReader::new(reader, boundary);
+    let mut form = Multipart::default();
+
+    // 첫 구분자 앞의 preamble은 버린다
+    reader.copy_part(&mut io::sink(), u64::MAX)?;
+    while reader.next_part()? {
+        if form.fields.len() + form.files.len() >= MAX_PARTS {
+            return Err(FormError::TooLarge);
+        }
+        let headers = read_headers(&mut reader).map_err(|e| match e {
+            ParseError::Io(e) => FormError::Io(e),
+            ParseError::HeaderTooLarge => FormError::TooLarge,
+            _ => FormError::Malformed("invalid part headers"),
+        })?;
+
+        let disposition = headers.get("Content-Disposition").ok_or(FormError::Malformed("missing Content-Disposition"))?;
+        let (kind, params) = parse_header_params(disposition);
+        let name = match param(&params, "name") {
+            Some(name) if kind == "form-data" => name.to_string(),
+            _ => return Err(FormError::Malformed("invalid Content-Disposition")),
+        };
+
+        match param(&params, "filename") {
+            // 파일을 고르지 않은 input도 filename=""인 빈 part로 온다
+            Some("") => {
+                reader.copy_part(&mut io::sink(), u64::MAX)?;
+            },
+            Some(filename) => {
+                let (path, file) = create_temp_file(dir)?;
+                let mut part = FilePart {
+                    name,
+                    // 브라우저에 따라 "C:\dir\a.txt"처럼 전체 경로를 보내기도 한다
+                    filename: filename.rsplit(['/', '\\']).next().unwrap_or("").to_string(),
+                    content_type: headers.get("Content-Type").unwrap_or("application/octet-stream").to_string(),
+                    size: 0,
+                    path,
+                    keep: false,
+                };
+                let mut out = BufWriter::new(file);
+                part.size = reader.copy_part(&mut out, u64::MAX)?;
+                out.flush()?;
+                form.files.push(part);
+            },
+            None => {
+                let mut value = Vec::new();
+                reader.copy_part(&mut value, MAX_FIELD_LEN)?;
+                let value = String::from_utf8(value).map_err(|_| FormError::Malformed("field is not UTF-8"))?;
+                form.fields.append(&name, &value);
+            },
+        }
+    }
+    Ok(form)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Method, Request};
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello world\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"C:\\dir\\notes.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line 1\r\n-- XyZ lookalike\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"empty\"; filename=\"\"\r\n\
+        \r\n\
+        \r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    // 한 번에 몇 바이트씩만 내주는 reader로 구분자가 read() 경계에 걸치는 경우를 확인한다
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn parses_header_params() {
+        let (value, params) = parse_header_params("form-data; name=\"a \\\"b\\\"\"; filename=x.txt");
+        assert_eq!("form-data", value);
+        assert_eq!(Some("a \"b\""), param(&params, "name"));
+        assert_eq!(Some("x.txt"), param(&params, "filename"));
+    }
+
+    #[test]
+    fn parses_multipart_and_streams_files_to_disk() {
+        let dir = std::env::temp_dir();
+        let form = parse_multipart(Trickle(BODY.as_bytes()), "XyZ", &dir).unwrap();
+        assert_eq!(Some("hello world"), form.fields.get("title"));
+        assert_eq!(1, form.files.len());
+
+        let file = form.file("upload").unwrap();
+        assert_eq!("notes.txt", file.filename);
+        assert_eq!("text/plain", file.content_type);
+        let contents = fs::read_to_string(file.path()).unwrap();
+        assert_eq!("line 1\r\n-- XyZ lookalike", contents);
+        assert_eq!(contents.len() as u64, file.size);
+
+        // persist하지 않은 임시 파일은 drop될 때 지워진다
+        let path = file.path().to_path_buf();
+        drop(form);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn request_exposes_query_form_and_multipart() {
+        let request = Request::new(Method::Get, "/search?q=a+b&tag=x&tag=y");
+        assert_eq!(Some("a b"), request.query().get("q"));
+        assert_eq!(2, request.query().get_all("tag").count());
+
+        let mut request = Request::new(Method::Post, "/login");
+        request.headers.insert("Content-Type", "application/x-www-form-urlencoded; charset=utf-8");
+        request.body = b"user=kim&pass=p%40ss".to_vec();
+        let form = request.form().unwrap();
+        assert_eq!(Some("p@ss"), form.get("pass"));
+        assert_eq!(415, request.multipart(&std::env::temp_dir()).unwrap_err().status());
+
+        request.headers.insert("Content-Type", "multipart/form-data; boundary=\"XyZ\"");
+        request.body = BODY.as_bytes().to_vec();
+        let form = request.multipart(&std::env::temp_dir()).unwrap();
+        assert_eq!(Some("hello world"), form.fields.get("title"));
+        assert_eq!(415, request.form().unwrap_err().status());
+    }
+
+    #[test]
+    fn rejects_truncated_multipart() {
+        let truncated = &BODY[..BODY.find("--XyZ--").unwrap()];
+        let err = parse_multipart(truncated.as_bytes(), "XyZ", &std::env::temp_dir()).unwrap_err();
+        assert_eq!(400, err.status());
+    }
+}
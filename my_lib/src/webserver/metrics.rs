@@ -0,0 +1,224 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::response::Response;
+use super::thread_pool::PoolStats;
+
+// 응답 시간 히스토그램의 구간 경계(초)
+const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// 100 ~ 599 상태 코드마다 하나씩. 범위 밖의 코드는 세지 않고 false를 돌려준다
+struct StatusCounters([AtomicU64; 500]);
+
+impl StatusCounters {
+    fn new() -> StatusCounters {
+        StatusCounters([const { AtomicU64::new(0) }; 500])
+    }
+
+    fn increment(&self, status: u16) -> bool {
+        match status.checked_sub(100).and_then(|i| self.0.get(usize::from(i))) {
+            Some(counter) => {
+                counter.fetch_add(1, Ordering::Relaxed);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // 한 번이라도 센 상태 코드와 그 횟수
+    fn nonzero(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| (i as u16 + 100, counter.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+    }
+}
+
+/*
+서버가 모으는 통계. 모든 값이 atomic이라 worker들이 lock 없이 동시에 기록한다
+요청 하나를 기록하는 데 fetch_add 몇 번이면 되고, 읽는 쪽(/metrics)만 배열을 훑는다
+응답 시간 히스토그램은 구간마다 그 구간에 들어온 수만 세어 두고, 내보낼 때 Prometheus 형식대로 누적한다
+*/
+pub(super) struct Metrics {
+    connections: AtomicU64,
+    rejected: StatusCounters,   // pool에 넘기기 전에 거절한 연결 (429, 503)
+    requests: StatusCounters,
+    bytes_sent: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS.len() + 1],    // 마지막은 +Inf 구간
+    latency_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub(super) fn new() -> Metrics {
+        Metrics {
+            connections: AtomicU64::new(0),
+            rejected: StatusCounters::new(),
+            requests: StatusCounters::new(),
+            bytes_sent: AtomicU64::new(0),
+            latency: [const { AtomicU64::new(0) }; LATENCY_BUCKETS.len() + 1],
+            latency_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn record_connection(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_rejected(&self, status: u16) {
+        self.rejected.increment(status);
+    }
+
+    // requests_total의 합과 히스토그램의 _count가 맞도록 세지 못한 상태 코드의 요청은 아무 데도 기록하지 않는다
+    pub(super) fn record_request(&self, status: u16, bytes: u64, duration: Duration) {
+        if !self.requests.increment(status) {
+            return;
+        }
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.latency[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    // Prometheus text exposition format (0.0.4)
+    pub(super) fn render(&self, pool: &PoolStats) -> String {
+        let mut out = String::new();
+        let gauges = [
+            ("webserver_pool_workers", "Worker threads currently running.", pool.workers),
+            ("webserver_pool_max_workers", "Upper limit of worker threads.", pool.max_workers),
+            ("webserver_pool_busy_workers", "Workers currently running a job.", pool.busy),
+            ("webserver_pool_queued_jobs", "Jobs waiting for a free worker.", pool.queued),
+        ];
+        for (name, help, value) in gauges {
+            header(&mut out, name, help, "gauge");
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        header(&mut out, "webserver_pool_jobs_completed_total", "Jobs finished by the pool.", "counter");
+        let _ = writeln!(out, "webserver_pool_jobs_completed_total {}", pool.completed);
+
+        header(&mut out, "webserver_connections_total", "Connections accepted.", "counter");
+        let _ = writeln!(out, "webserver_connections_total {}", self.connections.load(Ordering::Relaxed));
+        header(&mut out, "webserver_connections_rejected_total", "Connections rejected before reaching the pool.", "counter");
+        for (status, count) in self.rejected.nonzero() {
+            let _ = writeln!(out, "webserver_connections_rejected_total{{status=\"{}\"}} {}", status, count);
+        }
+
+        header(&mut out, "webserver_requests_total", "Requests answered, by response status.", "counter");
+        for (status, count) in self.requests.nonzero() {
+            let _ = writeln!(out, "webserver_requests_total{{status=\"{}\"}} {}", status, count);
+        }
+        header(&mut out, "webserver_response_bytes_total", "Response body bytes sent.", "counter");
+        let _ = writeln!(out, "webserver_response_bytes_total {}", self.bytes_sent.load(Ordering::Relaxed));
+
+        let name = "webserver_request_duration_seconds";
+        header(&mut out, name, "Time from reading a request to finishing its response.", "histogram");
+        let mut cumulative = 0;
+        for (i, counter) in self.latency.iter().enumerate() {
+            cumulative += counter.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS.get(i).map_or(String::from("+Inf"), |bound| bound.to_string());
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+        let sum = self.latency_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+        out
+    }
+
+    pub(super) fn response(&self, pool: &PoolStats) -> Response {
+        Response::new(200).with_header("Content-Type", CONTENT_TYPE).with_body(self.render(pool))
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.record_connection();
+        metrics.record_rejected(429);
+        metrics.record_request(200, 100, Duration::from_millis(3));
+        metrics.record_request(200, 50, Duration::from_millis(30));
+        metrics.record_request(404, 10, Duration::from_secs(20));
+        metrics.record_request(999, 1000, Duration::from_secs(1));
+
+        let pool = PoolStats { workers: 4, max_workers: 8, busy: 1, queued: 2, completed: 9 };
+        let text = metrics.render(&pool);
+        for line in [
+            "# TYPE webserver_pool_busy_workers gauge",
+            "webserver_pool_busy_workers 1",
+            "webserver_pool_queued_jobs 2",
+            "webserver_pool_jobs_completed_total 9",
+            "webserver_connections_total 1",
+            "webserver_connections_rejected_total{status=\"429\"} 1",
+            "webserver_requests_total{status=\"200\"} 2",
+            "webserver_requests_total{status=\"404\"} 1",
+            "webserver_response_bytes_total 160",
+            "webserver_request_duration_seconds_bucket{le=\"0.001\"} 0",
+            "webserver_request_duration_seconds_bucket{le=\"0.005\"} 1",
+            "webserver_request_duration_seconds_bucket{le=\"0.05\"} 2",
+            "webserver_request_duration_seconds_bucket{le=\"10\"} 2",
+            "webserver_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "webserver_request_duration_seconds_sum 20.033",
+            "webserver_request_duration_seconds_count 3",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+        // 범위 밖의 상태 코드는 요청 수에도, 바이트와 응답 시간에도 들어가지 않는다
+        assert!(!text.contains("status=\"999\""));
+    }
+
+    #[test]
+    fn counts_only_statuses_in_range() {
+        let counters = StatusCounters::new();
+        assert!(!counters.increment(99));
+        assert!(counters.increment(100));
+        assert!(counters.increment(599));
+        assert!(counters.increment(599));
+        assert!(!counters.increment(600));
+        assert!(!counters.increment(u16::MAX));
+        assert_eq!(vec![(100, 1), (599, 2)], counters.nonzero().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn places_latency_on_bucket_bounds() {
+        let metrics = Metrics::new();
+        // 경계값은 그 구간에 들어간다 (le = 이하)
+        metrics.record_request(200, 0, Duration::from_millis(1));
+        metrics.record_request(200, 0, Duration::from_secs(10));
+        metrics.record_request(200, 0, Duration::from_micros(10_000_001));
+
+        let text = metrics.render(&PoolStats { workers: 0, max_workers: 0, busy: 0, queued: 0, completed: 0 });
+        for line in [
+            "webserver_request_duration_seconds_bucket{le=\"0.001\"} 1",
+            "webserver_request_duration_seconds_bucket{le=\"5\"} 1",
+            "webserver_request_duration_seconds_bucket{le=\"10\"} 2",
+            "webserver_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "webserver_requests_total{status=\"200\"} 3",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn renders_headers_without_samples() {
+        let response = Metrics::new().response(&PoolStats { workers: 1, max_workers: 1, busy: 0, queued: 0, completed: 0 });
+        assert_eq!((200, Some(CONTENT_TYPE)), (response.status, response.headers.get("Content-Type")));
+        let text = String::from_utf8(response.body.as_bytes().unwrap().to_vec()).unwrap();
+        // 아직 센 것이 없어도 HELP/TYPE은 나오고, 상태 코드별 줄은 나오지 않는다
+        assert!(text.contains("# TYPE webserver_requests_total counter\n# HELP webserver_response_bytes_total"));
+        assert!(text.contains("# TYPE webserver_connections_rejected_total counter\n# HELP webserver_requests_total"));
+        assert!(text.lines().any(|l| l == "webserver_request_duration_seconds_count 0"));
+        assert!(text.lines().any(|l| l == "webserver_request_duration_seconds_sum 0"));
+    }
+}
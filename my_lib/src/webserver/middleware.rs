@@ -0,0 +1,140 @@
+use super::request::{Method, Request};
+use super::response::Response;
+
+/*
+route handler 앞뒤에 끼워 넣는 처리 단계
+  - before: handler가 호출되기 전에 요청을 보거나 고친다. Some(response)를 돌려주면 handler를 건너뛰고 바로 응답한다
+  - after: handler(또는 앞선 단계)가 만든 응답을 고친다
+Router::wrap()으로 등록한 순서대로 before가 불리고, after는 그 반대 순서로 불린다 (양파 껍질처럼 감싼다)
+    wrap(A), wrap(B) => A.before -> B.before -> handler -> B.after -> A.after
+여러 worker에서 동시에 호출되므로 Send + Sync 여야 한다
+*/
+pub trait Middleware: Send + Sync {
+    fn before(&self, _request: &mut Request) -> Option<Response> {
+        None
+    }
+
+    fn after(&self, _request: &Request, _response: &mut Response) {}
+}
+
+/*
+다른 origin의 브라우저 스크립트가 API를 호출할 수 있게 CORS 헤더를 붙인다
+preflight 요청(OPTIONS + Access-Control-Request-Method)은 route까지 가지 않고 여기서 204로 응답한다
+*/
+pub struct Cors {
+    origins: Vec<String>,   // 비어 있으면 모든 origin 허용
+    methods: String,
+    headers: String,
+    max_age: u64,
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors::new()
+    }
+}
+
+impl Cors {
+    // with_origin()으로 origin을 지정하지 않으면 모든 origin을 허용한다
+    pub fn new() -> Cors {
+        Cors {
+            origins: Vec::new(),
+            methods: String::from("GET, HEAD, POST, PUT, DELETE, PATCH"),
+            headers: String::from("Content-Type, Authorization"),
+            max_age: 600,
+        }
+    }
+
+    pub fn with_origin(mut self, origin: &str) -> Cors {
+        self.origins.push(origin.to_string());
+        self
+    }
+
+    pub fn with_methods(mut self, methods: &str) -> Cors {
+        self.methods = methods.to_string();
+        self
+    }
+
+    pub fn with_headers(mut self, headers: &str) -> Cors {
+        self.headers = headers.to_string();
+        self
+    }
+
+    // 요청의 Origin이 허용 목록에 있으면 응답에 그대로 돌려줄 값
+    fn allowed_origin(&self, request: &Request) -> Option<String> {
+        let origin = request.header("Origin")?;
+        if self.origins.is_empty() {
+            Some(String::from("*"))
+        } else if self.origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn add_origin(&self, origin: &str, response: &mut Response) {
+        response.headers.insert("Access-Control-Allow-Origin", origin);
+        // origin마다 응답이 달라지므로 캐시가 섞이지 않게 한다
+        if origin != "*" {
+            response.headers.append("Vary", "Origin");
+        }
+    }
+}
+
+impl Middleware for Cors {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        if request.method != Method::Options || !request.headers.contains("Access-Control-Request-Method") {
+            return None;
+        }
+
+        let mut response = Response::new(204);
+        if let Some(origin) = self.allowed_origin(request) {
+            self.add_origin(&origin, &mut response);
+            response.headers.insert("Access-Control-Allow-Methods", &self.methods);
+            response.headers.insert("Access-Control-Allow-Headers", &self.headers);
+            response.headers.insert("Access-Control-Max-Age", &self.max_age.to_string());
+        }
+        Some(response)
+    }
+
+    fn after(&self, request: &Request, response: &mut Response) {
+        if let Some(origin) = self.allowed_origin(request) {
+            self.add_origin(&origin, response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::Router;
+
+    fn router(cors: Cors) -> Router {
+        let mut router = Router::new();
+        router.get("/api", |_, _| Response::text(200, "ok")).wrap(cors);
+        router
+    }
+
+    #[test]
+    fn cors_answers_preflight_and_tags_responses() {
+        let router = router(Cors::new().with_origin("https://app.example"));
+
+        let mut preflight = Request::new(Method::Options, "/api");
+        preflight.headers.insert("Origin", "https://app.example");
+        preflight.headers.insert("Access-Control-Request-Method", "POST");
+        let response = router.respond(&mut preflight);
+        assert_eq!(204, response.status);
+        assert_eq!(Some("https://app.example"), response.headers.get("Access-Control-Allow-Origin"));
+        assert!(response.headers.contains("Access-Control-Allow-Methods"));
+
+        let mut request = Request::new(Method::Get, "/api");
+        request.headers.insert("Origin", "https://app.example");
+        let response = router.respond(&mut request);
+        assert_eq!(200, response.status);
+        assert_eq!(Some("Origin"), response.headers.get("Vary"));
+
+        let mut other = Request::new(Method::Get, "/api");
+        other.headers.insert("Origin", "https://evil.example");
+        assert_eq!(None, router.respond(&mut other).headers.get("Access-Control-Allow-Origin"));
+    }
+}
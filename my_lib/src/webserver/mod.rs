@@ -1,174 +1,185 @@
-use std::io::prelude::*;
-use std::net::TcpListener;
-use std::net::TcpStream;
-use std::fs::File;
+mod access_log;
+mod base64;
+mod client;
+mod compression;
+mod config;
+mod date;
+mod deflate;
+mod error;
+mod form;
+mod headers;
+mod metrics;
+mod middleware;
+mod proxy;
+mod range;
+mod rate_limit;
+mod request;
+mod response;
+mod router;
+mod server;
+mod sha1;
+mod signal;
+mod static_files;
+#[cfg(test)]
+mod test_util;
+mod thread_pool;
+mod url;
+mod vhost;
+mod websocket;
+
+pub use self::access_log::{AccessLog, LogEntry, LogFormat, RotatingFile};
+pub use self::client::{Client, ClientError, ClientResponse};
+pub use self::compression::{
+    adler32, crc32, gzip_decode, gzip_encode, negotiate_encoding, zlib_decode, zlib_encode, Compression, Encoding,
+};
+pub use self::config::{Command, ConfigError, LogTarget, RouteAction, RouteConfig, ServerConfig, DEFAULT_CONFIG_FILE, USAGE};
+pub use self::deflate::{deflate, inflate};
+pub use self::error::{ErrorPage, ErrorPages, HttpError, IntoResponse};
+pub use self::form::{parse_header_params, parse_multipart, FilePart, FormError, Multipart};
+pub use self::headers::Headers;
+pub use self::middleware::{Cors, Middleware};
+pub use self::proxy::ReverseProxy;
+pub use self::range::{parse_range, ByteRange, MultipartRanges, RangeError};
+pub use self::rate_limit::{RateLimit, RateLimitKey};
+pub use self::request::{Method, ParseError, Request, Version};
+pub use self::response::{reason_phrase, Body, ChunkedWriter, Response, StreamFn, Upgrade};
+pub use self::router::{Handler, Params, Router};
+pub use self::server::{Server, ShutdownHandle};
+pub use self::static_files::{mime_type, StaticFiles};
+pub use self::thread_pool::{
+    panic_message, ExecuteError, JobHandle, JoinError, OverflowPolicy, PoolConfig, PoolMonitor, PoolStats, ThreadPool,
+};
+pub use self::url::{form_decode, percent_decode, QueryMap};
+pub use self::websocket::{accept_key, upgrade_websocket, WebSocket, WebSocketHandler, WebSocketMessage};
+pub use self::date::{format_http_date, parse_http_date};
+
+use std::env;
+use std::io::Write;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use std::sync::mpsc;
-use std::sync::Arc;
-use std::sync::Mutex;
-
-// FnBox 트레잇은 함수 포인터를 가지고 있으며, call_box를 호출하면 가지고 있는 함수 포인터에 해당하는 함수를 호출함
-trait FnBox {
-    fn call_box(self: Box<Self>);
-}
-
-// F는 FnOnce() 타입이므로 F는 파라미터들의 소유권을 빼앗아오기때문에 한번만 호출할 수 있음
-impl<F: FnOnce()> FnBox for F {
-    fn call_box(self: Box<F>) {
-        (*self)()
-    }
-}
-
-// Job은 threadsafe 하게 소유권을 이동시킬 수 있고(Send) + 전역 lifetime을 갖는('static) + FnBox 타입의 포인터다
-type Job = Box<dyn FnBox + Send + 'static>;
-
-// 메세지는 Job 생성과 종료
-enum Message {
-    NewJob(Job),
-    Terminate,
-}
-
-// Worker는 Worker ID와 thread handle을 가지고 있음
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
-}
 
-impl Worker {
-    // Worker ID와 receiver의 소유권을 안전하게 전달 받는다
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) ->
-        Worker {
-
-        let thread = thread::spawn(move ||{
-            loop {
-                let message = receiver.lock().unwrap().recv().unwrap();
-
-                match message {
-                    Message::NewJob(job) => {
-                        println!("Worker {} got a job; executing.", id);
-
-                        job.call_box(); // Message에 묻어온 Job에 해당하는 함수를 실행시킴(Job이 가진 FnBox의 함수 포인터 호출)
-                    },
-                    Message::Terminate => {
-                        println!("Worker {} was told to terminate.", id);
-
-                        break;
-                    },
-                }
-            }
-        });
-
-        Worker {
-            id,
-            thread: Some(thread),
-        }
+pub fn sample() {
+    if let Err(e) = serve(&[]) {
+        println!("{}", e);
     }
 }
 
-pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
-}
-
-impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+/*
+"my_app serve [options]"의 본체. 설정 파일(기본 ./webserver.toml)과 명령행 옵션으로 서버를 띄운다
+설정이 잘못됐으면 서버를 열기 전에 문제를 모두 모아 돌려준다
+*/
+pub fn serve(args: &[String]) -> Result<(), ConfigError> {
+    let config = match ServerConfig::from_args(args)? {
+        Command::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        },
+        Command::Check(config) => {
+            println!("configuration OK\n{:#?}", config);
+            return Ok(());
+        },
+        Command::Run(config) => config,
+    };
 
-        // 채널을 통해 sender와 receiver 튜플을 받아오는데
-        let (sender, receiver) = mpsc::channel();
-        // receiver에는 mutex를 씌우고, threadsafe하게 소유권을 이동시킬 수 있도록 하여 재정의 한다
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
-        for id in 0..size {
-            /*
-            정수 ID값과 receiver의 참조자를 복사해서 갖고 있는 Worker를 만들어 Vector에 차례로 넣는다
-            receiver의 참조자를 threadsafe하게 복사해서 넘겼으므로 Worker 내부에서 receiver를 문제없이 사용할 수 있다
-            */
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
-        ThreadPool {
-            workers,
-            sender,
+    let mut server = config.bind(sample_router(&config))?;
+    // Ctrl+C(SIGINT) 또는 SIGTERM을 받으면 처리 중인 요청을 마무리하고 종료한다
+    server.handle_signals();
+    // status.localhost로 들어온 요청은 별도의 사이트로 처리한다. metrics도 이 사이트에서만 보여준다
+    if let Some(host) = &config.status_host {
+        let mut status = status_router(&config);
+        if let Some(path) = &config.metrics_path {
+            status.get(path, server.metrics_handler());
         }
+        server.add_host(host, status);
     }
 
-    // F는 한 번만 호출 될 수 있고(FnOnce : 캡쳐한 파라미터의 소유권을 한 번만 가져올 수 있으므로 호출도 한 번만 할 수 있음), 쓰레드 간 소유권을 이동시킬 수 있고(Send), 그리고 전역의 lifetime을 갖음('static)
-    pub fn execute<F>(&self, f: F) where F: FnOnce() + Send + 'static
-    {
-        /*
-        ThreadPool.execute() 하게 되면
-        파라미터 넘어온 F타입의 함수에 포인터를 씌워 Job 타입으로 만들고, 만든 Job을 Message에 담아 sender로 보낸다
-        해당 메세지는 Worker들 중 하나의 receiver에서 안전하게(mutex+arc) 처리할 것이고
-        결국 Message > Job > F로 감싸져 있는 구성에서, 실제 수행 대상인 F의 함수포인터가 실행된다
-        */
-        let job = Box::new(f);
-        self.sender.send(Message::NewJob(job)).unwrap();
+    for addr in server.local_addrs().map_err(|e| ConfigError::Bind(config.listen.join(", "), e))? {
+        println!("Listening on http://{}", addr);
     }
+    server.run();
+    Ok(())
 }
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        println!("Sending terminate message to all workers.");
-        for _ in &mut self.workers {
-            self.sender.send(Message::Terminate).unwrap();
-        }
-
-        println!("Shutting down all workers.");
-
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
-
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
+fn status_router(config: &ServerConfig) -> Router {
+    let mut router = Router::new();
+    router
+        .get("/", |request, _| {
+            Response::text(200, format!("ok ({})\n", request.header("Host").unwrap_or("-")))
+        })
+        .not_found(|_, _| Response::status_page(404));
+    if let Some((per_second, burst)) = config.rate_limit {
+        router.wrap(RateLimit::new(RateLimitKey::Ip, per_second, burst));
     }
+    router
 }
 
-pub fn sample() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    let pool = ThreadPool::new(4);
-
-    for stream in listener.incoming().take(2) {
-        let stream = stream.unwrap();
-
-        pool.execute(|| {
-            handle_connection(stream);
+fn sample_router(config: &ServerConfig) -> Router {
+    // 파일이 없으면 panic 대신 404를 돌려준다
+    let files = Arc::new(StaticFiles::new(&config.root));
+    let index = Arc::new(config.index.clone());
+
+    let mut router = Router::new();
+    let (home, home_index) = (Arc::clone(&files), Arc::clone(&index));
+    router
+        .get("/", move |request, _| home.serve(request, &home_index))
+        .get("/sleep", move |request, _| {
+            thread::sleep(Duration::from_secs(5));
+            files.serve(request, &index)
+        })
+        // id가 숫자가 아니면 400 에러 페이지로 응답한다
+        .get("/users/:id", |_, params| {
+            let id: u32 = params["id"].parse().map_err(|_| HttpError::bad_request("user id must be a number"))?;
+            Ok(Response::text(200, format!("Hello, user {}!\n", id)))
+        })
+        // 1초마다 한 줄씩 chunked로 흘려 보낸다
+        .get("/countdown", |_, _| {
+            Response::text(200, "").with_stream(|writer| {
+                for i in (1..=5).rev() {
+                    writeln!(writer, "{}...", i)?;
+                    writer.flush()?;
+                    thread::sleep(Duration::from_secs(1));
+                }
+                writeln!(writer, "liftoff!")
+            })
+        })
+        // 올라온 form 필드와 파일 정보를 돌려준다. 파일은 연결에서 임시 디렉터리로 바로 저장됐다가 응답 후 지워진다
+        .post("/upload", |request, _| {
+            let form = request.multipart(&env::temp_dir())?;
+            let mut body = String::new();
+            for (name, value) in form.fields.iter() {
+                body.push_str(&format!("field {} = {}\n", name, value));
+            }
+            for file in &form.files {
+                body.push_str(&format!("file {} = {} ({} bytes, {})\n", file.name, file.filename, file.size, file.content_type));
+            }
+            Ok(Response::text(200, body))
+        })
+        .stream_body()
+        // 받은 메시지를 그대로 돌려주는 WebSocket
+        .get("/ws", |request, _| {
+            upgrade_websocket(request, |socket: &WebSocket, message| {
+                let _ = socket.send(message);
+            })
         });
+    // 설정 파일의 [[route]]들 (기본은 /static 아래에 public 디렉터리의 파일)
+    config.mount_routes(&mut router);
+    if let Some(page) = &config.not_found_page {
+        router.error_page(404, ErrorPage::file(config.root.join(page)));
     }
-
-    println!("Shutting down.");
+    router
+        .fallback_error_page(ErrorPage::template(
+            "<!DOCTYPE html><html><body><h1>{status} {reason}</h1><p>{message}</p></body></html>",
+        ))
+        // 가장 바깥 단계라 다른 middleware가 헤더를 모두 붙인 뒤 마지막으로 본문을 압축한다
+        .wrap(Compression::new())
+        // 다른 origin의 페이지에서도 /users API를 호출할 수 있게 한다
+        .wrap(Cors::new());
+    // 기본은 IP마다 초당 10개, 한 번에 30개까지 (429에도 CORS 헤더가 붙도록 Cors 안쪽에 둔다)
+    // rate_limit_header를 설정하면 그 헤더 값마다 센다
+    if let Some((per_second, burst)) = config.rate_limit {
+        let key = config.rate_limit_header.clone().map_or(RateLimitKey::Ip, RateLimitKey::Header);
+        router.wrap(RateLimit::new(key, per_second, burst));
+    }
+    router
 }
-
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
-    stream.read(&mut buffer).unwrap();
-
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
-
-     let mut file = File::open(filename).unwrap();
-     let mut contents = String::new();
-
-     file.read_to_string(&mut contents).unwrap();
-
-     let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
-
-     stream.write(response.as_bytes()).unwrap();
-     stream.flush().unwrap();
-}
\ No newline at end of file
@@ -0,0 +1,437 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::client::{Client, ClientError, ClientResponse};
+use super::error::HttpError;
+use super::headers::Headers;
+use super::request::{Method, ParseError, Request};
+use super::response::Response;
+
+// 연결에 실패해 내려간 upstream을 health check 없이 다시 시도해 보기까지 기다리는 시간
+const RETRY_DOWN_AFTER: Duration = Duration::from_secs(10);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 연결 하나에만 의미가 있어서 upstream에 그대로 넘기면 안 되는 헤더 (RFC 7230 6.1)
+const HOP_BY_HOP: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+struct Upstream {
+    addr: String,                       // "127.0.0.1:9001"
+    down_since: Mutex<Option<Instant>>, // None이면 요청을 보낼 수 있는 상태
+}
+
+impl Upstream {
+    fn is_available(&self, health_checked: bool) -> bool {
+        match *self.down_since.lock().unwrap() {
+            None => true,
+            // health check를 하지 않으면 다시 살아났는지 알 길이 없으므로 잠시 뒤 요청을 보내 본다
+            Some(since) => !health_checked && since.elapsed() >= RETRY_DOWN_AFTER,
+        }
+    }
+
+    fn mark(&self, healthy: bool) {
+        let mut down_since = self.down_since.lock().unwrap();
+        match (healthy, down_since.is_some()) {
+            (true, true) => {
+                println!("Upstream {} is back up", self.addr);
+                *down_since = None;
+            },
+            (false, false) => {
+                println!("Upstream {} is down", self.addr);
+                *down_since = Some(Instant::now());
+            },
+            (false, true) => *down_since = Some(Instant::now()),
+            (true, false) => {},
+        }
+    }
+}
+
+struct Shared {
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+    client: Client,
+    strip_prefix: Option<String>,
+    health_check: Option<(String, Duration)>,   // 검사할 경로와 간격
+}
+
+// 요청을 다른 서버(upstream)로 넘기고 그 응답을 그대로 돌려주는 handler
+//     let api = Arc::new(ReverseProxy::new(&["127.0.0.1:9001", "127.0.0.1:9002"])
+//         .with_strip_prefix("/api")
+//         .with_health_check("/health", Duration::from_secs(5)));
+//     router.any("/api/*path", move |request, _| api.forward(request)).stream_body();
+//   - upstream이 여럿이면 round-robin으로 돌아가며 보내고, 연결되지 않는 upstream은 건너뛴다
+//   - Host는 upstream 주소로 바꾸고, 원래 Host와 클라이언트 주소는 X-Forwarded-Host, X-Forwarded-For로 알린다
+//   - 응답 본문은 메모리에 모으지 않고 받는 대로 클라이언트에게 흘려 보낸다 (chunked 응답의 trailer 포함)
+//   - 요청 본문도 stream_body()로 등록한 route면 받는 대로 upstream에 보낸다. 길이를 모르면 chunked로 보낸다
+//     본문이 있는 요청은 upstream마다 새 연결을 쓰고, 본문을 읽다 실패하면 그 에러의 상태 코드(413 등)로 응답한다
+//   - upstream에 연결할 수 없으면 502, 응답이 늦으면 504, 살아 있는 upstream이 없으면 503
+pub struct ReverseProxy {
+    shared: Arc<Shared>,
+    health_check_started: Once,
+}
+
+impl ReverseProxy {
+    pub fn new(upstreams: &[&str]) -> ReverseProxy {
+        assert!(!upstreams.is_empty(), "ReverseProxy needs at least one upstream");
+        let mut client = Client::new();
+        // 3xx 응답은 따라가지 않고 클라이언트에게 그대로 돌려준다
+        client.set_max_redirects(0);
+        ReverseProxy {
+            shared: Arc::new(Shared {
+                upstreams: upstreams
+                    .iter()
+                    .map(|addr| Upstream { addr: addr.to_string(), down_since: Mutex::new(None) })
+                    .collect(),
+                next: AtomicUsize::new(0),
+                client,
+                strip_prefix: None,
+                health_check: None,
+            }),
+            health_check_started: Once::new(),
+        }
+    }
+
+    // upstream으로 보낼 때 경로 앞에서 뗄 부분. "/api/users"는 "/users"로 간다
+    pub fn with_strip_prefix(mut self, prefix: &str) -> ReverseProxy {
+        self.shared_mut().strip_prefix = Some(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    // upstream의 응답을 기다리는 시간
+    pub fn with_timeout(mut self, timeout: Duration) -> ReverseProxy {
+        self.shared_mut().client.set_timeout(timeout);
+        self
+    }
+
+    /*
+    interval마다 각 upstream의 path로 GET을 보내 2xx, 3xx가 아니면 내려간 것으로 보고 요청을 보내지 않는다
+    다시 정상 응답을 하면 되살린다. 검사 쓰레드는 첫 요청 때 시작하고 ReverseProxy가 drop되면 끝난다
+    */
+    pub fn with_health_check(mut self, path: &str, interval: Duration) -> ReverseProxy {
+        self.shared_mut().health_check = Some((path.to_string(), interval));
+        self
+    }
+
+    fn shared_mut(&mut self) -> &mut Shared {
+        Arc::get_mut(&mut self.shared).expect("ReverseProxy must be configured before use")
+    }
+
+    // 요청을 upstream 하나에 보낸다. 연결이 거부되면 다음 upstream으로 넘어간다
+    pub fn forward(&self, request: &Request) -> Result<Response, HttpError> {
+        let shared = &self.shared;
+        if let Some((path, interval)) = shared.health_check.clone() {
+            self.health_check_started.call_once(|| {
+                let shared = Arc::downgrade(shared);
+                thread::Builder::new()
+                    .name(String::from("proxy-health-check"))
+                    .spawn(move || health_check(shared, &path, interval))
+                    .expect("failed to spawn health check thread");
+            });
+        }
+        let outgoing = outgoing_request(request, shared.strip_prefix.as_deref());
+        let body_len = request.body_len();
+        let mut body = request.body_reader();
+        let count = shared.upstreams.len();
+        let start = shared.next.fetch_add(1, Ordering::Relaxed);
+
+        let mut last_error = None;
+        for i in 0..count {
+            let upstream = &shared.upstreams[(start + i) % count];
+            if !upstream.is_available(shared.health_check.is_some()) {
+                continue;
+            }
+            let url = format!("http://{}{}", upstream.addr, outgoing.target);
+            // 연결하기 전에는 본문을 읽지 않으므로 연결에 실패하면 다음 upstream에 그대로 보낼 수 있다
+            let result = match body_len {
+                Some(0) => shared.client.send(&url, outgoing.clone()),
+                len => shared.client.send_reader(&url, outgoing.clone(), &mut body, len),
+            };
+            match result {
+                Ok(response) => {
+                    upstream.mark(true);
+                    return Ok(incoming_response(request, response));
+                },
+                // 연결조차 되지 않았으면 요청이 전달되지 않았으므로 다른 upstream에 보내도 안전하다
+                Err(ClientError::Io(e)) if is_connect_error(&e) => {
+                    upstream.mark(false);
+                    last_error = Some(e);
+                },
+                Err(ClientError::Body(e)) => return Err(body_error(e)),
+                Err(e) => return Err(gateway_error(&upstream.addr, e)),
+            }
+        }
+        match last_error {
+            Some(e) => Err(HttpError::new(502, &format!("no upstream reachable: {}", e))),
+            None => Err(HttpError::new(503, "all upstreams are down")),
+        }
+    }
+}
+
+fn is_connect_error(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound | io::ErrorKind::AddrNotAvailable)
+}
+
+fn gateway_error(addr: &str, e: ClientError) -> HttpError {
+    let status = match &e {
+        ClientError::Io(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => 504,
+        _ => 502,
+    };
+    HttpError::new(status, &format!("upstream {}: {}", addr, e))
+}
+
+// 클라이언트가 보내는 본문을 읽지 못한 것은 upstream 탓이 아니다
+fn body_error(e: io::Error) -> HttpError {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<ParseError>()) {
+        Some(parse_error) => HttpError::new(parse_error.status(), &format!("cannot read request body: {}", parse_error)),
+        None => HttpError::bad_request(&format!("cannot read request body: {}", e)),
+    }
+}
+
+// Connection 헤더에 나열된 헤더도 hop-by-hop이다
+fn remove_hop_by_hop(headers: &mut Headers) {
+    let listed: Vec<String> = headers
+        .get_all("Connection")
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().to_string())
+        .collect();
+    for name in HOP_BY_HOP.iter().copied().chain(listed.iter().map(|s| s.as_str())) {
+        headers.remove(name);
+    }
+}
+
+fn outgoing_request(request: &Request, strip_prefix: Option<&str>) -> Request {
+    let mut target = request.target.as_str();
+    if let Some(rest) = strip_prefix.and_then(|prefix| target.strip_prefix(prefix)) {
+        if rest.is_empty() || rest.starts_with('/') || rest.starts_with('?') {
+            target = rest;
+        }
+    }
+    let target = if target.starts_with('/') { target.to_string() } else { format!("/{}", target) };
+
+    let mut outgoing = Request::new(request.method.clone(), &target);
+    outgoing.headers = request.headers.clone();
+    remove_hop_by_hop(&mut outgoing.headers);
+    outgoing.headers.remove("Content-Length");
+
+    // 앞단 proxy가 붙인 값 뒤에 이번 클라이언트 주소를 덧붙인다
+    if let Some(addr) = request.remote_addr {
+        let forwarded_for = match request.header("X-Forwarded-For") {
+            Some(previous) => format!("{}, {}", previous, addr.ip()),
+            None => addr.ip().to_string(),
+        };
+        outgoing.headers.insert("X-Forwarded-For", &forwarded_for);
+    }
+    if let Some(host) = request.header("Host") {
+        if !outgoing.headers.contains("X-Forwarded-Host") {
+            outgoing.headers.insert("X-Forwarded-Host", host);
+        }
+    }
+    if !outgoing.headers.contains("X-Forwarded-Proto") {
+        outgoing.headers.insert("X-Forwarded-Proto", "http");
+    }
+    outgoing
+}
+
+// upstream 응답을 클라이언트에게 보낼 Response로 바꾼다. 본문은 읽지 않고 연결째 넘긴다
+fn incoming_response(request: &Request, mut upstream: ClientResponse) -> Response {
+    let mut response = Response::new(upstream.status);
+    response.headers = upstream.headers.clone();
+    remove_hop_by_hop(&mut response.headers);
+    response.headers.remove("Content-Length");
+
+    if request.method == Method::Head {
+        return response;
+    }
+    let length = match upstream.header("Content-Length") {
+        Some(_) if upstream.headers.contains("Transfer-Encoding") => None,
+        Some(value) => value.trim().parse::<u64>().ok(),
+        None => None,
+    };
+    match length {
+        Some(len) => response.with_reader(upstream, len),
+        None => response.with_stream(move |writer| {
+            io::copy(&mut upstream, writer)?;
+            for (name, value) in upstream.trailers().iter() {
+                writer.trailer(name, value);
+            }
+            Ok(())
+        }),
+    }
+}
+
+fn health_check(shared: Weak<Shared>, path: &str, interval: Duration) {
+    let mut client = Client::new();
+    client.set_timeout(HEALTH_CHECK_TIMEOUT.min(interval));
+    client.set_max_redirects(0);
+    loop {
+        thread::sleep(interval);
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        for upstream in &shared.upstreams {
+            let healthy = match client.get(&format!("http://{}{}", upstream.addr, path)) {
+                Ok(mut response) => {
+                    let _ = io::copy(&mut response, &mut io::sink());
+                    (200..400).contains(&response.status)
+                },
+                Err(_) => false,
+            };
+            upstream.mark(healthy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Router, Server};
+    use std::io::{Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::atomic::AtomicBool;
+
+    fn start(router: Router) -> String {
+        let server = Server::bind("127.0.0.1:0", 4, router).unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    fn backend(name: &'static str) -> String {
+        let mut router = Router::new();
+        router
+            .get("/whoami", move |request, _| {
+                let header = |name| request.header(name).unwrap_or("-").to_string();
+                Response::text(200, format!("{} {} {} {} {}", name, request.target, header("Host"), header("X-Forwarded-For"), header("X-Forwarded-Host")))
+            })
+            .post("/echo", |request, _| Response::text(201, request.body.clone()).with_header("X-Backend", "echo"))
+            // 받은 본문의 길이와 전송 방식
+            .post("/count", |request, _| {
+                let len = io::copy(&mut request.body_reader(), &mut io::sink()).map_err(|e| HttpError::bad_request(&e.to_string()))?;
+                Ok(Response::text(200, format!("{} {}", len, request.header("Transfer-Encoding").unwrap_or("-"))))
+            })
+            .stream_body()
+            .get("/stream", |_, _| {
+                Response::text(200, "").with_stream(|writer| {
+                    writer.write_all(b"streamed ")?;
+                    writer.flush()?;
+                    writer.write_all(b"body")?;
+                    writer.trailer("X-Done", "yes");
+                    Ok(())
+                })
+            });
+        start(router)
+    }
+
+    fn front(proxy: ReverseProxy) -> String {
+        let proxy = Arc::new(proxy);
+        let mut router = Router::new();
+        router.any("/api/*path", move |request, _| proxy.forward(request)).stream_body();
+        format!("http://{}", start(router))
+    }
+
+    fn unused_addr() -> String {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn balances_and_rewrites_headers() {
+        let (a, b) = (backend("a"), backend("b"));
+        let base = front(ReverseProxy::new(&[&a, &b]).with_strip_prefix("/api"));
+        let client = Client::new();
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            let text = client.get(&format!("{}/api/whoami?x=1", base)).unwrap().text().unwrap();
+            let fields: Vec<&str> = text.split(' ').collect();
+            assert_eq!("/whoami?x=1", fields[1]);
+            assert_eq!(if fields[0] == "a" { &a } else { &b }, fields[2]);
+            assert_eq!("127.0.0.1", fields[3]);
+            assert_eq!(base.trim_start_matches("http://"), fields[4]);
+            seen.push(fields[0].to_string());
+        }
+        assert_eq!(seen[0], seen[2]);
+        assert_ne!(seen[0], seen[1]);
+    }
+
+    #[test]
+    fn streams_bodies_both_ways() {
+        let base = front(ReverseProxy::new(&[&backend("a")]).with_strip_prefix("/api"));
+        let client = Client::new();
+
+        let mut response = client.post(&format!("{}/api/echo", base), "text/plain", "ping").unwrap();
+        assert_eq!(201, response.status);
+        assert_eq!(Some("echo"), response.header("X-Backend"));
+        assert_eq!("ping", response.text().unwrap());
+
+        // 서버가 메모리에 모을 수 있는 크기(10MiB)보다 큰 본문도 받는 대로 upstream에 넘긴다
+        let size = 11 * 1024 * 1024;
+        let mut response = client.post(&format!("{}/api/count", base), "application/octet-stream", vec![b'x'; size]).unwrap();
+        assert_eq!(format!("{} -", size), response.text().unwrap());
+        // 길이를 모르는 본문은 chunked로 받아 chunked로 넘긴다
+        let upload = Request::new(Method::Post, "/");
+        let mut response = client.send_reader(&format!("{}/api/count", base), upload, &[b'y'; 100_000][..], None).unwrap();
+        assert_eq!("100000 chunked", response.text().unwrap());
+
+        let mut response = client.get(&format!("{}/api/stream", base)).unwrap();
+        assert_eq!(Some("chunked"), response.header("Transfer-Encoding"));
+        assert_eq!("streamed body", response.text().unwrap());
+        assert_eq!(Some("yes"), response.trailers().get("X-Done"));
+
+        assert_eq!(404, client.get(&format!("{}/api/missing", base)).unwrap().status);
+    }
+
+    #[test]
+    fn skips_unreachable_and_unhealthy_upstreams() {
+        let live = backend("live");
+        let base = front(ReverseProxy::new(&[&unused_addr(), &live]).with_strip_prefix("/api"));
+        let client = Client::new();
+        for _ in 0..3 {
+            let text = client.get(&format!("{}/api/whoami", base)).unwrap().text().unwrap();
+            assert!(text.starts_with("live "));
+        }
+
+        let base = front(ReverseProxy::new(&[&unused_addr()]));
+        assert_eq!(502, client.get(&format!("{}/api/whoami", base)).unwrap().status);
+
+        // health check가 실패하면 살아 있어도 보내지 않는다
+        let healthy = Arc::new(AtomicBool::new(true));
+        let flag = Arc::clone(&healthy);
+        let mut router = Router::new();
+        router.get("/health", move |_, _| Response::status_page(if flag.load(Ordering::SeqCst) { 200 } else { 503 }));
+        let upstream = start(router);
+        let base = front(ReverseProxy::new(&[&upstream]).with_health_check("/health", Duration::from_millis(20)));
+
+        assert_eq!(404, client.get(&format!("{}/api/health", base)).unwrap().status);
+        healthy.store(false, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(503, client.get(&format!("{}/api/health", base)).unwrap().status);
+        healthy.store(true, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(404, client.get(&format!("{}/api/health", base)).unwrap().status);
+    }
+
+    #[test]
+    fn answers_truncated_upload_without_blaming_upstream() {
+        let base = front(ReverseProxy::new(&[&backend("a")]).with_strip_prefix("/api"));
+        let mut stream = TcpStream::connect(base.trim_start_matches("http://")).unwrap();
+        stream.write_all(b"POST /api/count HTTP/1.1\r\nHost: test\r\nContent-Length: 100\r\n\r\nonly ten..").unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+    }
+}
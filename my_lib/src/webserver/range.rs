@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{Cursor, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 요청 하나에 허용하는 최대 range 수. 너무 잘게 쪼갠 요청으로 서버를 괴롭히지 못하게 한다
+const MAX_RANGES: usize = 16;
+
+// 파일의 start ~ end 바이트 (end 포함)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    // start <= end 이므로 항상 한 바이트 이상이다
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    // Content-Range 헤더 값
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeError {
+    Invalid,        // 문법이 틀렸음. 이 경우 Range 헤더를 무시하고 전체를 보낸다
+    Unsatisfiable,  // 파일 안에 해당하는 바이트가 하나도 없음 (416)
+}
+
+/*
+"bytes=0-499", "bytes=500-", "bytes=-500"(마지막 500바이트), "bytes=0-0,-1" 형태를 해석한다
+파일 길이를 넘는 끝 위치는 파일 끝으로 줄이고, 시작 위치가 파일 밖인 range는 버린다
+*/
+pub fn parse_range(value: &str, total: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let specs = value.trim().strip_prefix("bytes=").ok_or(RangeError::Invalid)?;
+    if specs.split(',').count() > MAX_RANGES {
+        return Err(RangeError::Invalid);
+    }
+    let mut ranges = Vec::new();
+
+    for spec in specs.split(',') {
+        let (start, end) = spec.trim().split_once('-').ok_or(RangeError::Invalid)?;
+        let parse = |s: &str| s.parse::<u64>().map_err(|_| RangeError::Invalid);
+
+        let range = match (start.is_empty(), end.is_empty()) {
+            (true, true) => return Err(RangeError::Invalid),
+            // suffix range: 마지막 n 바이트
+            (true, false) => {
+                let suffix = parse(end)?;
+                if suffix == 0 || total == 0 {
+                    continue;
+                }
+                ByteRange { start: total.saturating_sub(suffix), end: total - 1 }
+            },
+            (false, true) => {
+                let start = parse(start)?;
+                if start >= total {
+                    continue;
+                }
+                ByteRange { start, end: total - 1 }
+            },
+            (false, false) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if end < start {
+                    return Err(RangeError::Invalid);
+                }
+                if start >= total {
+                    continue;
+                }
+                ByteRange { start, end: end.min(total - 1) }
+            },
+        };
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return Err(RangeError::Unsatisfiable);
+    }
+    Ok(ranges)
+}
+
+// multipart/byteranges 구분자. 본문에 우연히 같은 문자열이 나올 확률을 낮추기 위해 시간과 카운터를 섞는다
+fn boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("byteranges_{:x}_{:x}", nanos, COUNTER.fetch_add(1, Ordering::SeqCst))
+}
+
+enum Part {
+    Bytes(Cursor<Vec<u8>>),
+    File(ByteRange),
+}
+
+/*
+여러 range를 multipart/byteranges 본문으로 흘려 보내는 Reader
+각 part의 헤더는 미리 만들어 두고, 파일 내용은 읽을 차례가 되었을 때 seek해서 읽는다
+*/
+pub struct MultipartRanges {
+    file: File,
+    parts: VecDeque<Part>,
+    remaining: u64,     // 현재 파일 part에서 남은 바이트 수
+    len: u64,
+}
+
+impl MultipartRanges {
+    // (Content-Type 헤더 값, 본문 Reader)
+    pub fn new(file: File, ranges: &[ByteRange], content_type: &str, total: u64) -> (String, MultipartRanges) {
+        let boundary = boundary();
+        let mut parts = VecDeque::new();
+        let mut len = 0;
+
+        for range in ranges {
+            let head = format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary, content_type, range.content_range(total)
+            );
+            len += head.len() as u64 + range.len();
+            parts.push_back(Part::Bytes(Cursor::new(head.into_bytes())));
+            parts.push_back(Part::File(*range));
+        }
+        let tail = format!("\r\n--{}--\r\n", boundary);
+        len += tail.len() as u64;
+        parts.push_back(Part::Bytes(Cursor::new(tail.into_bytes())));
+
+        let reader = MultipartRanges { file, parts, remaining: 0, len };
+        (format!("multipart/byteranges; boundary={}", boundary), reader)
+    }
+
+    // 본문 전체 길이 (Content-Length)
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for MultipartRanges {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = match self.parts.front_mut() {
+                None => return Ok(0),
+                Some(Part::Bytes(cursor)) => cursor.read(buf)?,
+                Some(Part::File(range)) => {
+                    if self.remaining == 0 {
+                        // 이 part를 처음 읽는 순간에 파일 위치를 옮긴다
+                        self.file.seek(SeekFrom::Start(range.start))?;
+                        self.remaining = range.len();
+                    }
+                    let max = buf.len().min(self.remaining as usize);
+                    let n = self.file.read(&mut buf[..max])?;
+                    if n == 0 && max > 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while sending"));
+                    }
+                    self.remaining -= n as u64;
+                    if self.remaining == 0 {
+                        self.parts.pop_front();
+                    }
+                    return Ok(n);
+                },
+            };
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.parts.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::test_util::TempDir;
+
+    #[test]
+    fn parses_range_forms() {
+        let r = |start, end| ByteRange { start, end };
+
+        assert_eq!(Ok(vec![r(0, 499)]), parse_range("bytes=0-499", 1000));
+        assert_eq!(Ok(vec![r(900, 999)]), parse_range("bytes=900-", 1000));
+        assert_eq!(Ok(vec![r(500, 999)]), parse_range("bytes=-500", 1000));
+        assert_eq!(Ok(vec![r(0, 999)]), parse_range("bytes=-5000", 1000));
+        assert_eq!(Ok(vec![r(990, 999)]), parse_range("bytes=990-2000", 1000));
+        assert_eq!(Ok(vec![r(0, 0), r(999, 999)]), parse_range("bytes=0-0, -1", 1000));
+    }
+
+    #[test]
+    fn reports_invalid_and_unsatisfiable_ranges() {
+        assert_eq!(Err(RangeError::Invalid), parse_range("items=0-1", 1000));
+        assert_eq!(Err(RangeError::Invalid), parse_range("bytes=5-1", 1000));
+        assert_eq!(Err(RangeError::Invalid), parse_range("bytes=a-b", 1000));
+        assert_eq!(Err(RangeError::Unsatisfiable), parse_range("bytes=1000-", 1000));
+        assert_eq!(Err(RangeError::Unsatisfiable), parse_range("bytes=-0", 1000));
+        assert_eq!(Ok(vec![ByteRange { start: 0, end: 1 }]), parse_range("bytes=0-1,2000-3000", 1000));
+    }
+
+    #[test]
+    fn streams_multipart_body() {
+        let dir = TempDir::new("range");
+        let path = dir.join("digits.txt");
+        std::fs::write(&path, "0123456789").unwrap();
+        let ranges = [ByteRange { start: 0, end: 1 }, ByteRange { start: 8, end: 9 }];
+
+        let (content_type, mut reader) = MultipartRanges::new(File::open(&path).unwrap(), &ranges, "text/plain", 10);
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        let len = reader.len();
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(expected, body);
+        assert_eq!(len, body.len() as u64);
+    }
+}
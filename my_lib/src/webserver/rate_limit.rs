@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::middleware::Middleware;
+use super::request::Request;
+use super::response::Response;
+
+// 꽉 찬 bucket은 새로 만든 것과 같으므로 이 간격마다 지워서 map이 끝없이 커지지 않게 한다
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+// key를 바꿔 가며 보내는 클라이언트 때문에 map이 끝없이 커지지 않도록 기억하는 key 수의 상한
+const MAX_KEYS: usize = 100_000;
+// 이보다 느린 속도는 token 하나가 차는 데 몇 시간씩 걸려 사실상 차단과 같다. Retry-After도 이 안에서 정한다
+pub(super) const MIN_RATE: f64 = 0.001;
+const MAX_WAIT: Duration = Duration::from_secs(1000);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/*
+key(클라이언트 IP 등)마다 하나씩 두는 token bucket
+bucket에는 최대 burst개의 token이 있고 초당 per_second개씩 다시 찬다. 요청 하나가 token 하나를 쓴다
+여러 worker 쓰레드가 함께 쓰므로 Mutex로 감싼다
+*/
+pub(super) struct TokenBuckets {
+    per_second: f64,
+    burst: f64,
+    max_keys: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    buckets: HashMap<String, Bucket>,
+    last_prune: Instant,
+}
+
+impl TokenBuckets {
+    pub(super) fn new(per_second: f64, burst: u32) -> TokenBuckets {
+        assert!(per_second >= MIN_RATE && burst > 0, "rate limit must allow at least some requests");
+        TokenBuckets {
+            per_second,
+            burst: f64::from(burst),
+            max_keys: MAX_KEYS,
+            state: Mutex::new(State { buckets: HashMap::new(), last_prune: Instant::now() }),
+        }
+    }
+
+    // token을 하나 쓴다. 남은 것이 없으면 다음 token이 찰 때까지 기다려야 하는 시간을 돌려준다
+    pub(super) fn take(&self, key: &str) -> Result<(), Duration> {
+        self.take_at(key, Instant::now())
+    }
+
+    fn take_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let full = !state.buckets.contains_key(key) && state.buckets.len() >= self.max_keys;
+        if full || now.saturating_duration_since(state.last_prune) >= PRUNE_INTERVAL {
+            let (per_second, burst) = (self.per_second, self.burst);
+            state.buckets.retain(|_, bucket| refill(bucket, now, per_second, burst) < burst);
+            state.last_prune = now;
+        }
+        // 정리한 뒤에도 자리가 없으면 처음 보는 key는 bucket이 빌 때까지 기다리게 한다
+        if !state.buckets.contains_key(key) && state.buckets.len() >= self.max_keys {
+            return Err(self.wait_for(1.0));
+        }
+
+        let bucket = state.buckets.entry(key.to_string()).or_insert(Bucket { tokens: self.burst, updated: now });
+        let tokens = refill(bucket, now, self.per_second, self.burst);
+        *bucket = Bucket { tokens, updated: now.max(bucket.updated) };
+        if tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.wait_for(1.0 - tokens))
+        }
+    }
+
+    // tokens개가 다시 차는 데 걸리는 시간
+    fn wait_for(&self, tokens: f64) -> Duration {
+        Duration::try_from_secs_f64(tokens / self.per_second).map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT))
+    }
+}
+
+fn refill(bucket: &Bucket, now: Instant, per_second: f64, burst: f64) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * per_second).min(burst)
+}
+
+// Retry-After 값. 초 단위 정수라서 올림하고, 0초는 바로 다시 보내라는 뜻이 되므로 최소 1초
+pub(super) fn retry_after(wait: Duration) -> String {
+    wait.as_secs_f64().ceil().max(1.0).to_string()
+}
+
+// 요청을 어느 bucket에 셀지
+#[derive(Debug, Clone, PartialEq)]
+pub enum RateLimitKey {
+    Ip,
+    Header(String), // 이 헤더 값(API key 등)마다. 헤더가 없는 요청은 IP로 센다
+}
+
+/*
+클라이언트마다 초당 요청 수를 제한하는 middleware. 넘으면 handler를 부르지 않고 429와 Retry-After로 응답한다
+    router.wrap(RateLimit::per_ip(10.0, 20));                   // IP마다 초당 10개, 한 번에 20개까지
+    router.wrap(RateLimit::per_header("X-Api-Key", 5.0, 5));    // API key마다
+IP는 연결의 주소이므로 앞에 reverse proxy가 있으면 모든 요청이 proxy 하나로 세어진다
+헤더 값은 클라이언트가 마음대로 정하므로 요청마다 새 값을 보내면 제한을 피할 수 있다
+앞단에서 key를 검증할 때만 per_header를 쓰고, 그 밖에는 per_ip를 쓴다
+*/
+pub struct RateLimit {
+    key: RateLimitKey,
+    buckets: TokenBuckets,
+}
+
+impl RateLimit {
+    pub fn new(key: RateLimitKey, per_second: f64, burst: u32) -> RateLimit {
+        RateLimit { key, buckets: TokenBuckets::new(per_second, burst) }
+    }
+
+    pub fn per_ip(per_second: f64, burst: u32) -> RateLimit {
+        RateLimit::new(RateLimitKey::Ip, per_second, burst)
+    }
+
+    pub fn per_header(name: &str, per_second: f64, burst: u32) -> RateLimit {
+        RateLimit::new(RateLimitKey::Header(name.to_string()), per_second, burst)
+    }
+
+    // 헤더 값과 IP가 우연히 같아도 섞이지 않게 종류를 앞에 붙인다
+    fn key_of(&self, request: &Request) -> String {
+        if let RateLimitKey::Header(name) = &self.key {
+            if let Some(value) = request.header(name) {
+                return format!("header:{}", value);
+            }
+        }
+        match request.remote_addr {
+            Some(addr) => format!("ip:{}", addr.ip()),
+            None => String::from("ip:-"),
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        let wait = self.buckets.take(&self.key_of(request)).err()?;
+        Some(Response::status_page(429).with_header("Retry-After", &retry_after(wait)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Method, Router};
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let buckets = TokenBuckets::new(2.0, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(Ok(()), buckets.take_at("a", start));
+        }
+        assert_eq!(Err(Duration::from_millis(500)), buckets.take_at("a", start));
+        // 다른 key는 따로 센다
+        assert_eq!(Ok(()), buckets.take_at("b", start));
+
+        assert_eq!(Ok(()), buckets.take_at("a", start + Duration::from_millis(500)));
+        assert!(buckets.take_at("a", start + Duration::from_millis(600)).is_err());
+        // 오래 쉬어도 burst 이상으로 쌓이지 않는다
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), buckets.take_at("a", later));
+        }
+        assert!(buckets.take_at("a", later).is_err());
+        assert_eq!(1, buckets.state.lock().unwrap().buckets.len());
+
+        // 아주 느린 속도에서도 기다릴 시간은 상한을 넘지 않는다
+        let slow = TokenBuckets::new(MIN_RATE, 1);
+        assert_eq!(Ok(()), slow.take_at("a", start));
+        assert_eq!(Err(MAX_WAIT), slow.take_at("a", start));
+
+        // 기억할 수 있는 key 수를 넘으면 처음 보는 key는 기다려야 한다
+        let buckets = TokenBuckets { max_keys: 2, ..TokenBuckets::new(1.0, 1) };
+        assert_eq!(Ok(()), buckets.take_at("a", start));
+        assert_eq!(Ok(()), buckets.take_at("b", start));
+        assert_eq!(Err(Duration::from_secs(1)), buckets.take_at("c", start));
+        // 가득 찬 bucket은 정리되므로 시간이 지나면 자리가 난다
+        assert_eq!(Ok(()), buckets.take_at("c", start + Duration::from_secs(1)));
+
+        assert_eq!("1", retry_after(Duration::from_millis(10)));
+        assert_eq!("3", retry_after(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn limits_requests_by_header() {
+        let mut router = Router::new();
+        router.get("/", |_, _| Response::text(200, "ok")).wrap(RateLimit::per_header("X-Api-Key", 0.1, 2));
+
+        let status = |key: Option<&str>| {
+            let mut request = Request::new(Method::Get, "/");
+            request.remote_addr = Some("10.0.0.1:5000".parse().unwrap());
+            if let Some(key) = key {
+                request.headers.insert("X-Api-Key", key);
+            }
+            let response = router.respond(&mut request);
+            (response.status, response.headers.get("Retry-After").map(String::from))
+        };
+        assert_eq!((200, None), status(Some("alice")));
+        assert_eq!((200, None), status(Some("alice")));
+        assert_eq!((429, Some(String::from("10"))), status(Some("alice")));
+        assert_eq!((200, None), status(Some("bob")));
+        // 헤더가 없으면 IP로 센다
+        assert_eq!((200, None), status(None));
+        assert_eq!((200, None), status(None));
+        assert_eq!(429, status(None).0);
+    }
+}
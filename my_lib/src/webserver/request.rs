@@ -0,0 +1,781 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::form::{parse_header_params, parse_multipart, FormError, Multipart};
+use super::headers::Headers;
+use super::url::QueryMap;
+
+// 요청 라인/헤더 한 줄, 헤더 개수, 본문 크기의 상한
+// 상한이 없으면 악의적인 클라이언트가 끝없이 데이터를 보내 메모리를 고갈시킬 수 있다
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY_LEN: u64 = 10 * 1024 * 1024;
+// handler가 읽는 만큼 가져오는 본문(stream_body)의 상한. 파일은 디스크로 바로 가므로 훨씬 크게 둔다
+const MAX_STREAMED_BODY_LEN: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Patch,
+    Trace,
+    Connect,
+    Other(String),
+}
+
+impl Method {
+    // 메소드는 RFC 7230의 token 문자로만 이루어져야 한다
+    pub fn parse(s: &str) -> Option<Method> {
+        if s.is_empty() || !s.bytes().all(is_token_char) {
+            return None;
+        }
+
+        let method = match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "OPTIONS" => Method::Options,
+            "PATCH" => Method::Patch,
+            "TRACE" => Method::Trace,
+            "CONNECT" => Method::Connect,
+            other => Method::Other(other.to_string()),
+        };
+        Some(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+            Method::Patch => "PATCH",
+            Method::Trace => "TRACE",
+            Method::Connect => "CONNECT",
+            Method::Other(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::Http10 => f.write_str("HTTP/1.0"),
+            Version::Http11 => f.write_str("HTTP/1.1"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    Eof,                        // 요청을 한 바이트도 받기 전에 연결이 닫힘
+    Malformed(&'static str),    // 문법에 맞지 않는 요청
+    HeaderTooLarge,
+    BodyTooLarge,
+    UnsupportedVersion,
+    UnsupportedTransferEncoding,
+}
+
+impl ParseError {
+    // 에러를 클라이언트에게 돌려줄 상태 코드
+    pub fn status(&self) -> u16 {
+        match self {
+            // 정해진 시간 안에 요청을 다 보내지 않음
+            ParseError::Io(e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => 408,
+            ParseError::HeaderTooLarge => 431,
+            ParseError::BodyTooLarge => 413,
+            ParseError::UnsupportedVersion => 505,
+            ParseError::UnsupportedTransferEncoding => 501,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "i/o error: {}", e),
+            ParseError::Eof => f.write_str("connection closed"),
+            ParseError::Malformed(reason) => write!(f, "malformed request: {}", reason),
+            ParseError::HeaderTooLarge => f.write_str("request header too large"),
+            ParseError::BodyTooLarge => f.write_str("request body too large"),
+            ParseError::UnsupportedVersion => f.write_str("unsupported HTTP version"),
+            ParseError::UnsupportedTransferEncoding => f.write_str("unsupported transfer encoding"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// ? 연산자로 io::Error를 ParseError로 바로 전파하기 위함
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub target: String,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub trailers: Headers,  // chunked 본문 뒤에 붙어 온 헤더
+    pub remote_addr: Option<SocketAddr>,    // 요청을 보낸 클라이언트 주소 (서버가 채운다)
+    pub(super) body_stream: Option<BodyReader>, // stream_body()로 받은 요청이면 아직 읽지 않은 본문
+}
+
+impl Request {
+    pub fn new(method: Method, target: &str) -> Request {
+        Request {
+            method,
+            target: target.to_string(),
+            version: Version::Http11,
+            headers: Headers::new(),
+            body: Vec::new(),
+            trailers: Headers::new(),
+            remote_addr: None,
+            body_stream: None,
+        }
+    }
+
+    /*
+    BufRead에서 요청 하나를 읽어 파싱한다
+    고정 크기 버퍼에 한 번 read() 하는 대신, 줄 단위로 필요한 만큼만 읽기 때문에
+    요청 크기에 제한이 없고 reader에 남은 바이트는 다음 요청을 위해 그대로 남는다
+    */
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<Request, ParseError> {
+        let mut request = Request::read_head(reader)?;
+        request.read_body(reader)?;
+        Ok(request)
+    }
+
+    // 요청 라인과 헤더까지만 읽는다. 본문을 읽기 전에 timeout 등을 바꾸고 싶을 때 read_body()와 나눠 쓴다
+    pub fn read_head<R: BufRead>(reader: &mut R) -> Result<Request, ParseError> {
+        // 요청 라인 앞의 빈 줄은 무시한다 (RFC 7230 3.5)
+        let mut line = match read_line(reader)? {
+            Some(line) => line,
+            None => return Err(ParseError::Eof),
+        };
+        while line.is_empty() {
+            line = read_line(reader)?.ok_or(ParseError::Eof)?;
+        }
+
+        let (method, target, version) = parse_request_line(&line)?;
+        let headers = read_headers(reader)?;
+
+        if version == Version::Http11 && !headers.contains("Host") {
+            return Err(ParseError::Malformed("missing Host header"));
+        }
+
+        let mut request = Request::new(method, &target);
+        request.version = version;
+        request.headers = headers;
+        Ok(request)
+    }
+
+    // read_head()로 읽은 요청의 본문을 헤더(Content-Length, Transfer-Encoding)에 맞게 읽는다
+    pub fn read_body<R: BufRead>(&mut self, reader: &mut R) -> Result<(), ParseError> {
+        if is_chunked(&self.headers)? {
+            let (body, trailers) = read_chunked_body(reader)?;
+            self.body = body;
+            self.trailers = trailers;
+        } else {
+            self.body = read_body(reader, &self.headers)?;
+        }
+        Ok(())
+    }
+
+    /*
+    본문을 미리 읽지 않고 reader를 맡겨 둔다. handler가 body_reader()로 읽는 만큼 reader에서 가져온다
+    서버는 Router::stream_body()로 등록한 route의 요청에 read_body() 대신 이것을 부른다
+    Content-Length가 상한을 넘으면 handler를 부르기 전에 413으로 거절할 수 있게 바로 에러를 돌려준다
+    */
+    pub fn stream_body<R: BufRead + Send + 'static>(&mut self, reader: R) -> Result<(), ParseError> {
+        let framing = if is_chunked(&self.headers)? {
+            Framing::ChunkSize
+        } else {
+            match content_length(&self.headers)? {
+                Some(length) if length > MAX_STREAMED_BODY_LEN => return Err(ParseError::BodyTooLarge),
+                Some(length) => Framing::Length(length),
+                None => Framing::Done,
+            }
+        };
+        self.body_stream = Some(BodyReader::new(Box::new(reader), framing));
+        Ok(())
+    }
+
+    // 본문 길이. 아직 읽지 않은 chunked 본문이면 미리 알 수 없으므로 None
+    pub fn body_len(&self) -> Option<u64> {
+        match &self.body_stream {
+            Some(_) if is_chunked(&self.headers).unwrap_or(false) => None,
+            Some(_) => Some(content_length(&self.headers).ok().flatten().unwrap_or(0)),
+            None => Some(self.body.len() as u64),
+        }
+    }
+
+    // 요청 본문을 읽는다. stream_body()로 받은 요청이면 연결에서 읽어 오고, 아니면 이미 읽어 둔 body를 읽는다
+    pub fn body_reader(&self) -> Box<dyn Read + Send + '_> {
+        match &self.body_stream {
+            Some(stream) => Box::new(stream.clone()),
+            None => Box::new(&self.body[..]),
+        }
+    }
+
+    /*
+    요청을 HTTP/1.1 형식으로 쓴다 (클라이언트용)
+    Content-Length는 본문 길이로 직접 계산해 넣고, 헤더에 있는 Content-Length/Transfer-Encoding은 무시한다
+    */
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_head(writer, Some(self.body.len() as u64))?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+
+    // 요청 줄과 헤더만 쓴다. body_len이 None이면 본문을 chunked로 보낸다고 알린다
+    pub(super) fn write_head<W: Write>(&self, writer: &mut W, body_len: Option<u64>) -> io::Result<()> {
+        let mut head = format!("{} {} {}\r\n", self.method, self.target, self.version);
+        for (name, value) in self.headers.iter() {
+            if name.eq_ignore_ascii_case("Content-Length") || name.eq_ignore_ascii_case("Transfer-Encoding") {
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        // 본문을 가질 수 있는 메소드는 빈 본문이어도 길이를 알려야 서버가 본문을 기다리지 않는다
+        let may_have_body = matches!(self.method, Method::Post | Method::Put | Method::Patch);
+        match body_len {
+            Some(len) if len > 0 || may_have_body => head.push_str(&format!("Content-Length: {}\r\n", len)),
+            Some(_) => {},
+            None => head.push_str("Transfer-Encoding: chunked\r\n"),
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())
+    }
+
+    // 쿼리 스트링을 제외한 경로
+    pub fn path(&self) -> &str {
+        match self.target.find('?') {
+            Some(i) => &self.target[..i],
+            None => &self.target,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    // '?' 뒤의 쿼리 스트링. 쿼리가 없으면 빈 QueryMap
+    pub fn query(&self) -> QueryMap {
+        match self.target.split_once('?') {
+            Some((_, query)) => QueryMap::parse(query.split('#').next().unwrap_or("")),
+            None => QueryMap::new(),
+        }
+    }
+
+    // Content-Type의 본 값(소문자)과 파라미터
+    fn content_type(&self) -> (String, Vec<(String, String)>) {
+        parse_header_params(self.header("Content-Type").unwrap_or(""))
+    }
+
+    // application/x-www-form-urlencoded 본문
+    pub fn form(&self) -> Result<QueryMap, FormError> {
+        if self.content_type().0 != "application/x-www-form-urlencoded" {
+            return Err(FormError::UnsupportedMediaType);
+        }
+        let mut body = Vec::new();
+        self.body_reader().take(MAX_BODY_LEN + 1).read_to_end(&mut body).map_err(|e| body_error(e.into()))?;
+        if body.len() as u64 > MAX_BODY_LEN {
+            return Err(FormError::TooLarge);
+        }
+        let body = std::str::from_utf8(&body).map_err(|_| FormError::Malformed("form is not UTF-8"))?;
+        Ok(QueryMap::parse(body))
+    }
+
+    /*
+    multipart/form-data 본문. 올라온 파일은 dir 아래 임시 파일로 저장된다
+    stream_body()로 받은 요청이면 파일 내용이 메모리를 거치지 않고 연결에서 디스크로 바로 간다
+    */
+    pub fn multipart(&self, dir: &Path) -> Result<Multipart, FormError> {
+        let (mime, params) = self.content_type();
+        if mime != "multipart/form-data" {
+            return Err(FormError::UnsupportedMediaType);
+        }
+        let boundary = params
+            .iter()
+            .find(|(name, _)| name == "boundary")
+            .ok_or(FormError::Malformed("missing multipart boundary"))?;
+        parse_multipart(self.body_reader(), &boundary.1, dir).map_err(body_error)
+    }
+}
+
+// 본문을 읽다 난 에러(연결이 끊김, 시간 초과, 너무 큼)는 임시 파일을 쓰다 난 에러와 구분해서 알맞은 상태 코드로 응답한다
+fn body_error(error: FormError) -> FormError {
+    match error {
+        FormError::Io(e) if e.get_ref().is_some_and(|inner| inner.is::<ParseError>()) => {
+            match e.into_inner().map(|inner| inner.downcast::<ParseError>()) {
+                Some(Ok(parse_error)) => FormError::Body(*parse_error),
+                _ => FormError::Malformed("invalid request body"),
+            }
+        },
+        error => error,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Length(u64),    // 남은 바이트 수
+    ChunkSize,      // 다음 chunk의 크기 줄을 읽을 차례
+    Chunk(u64),     // 지금 chunk에 남은 바이트 수
+    Done,
+}
+
+/*
+handler가 직접 읽는 요청 본문 (Request::body_reader())
+Content-Length만큼, 또는 chunked 인코딩을 풀면서 마지막 chunk까지 읽으면 EOF를 돌려준다. trailer는 버린다
+clone은 같은 본문을 가리키므로 한쪽에서 읽은 만큼 다른 쪽에서도 읽은 것이 된다
+읽다가 난 에러는 ParseError를 담은 io::Error다
+*/
+#[derive(Clone)]
+pub(super) struct BodyReader {
+    state: Arc<Mutex<BodyState>>,
+}
+
+struct BodyState {
+    reader: Box<dyn BufRead + Send>,
+    framing: Framing,
+    read: u64,
+}
+
+impl BodyReader {
+    fn new(reader: Box<dyn BufRead + Send>, framing: Framing) -> BodyReader {
+        BodyReader { state: Arc::new(Mutex::new(BodyState { reader, framing, read: 0 })) }
+    }
+}
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BodyReader")
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.read(buf).map_err(|e| {
+            let kind = match &e {
+                ParseError::Io(e) => e.kind(),
+                _ => io::ErrorKind::InvalidData,
+            };
+            io::Error::new(kind, e)
+        })
+    }
+}
+
+impl BodyState {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ParseError> {
+        loop {
+            match self.framing {
+                Framing::Done => return Ok(0),
+                Framing::Length(0) => self.framing = Framing::Done,
+                Framing::ChunkSize => {
+                    let size = read_chunk_size(&mut self.reader)?;
+                    if size == 0 {
+                        read_headers(&mut self.reader)?;
+                        self.framing = Framing::Done;
+                    } else if size > MAX_STREAMED_BODY_LEN - self.read {
+                        return Err(ParseError::BodyTooLarge);
+                    } else {
+                        self.framing = Framing::Chunk(size);
+                    }
+                },
+                Framing::Chunk(0) => {
+                    let mut crlf = [0; 2];
+                    read_exact(&mut self.reader, &mut crlf)?;
+                    if &crlf != b"\r\n" {
+                        return Err(ParseError::Malformed("missing CRLF after chunk"));
+                    }
+                    self.framing = Framing::ChunkSize;
+                },
+                Framing::Length(remaining) | Framing::Chunk(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+                    let max = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    let n = self.reader.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(ParseError::Malformed("incomplete body"));
+                    }
+                    self.read += n as u64;
+                    let remaining = remaining - n as u64;
+                    self.framing = match self.framing {
+                        Framing::Length(_) => Framing::Length(remaining),
+                        _ => Framing::Chunk(remaining),
+                    };
+                    return Ok(n);
+                },
+            }
+        }
+    }
+}
+
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// CRLF(또는 LF)로 끝나는 한 줄을 읽어 줄바꿈을 뗀 문자열로 돌려준다
+// 아무것도 읽지 못하고 EOF를 만나면 None
+pub(super) fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, ParseError> {
+    let mut buf = Vec::new();
+    let n = reader.by_ref().take(MAX_LINE_LEN as u64 + 1).read_until(b'\n', &mut buf)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if buf.last() != Some(&b'\n') {
+        return Err(if buf.len() > MAX_LINE_LEN {
+            ParseError::HeaderTooLarge
+        } else {
+            ParseError::Malformed("unexpected end of stream")
+        });
+    }
+
+    buf.pop();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|_| ParseError::Malformed("invalid utf-8"))
+}
+
+fn parse_request_line(line: &str) -> Result<(Method, String, Version), ParseError> {
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v), None) => (m, t, v),
+        _ => return Err(ParseError::Malformed("invalid request line")),
+    };
+
+    let method = Method::parse(method).ok_or(ParseError::Malformed("invalid method"))?;
+
+    if target.is_empty() || target.bytes().any(|b| b.is_ascii_control()) {
+        return Err(ParseError::Malformed("invalid request target"));
+    }
+
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        v if v.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+        _ => return Err(ParseError::Malformed("invalid HTTP version")),
+    };
+
+    Ok((method, target.to_string(), version))
+}
+
+pub(super) fn read_headers<R: BufRead>(reader: &mut R) -> Result<Headers, ParseError> {
+    let mut headers = Headers::new();
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::Malformed("unexpected end of stream"))?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(ParseError::HeaderTooLarge);
+        }
+        // 공백으로 시작하는 줄은 이전 헤더를 이어 쓰는 obs-fold인데, 더 이상 허용되지 않는다
+        if line.starts_with(' ') || line.starts_with('\t') {
+            return Err(ParseError::Malformed("obsolete header folding"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::Malformed("header without colon"))?;
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(ParseError::Malformed("invalid header name"));
+        }
+        headers.append(name, value.trim_matches(|c| c == ' ' || c == '\t'));
+    }
+}
+
+/*
+Transfer-Encoding이 있으면 본문 길이는 chunked 형식이 정한다
+  - Content-Length가 함께 오면 프록시와 서버가 본문 끝을 다르게 해석할 수 있으므로 거부한다 (request smuggling)
+  - chunked 말고 다른 coding(gzip 등)은 풀 수 없으므로 501
+*/
+pub(super) fn is_chunked(headers: &Headers) -> Result<bool, ParseError> {
+    if !headers.contains("Transfer-Encoding") {
+        return Ok(false);
+    }
+    if headers.contains("Content-Length") {
+        return Err(ParseError::Malformed("both Transfer-Encoding and Content-Length"));
+    }
+
+    let codings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|v| v.split(','))
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .collect();
+    match codings.as_slice() {
+        [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(true),
+        _ => Err(ParseError::UnsupportedTransferEncoding),
+    }
+}
+
+/*
+chunked 본문을 읽는다
+    <16진수 크기>[;확장]\r\n<데이터>\r\n ... 0\r\n<trailer 헤더들>\r\n
+chunk 크기를 모두 더한 값도 MAX_BODY_LEN을 넘을 수 없다
+*/
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<(Vec<u8>, Headers), ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let size = read_chunk_size(reader)?;
+        if size == 0 {
+            break;
+        }
+        // 크기 줄은 16자리까지 받으므로 더하면 넘칠 수 있다. 남은 여유와 비교한다
+        if size > MAX_BODY_LEN - body.len() as u64 {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        read_exact(reader, &mut body[start..])?;
+        let mut crlf = [0; 2];
+        read_exact(reader, &mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ParseError::Malformed("missing CRLF after chunk"));
+        }
+    }
+
+    let trailers = read_headers(reader)?;
+    Ok((body, trailers))
+}
+
+// chunk 앞의 크기 줄을 읽는다. chunk 확장(;name=value)은 의미를 정의한 것이 없으므로 무시한다
+pub(super) fn read_chunk_size<R: BufRead>(reader: &mut R) -> Result<u64, ParseError> {
+    let line = read_line(reader)?.ok_or(ParseError::Malformed("unexpected end of stream"))?;
+    let size = line.split(';').next().unwrap_or("").trim();
+    if size.is_empty() || size.len() > 16 || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::Malformed("invalid chunk size"));
+    }
+    u64::from_str_radix(size, 16).map_err(|_| ParseError::Malformed("invalid chunk size"))
+}
+
+fn read_exact<R: BufRead>(reader: &mut R, buf: &mut [u8]) -> Result<(), ParseError> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ParseError::Malformed("incomplete body"),
+        _ => ParseError::Io(e),
+    })
+}
+
+fn read_body<R: BufRead>(reader: &mut R, headers: &Headers) -> Result<Vec<u8>, ParseError> {
+    let length = match content_length(headers)? {
+        Some(length) => length,
+        None => return Ok(Vec::new()),
+    };
+    if length > MAX_BODY_LEN {
+        return Err(ParseError::BodyTooLarge);
+    }
+
+    let mut body = vec![0; length as usize];
+    read_exact(reader, &mut body)?;
+    Ok(body)
+}
+
+// Content-Length가 여러 번 오는 경우 값이 모두 같을 때만 받아들인다 (request smuggling 방지)
+pub(super) fn content_length(headers: &Headers) -> Result<Option<u64>, ParseError> {
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::Malformed("invalid Content-Length"));
+        }
+        let value: u64 = value.parse().map_err(|_| ParseError::BodyTooLarge)?;
+        match length {
+            Some(prev) if prev != value => {
+                return Err(ParseError::Malformed("conflicting Content-Length"))
+            }
+            _ => length = Some(value),
+        }
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Request, ParseError> {
+        Request::read_from(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn parses_request_with_headers_and_body() {
+        let request = parse(
+            "POST /users?id=1 HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 13\r\n\r\n{\"name\":\"a\"}\n",
+        )
+        .unwrap();
+
+        assert_eq!(Method::Post, request.method);
+        assert_eq!("/users?id=1", request.target);
+        assert_eq!("/users", request.path());
+        assert_eq!(Version::Http11, request.version);
+        assert_eq!(Some("application/json"), request.header("content-type"));
+        assert_eq!(b"{\"name\":\"a\"}\n".to_vec(), request.body);
+    }
+
+    #[test]
+    fn leaves_following_request_in_reader() {
+        let raw = "GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n";
+        let mut reader = raw.as_bytes();
+
+        assert_eq!("/a", Request::read_from(&mut reader).unwrap().target);
+        assert_eq!("/b", Request::read_from(&mut reader).unwrap().target);
+        assert!(matches!(Request::read_from(&mut reader), Err(ParseError::Eof)));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(400, parse("GET /\r\n\r\n").unwrap_err().status());
+        assert_eq!(400, parse("G(T / HTTP/1.1\r\nHost: x\r\n\r\n").unwrap_err().status());
+        assert_eq!(400, parse("GET / HTTP/1.1\r\n\r\n").unwrap_err().status());
+        assert_eq!(400, parse("GET / HTTP/1.1\r\nHost x\r\n\r\n").unwrap_err().status());
+        assert_eq!(505, parse("GET / HTTP/2.0\r\nHost: x\r\n\r\n").unwrap_err().status());
+    }
+
+    #[test]
+    fn rejects_bad_content_length() {
+        let conflicting = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab";
+        assert_eq!(400, parse(conflicting).unwrap_err().status());
+
+        let short = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nabc";
+        assert_eq!(400, parse(short).unwrap_err().status());
+
+        let huge = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 99999999999\r\n\r\n";
+        assert_eq!(413, parse(huge).unwrap_err().status());
+    }
+
+    #[test]
+    fn decodes_chunked_body_with_trailers() {
+        let raw = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nX-Checksum: 42\r\n\r\n\
+                   GET /next HTTP/1.1\r\nHost: x\r\n\r\n";
+        let mut reader = raw.as_bytes();
+
+        let request = Request::read_from(&mut reader).unwrap();
+        assert_eq!(b"hello, world".to_vec(), request.body);
+        assert_eq!(Some("42"), request.trailers.get("x-checksum"));
+        assert_eq!("/next", Request::read_from(&mut reader).unwrap().target);
+    }
+
+    #[test]
+    fn rejects_bad_transfer_encoding() {
+        let both = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n0\r\n\r\n";
+        assert_eq!(400, parse(both).unwrap_err().status());
+
+        let gzip = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n";
+        assert_eq!(501, parse(gzip).unwrap_err().status());
+
+        let bad_size = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n\r\n";
+        assert_eq!(400, parse(bad_size).unwrap_err().status());
+
+        let no_crlf = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcd\r\n0\r\n\r\n";
+        assert_eq!(400, parse(no_crlf).unwrap_err().status());
+    }
+
+    #[test]
+    fn rejects_oversized_chunk() {
+        let huge = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    3\r\nabc\r\nffffffffffffffff\r\n";
+        assert_eq!(413, parse(huge).unwrap_err().status());
+
+        let total = format!(
+            "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n{:x}\r\n",
+            MAX_BODY_LEN
+        );
+        assert_eq!(413, parse(&total).unwrap_err().status());
+    }
+
+    // 헤더까지만 읽고 나머지는 stream_body()에 넘긴다
+    fn streamed(raw: &str) -> Result<Request, ParseError> {
+        let mut reader = io::Cursor::new(raw.as_bytes().to_vec());
+        let mut request = Request::read_head(&mut reader)?;
+        request.stream_body(reader)?;
+        Ok(request)
+    }
+
+    fn read_streamed(request: &Request) -> Result<String, u16> {
+        let mut body = String::new();
+        match request.body_reader().read_to_string(&mut body) {
+            Ok(_) => Ok(body),
+            Err(e) => Err(e.get_ref().and_then(|e| e.downcast_ref::<ParseError>()).map_or(0, |e| e.status())),
+        }
+    }
+
+    #[test]
+    fn streams_body_on_demand() {
+        let request = streamed("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabcGET /next").unwrap();
+        assert!(request.body.is_empty());
+        assert_eq!(Ok(String::from("abc")), read_streamed(&request));
+        // 다 읽은 뒤에는 EOF
+        assert_eq!(Ok(String::new()), read_streamed(&request));
+
+        let chunked = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                       5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nX-Checksum: 42\r\n\r\n";
+        assert_eq!(Ok(String::from("hello, world")), read_streamed(&streamed(chunked).unwrap()));
+
+        // 본문이 없는 요청은 바로 EOF
+        assert_eq!(Ok(String::new()), read_streamed(&streamed("POST / HTTP/1.1\r\nHost: x\r\n\r\n").unwrap()));
+        // 읽어 둔 본문도 같은 방법으로 읽을 수 있다
+        assert_eq!(Ok(String::from("abc")), read_streamed(&parse("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc").unwrap()));
+    }
+
+    #[test]
+    fn rejects_bad_streamed_body() {
+        // 10 MiB보다 큰 본문도 받지만, 상한을 넘으면 handler를 부르기 전에 413
+        let big = format!("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n", MAX_BODY_LEN + 1);
+        assert!(streamed(&big).is_ok());
+        let huge = format!("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n", MAX_STREAMED_BODY_LEN + 1);
+        assert_eq!(413, streamed(&huge).unwrap_err().status());
+
+        let truncated = streamed("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nabc").unwrap();
+        assert_eq!(Err(400), read_streamed(&truncated));
+        let huge_chunk = streamed("POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\n").unwrap();
+        assert_eq!(Err(413), read_streamed(&huge_chunk));
+        let no_crlf = streamed("POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcd\r\n0\r\n\r\n").unwrap();
+        assert_eq!(Err(400), read_streamed(&no_crlf));
+    }
+
+    #[test]
+    fn rejects_overlong_header_line() {
+        let raw = format!("GET / HTTP/1.1\r\nHost: x\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert_eq!(431, parse(&raw).unwrap_err().status());
+    }
+}
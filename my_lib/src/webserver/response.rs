@@ -0,0 +1,422 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::net::TcpStream;
+
+use super::headers::Headers;
+
+// 상태 코드에 대응하는 reason phrase
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        422 => "Unprocessable Content",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+// 응답 본문을 조금씩 만들어 내는 함수. 길이를 미리 알 수 없는 응답에 쓴다
+pub type StreamFn = Box<dyn FnOnce(&mut ChunkedWriter) -> io::Result<()> + Send>;
+
+/*
+응답 본문
+파일처럼 큰 본문을 String으로 모두 읽어 들이면 메모리를 많이 쓰고 첫 바이트도 늦게 나가므로,
+Reader는 길이만 미리 알려주고 write_to()에서 조금씩 읽어 바로 내보낸다
+Stream은 길이를 모르는 본문으로, Content-Length 대신 chunked 전송 인코딩으로 보낸다
+*/
+pub enum Body {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>, u64),  // 본문을 읽을 reader와 보낼 바이트 수
+    Stream(StreamFn),
+}
+
+impl Body {
+    // Stream은 길이를 미리 알 수 없으므로 0
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::Reader(_, len) => *len,
+            Body::Stream(_) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.is_stream() && self.len() == 0
+    }
+
+    pub fn is_stream(&self) -> bool {
+        matches!(self, Body::Stream(_))
+    }
+
+    // 메모리에 있는 본문이면 그 내용을 돌려준다
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::Reader(..) | Body::Stream(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Body::Reader(_, len) => write!(f, "Reader({} bytes)", len),
+            Body::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+// chunk 하나의 최대 크기. 작은 write()마다 chunk를 만들면 헤더 오버헤드가 커지므로 모아서 보낸다
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/*
+Body::Stream 함수가 본문을 쓰는 writer
+쓴 데이터는 CHUNK_SIZE만큼 모였을 때나 flush()를 부를 때 chunk 하나로 나간다
+trailer()로 넣은 헤더는 본문이 끝난 뒤 마지막 chunk에 붙는다 (예: 본문 전체의 checksum)
+*/
+pub struct ChunkedWriter<'a> {
+    inner: &'a mut dyn Write,
+    buffer: Vec<u8>,
+    trailers: Headers,
+    chunked: bool,  // false면 chunk 형식 없이 그대로 쓴다 (HTTP/1.0 클라이언트용)
+    written: u64,   // chunk 형식을 뺀 본문 바이트 수
+}
+
+impl<'a> ChunkedWriter<'a> {
+    pub(super) fn new(inner: &'a mut dyn Write, chunked: bool) -> ChunkedWriter<'a> {
+        ChunkedWriter { inner, buffer: Vec::with_capacity(CHUNK_SIZE), trailers: Headers::new(), chunked, written: 0 }
+    }
+
+    pub fn trailer(&mut self, name: &str, value: &str) {
+        self.trailers.append(name, value);
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        // 크기가 0인 chunk는 본문의 끝을 뜻하므로 빈 버퍼는 보내지 않는다
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.chunked {
+            write!(self.inner, "{:x}\r\n", self.buffer.len())?;
+            self.inner.write_all(&self.buffer)?;
+            self.inner.write_all(b"\r\n")?;
+        } else {
+            self.inner.write_all(&self.buffer)?;
+        }
+        self.written += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // 남은 데이터와 마지막 chunk, trailer를 보내고 본문 바이트 수를 돌려준다
+    pub(super) fn finish(mut self) -> io::Result<u64> {
+        self.write_chunk()?;
+        if self.chunked {
+            let mut last = String::from("0\r\n");
+            for (name, value) in self.trailers.iter() {
+                last.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            last.push_str("\r\n");
+            self.inner.write_all(last.as_bytes())?;
+        }
+        self.inner.flush()?;
+        Ok(self.written)
+    }
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(n)
+    }
+
+    // 지금까지 쓴 데이터를 바로 클라이언트에게 보낸다 (진행 상황 알림 등)
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.inner.flush()
+    }
+}
+
+/*
+101 Switching Protocols 응답을 보낸 뒤 연결을 넘겨받아 다른 프로토콜(WebSocket 등)로 이야기하는 함수
+인자는 연결과, 서버가 HTTP 요청을 읽으면서 미리 읽어 둔(아직 처리하지 않은) 바이트
+이 함수가 끝나면 연결이 닫힌다
+*/
+pub struct Upgrade {
+    run: Box<dyn FnOnce(TcpStream, Vec<u8>) + Send>,
+    going_away: Option<GoingAway>,
+}
+
+// 서버가 종료될 때 upgrade된 연결의 상대에게 종료를 알리는 함수
+pub(super) type GoingAway = Box<dyn FnOnce() + Send>;
+
+impl Upgrade {
+    pub fn new<F>(upgrade: F) -> Upgrade
+        where F: FnOnce(TcpStream, Vec<u8>) + Send + 'static
+    {
+        Upgrade { run: Box::new(upgrade), going_away: None }
+    }
+
+    /*
+    서버가 종료될 때 upgrade 함수가 아직 돌고 있으면 부른다 (WebSocket이면 close 1001을 보낸다)
+    상대가 drain_timeout 안에 연결을 끝내지 않으면 서버가 소켓을 닫는다
+    */
+    pub fn on_shutdown<F>(mut self, going_away: F) -> Upgrade
+        where F: FnOnce() + Send + 'static
+    {
+        self.going_away = Some(Box::new(going_away));
+        self
+    }
+
+    pub fn run(self, stream: TcpStream, buffered: Vec<u8>) {
+        (self.run)(stream, buffered)
+    }
+
+    pub(super) fn take_going_away(&mut self) -> Option<GoingAway> {
+        self.going_away.take()
+    }
+}
+
+impl fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Upgrade")
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Body,
+    pub upgrade: Option<Upgrade>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::Bytes(Vec::new()),
+            upgrade: None,
+        }
+    }
+
+    pub fn html<B: Into<Vec<u8>>>(status: u16, body: B) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body)
+    }
+
+    pub fn text<B: Into<Vec<u8>>>(status: u16, body: B) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body)
+    }
+
+    // 본문에 상태 코드와 reason phrase만 담은 간단한 응답 (에러 응답용)
+    pub fn status_page(status: u16) -> Response {
+        Response::text(status, format!("{} {}\n", status, reason_phrase(status)))
+    }
+
+    pub fn redirect(status: u16, location: &str) -> Response {
+        Response::status_page(status).with_header("Location", location)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, body: B) -> Response {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    // len 바이트를 reader에서 읽어 보내는 본문
+    pub fn with_reader<R: Read + Send + 'static>(mut self, reader: R, len: u64) -> Response {
+        self.body = Body::Reader(Box::new(reader), len);
+        self
+    }
+
+    /*
+    본문을 조금씩 만들어 보내는 응답
+        Response::text(200, "").with_stream(|writer| {
+            for i in 0..10 {
+                writeln!(writer, "line {}", i)?;
+                writer.flush()?;
+            }
+            writer.trailer("X-Lines", "10");
+            Ok(())
+        })
+    함수가 에러를 돌려주면 본문이 중간에 끊긴 것이므로 연결을 닫는다
+    */
+    pub fn with_stream<F>(mut self, stream: F) -> Response
+        where F: FnOnce(&mut ChunkedWriter) -> io::Result<()> + Send + 'static
+    {
+        self.body = Body::Stream(Box::new(stream));
+        self
+    }
+
+    // 101 응답을 보낸 뒤 연결을 upgrade 함수에 넘긴다
+    pub fn with_upgrade(mut self, upgrade: Upgrade) -> Response {
+        self.upgrade = Some(upgrade);
+        self
+    }
+
+    /*
+    Stream 본문을 끝까지 실행해 메모리에 모은다
+    chunked를 모르는 HTTP/1.0 클라이언트에게는 이렇게 길이를 구한 뒤 보낸다 (trailer는 버려진다)
+    */
+    pub fn buffer_stream(&mut self) -> io::Result<()> {
+        if let Body::Stream(_) = self.body {
+            let stream = match std::mem::replace(&mut self.body, Body::Bytes(Vec::new())) {
+                Body::Stream(stream) => stream,
+                _ => unreachable!(),
+            };
+            let mut bytes = Vec::new();
+            let mut writer = ChunkedWriter::new(&mut bytes, false);
+            stream(&mut writer)?;
+            writer.finish()?;
+            self.body = Body::Bytes(bytes);
+        }
+        Ok(())
+    }
+
+    // 상태 라인과 헤더만 기록한다. HEAD 요청에는 본문 없이 GET과 같은 헤더를 보내야 한다
+    pub fn write_head<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for (name, value) in self.headers.iter() {
+            // 본문 길이를 알리는 헤더는 실제 본문에 맞게 아래에서 직접 채운다
+            if !name.eq_ignore_ascii_case("Content-Length") && !name.eq_ignore_ascii_case("Transfer-Encoding") {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        // 1xx, 204, 304 응답에는 본문이 없으므로 Content-Length도 보내지 않는다
+        if self.may_have_body() {
+            if self.body.is_stream() {
+                head.push_str("Transfer-Encoding: chunked\r\n");
+            } else {
+                head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+            }
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())?;
+        writer.flush()
+    }
+
+    fn may_have_body(&self) -> bool {
+        !(self.status < 200 || self.status == 204 || self.status == 304)
+    }
+
+    // 상태 라인, 헤더, 본문 순서로 기록한다. Content-Length는 본문 길이로 채운다
+    // 보낸 본문 바이트 수를 돌려준다 (access log 등에 사용)
+    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<u64> {
+        self.write_head(writer)?;
+        if !self.may_have_body() {
+            return Ok(0);
+        }
+
+        let written = match self.body {
+            Body::Bytes(bytes) => {
+                writer.write_all(&bytes)?;
+                bytes.len() as u64
+            },
+            Body::Reader(reader, len) => {
+                // io::copy는 내부 버퍼 크기만큼씩 읽고 쓰므로 파일 전체를 메모리에 올리지 않는다
+                let copied = io::copy(&mut reader.take(len), writer)?;
+                if copied != len {
+                    // 이미 Content-Length를 보냈으므로 연결을 끊는 것 말고는 방법이 없다
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "body shorter than Content-Length"));
+                }
+                copied
+            },
+            Body::Stream(stream) => {
+                let mut chunked = ChunkedWriter::new(writer, true);
+                stream(&mut chunked)?;
+                return chunked.finish();
+            },
+        };
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_stream_as_chunks_with_trailers() {
+        let response = Response::text(200, "").with_stream(|writer| {
+            writer.write_all(b"hello")?;
+            writer.flush()?;
+            writer.write_all(b", world")?;
+            writer.trailer("X-Checksum", "42");
+            Ok(())
+        });
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!out.contains("Content-Length"));
+        assert!(out.ends_with("\r\n\r\n5\r\nhello\r\n7\r\n, world\r\n0\r\nX-Checksum: 42\r\n\r\n"));
+    }
+
+    #[test]
+    fn buffers_stream_for_clients_without_chunked() {
+        let mut response = Response::new(200).with_stream(|writer| {
+            writer.write_all(&[b'a'; CHUNK_SIZE + 1])?;
+            writer.trailer("X-Ignored", "1");
+            Ok(())
+        });
+        response.buffer_stream().unwrap();
+
+        assert_eq!(CHUNK_SIZE as u64 + 1, response.body.len());
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("Content-Length: {}\r\n", CHUNK_SIZE + 1)));
+        assert!(!out.contains("X-Ignored"));
+    }
+}
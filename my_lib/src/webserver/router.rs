@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use super::error::{ErrorPage, ErrorPages, HttpError, IntoResponse};
+use super::middleware::Middleware;
+use super::request::{Method, Request};
+use super::response::Response;
+use super::thread_pool::panic_message;
+
+// 경로 패턴에서 뽑아낸 파라미터. "/users/:id"에 "/users/7"이 오면 {"id": "7"}
+pub type Params = HashMap<String, String>;
+
+// 여러 worker 쓰레드에서 동시에 호출되므로 Send + Sync 여야 한다
+// route로 등록하는 함수는 Response나 Result<Response, HttpError>를 돌려주면 되고, 여기에 맞게 감싸서 저장한다
+pub type Handler = Box<dyn Fn(&Request, &Params) -> Result<Response, HttpError> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),    // users
+    Param(String),      // :id
+    Wildcard(String),   // *path (나머지 경로 전체, 패턴의 마지막에만 올 수 있음)
+}
+
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Handler,
+    cache_control: Option<String>,
+    stream_body: bool,
+}
+
+pub struct Router {
+    routes: Vec<Route>,
+    last_added: usize,  // 바로 앞의 등록 호출이 추가한 route 수. any()는 메소드마다 하나씩 추가한다
+    not_found: Handler,
+    middleware: Vec<Box<dyn Middleware>>,
+    error_pages: ErrorPages,
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            last_added: 0,
+            not_found: Box::new(|_, _| Err(HttpError::not_found())),
+            middleware: Vec::new(),
+            error_pages: ErrorPages::new(),
+        }
+    }
+
+    // 먼저 등록한 route가 우선한다
+    pub fn route<F, R>(&mut self, method: Method, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.routes.push(Route {
+            method,
+            segments: parse_pattern(pattern),
+            handler: Box::new(move |request, params| handler(request, params).into_response()),
+            cache_control: None,
+            stream_body: false,
+        });
+        self.last_added = 1;
+        self
+    }
+
+    // 바로 앞의 등록 호출이 추가한 route들. cache_control() 같은 수식 메소드가 쓴다
+    fn last_routes(&mut self, modifier: &str) -> &mut [Route] {
+        assert!(self.last_added > 0, "{}() must follow a route", modifier);
+        let start = self.routes.len() - self.last_added;
+        &mut self.routes[start..]
+    }
+
+    // 바로 앞에 등록한 route의 응답에 붙일 Cache-Control 값
+    // handler가 직접 Cache-Control을 넣었으면 그 값을 그대로 둔다
+    //     router.get("/static/*path", handler).cache_control("public, max-age=86400");
+    pub fn cache_control(&mut self, value: &str) -> &mut Router {
+        for route in self.last_routes("cache_control") {
+            route.cache_control = Some(value.to_string());
+        }
+        self
+    }
+
+    /*
+    바로 앞에 등록한 route는 서버가 본문을 미리 메모리에 읽지 않는다
+    handler가 request.body_reader()나 multipart()로 읽는 만큼 연결에서 가져오므로 큰 파일도 디스크로 바로 저장할 수 있다
+    이런 요청은 본문을 다 읽었는지 알 수 없으므로 응답 뒤에 연결을 닫는다
+        router.post("/upload", handler).stream_body();
+    */
+    pub fn stream_body(&mut self) -> &mut Router {
+        for route in self.last_routes("stream_body") {
+            route.stream_body = true;
+        }
+        self
+    }
+
+    pub fn get<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    // 모든 메소드를 같은 handler로 처리한다 (reverse proxy 등)
+    pub fn any<F, R>(&mut self, pattern: &str, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        let handler = Arc::new(handler);
+        let methods = [Method::Get, Method::Head, Method::Post, Method::Put, Method::Delete, Method::Options, Method::Patch];
+        let count = methods.len();
+        for method in methods {
+            let handler = Arc::clone(&handler);
+            self.route(method, pattern, move |request, params| handler(request, params));
+        }
+        self.last_added = count;
+        self
+    }
+
+    // 어떤 경로와도 맞지 않을 때 호출할 handler
+    pub fn not_found<F, R>(&mut self, handler: F) -> &mut Router
+        where F: Fn(&Request, &Params) -> R + Send + Sync + 'static, R: IntoResponse
+    {
+        self.not_found = Box::new(move |request, params| handler(request, params).into_response());
+        self
+    }
+
+    /*
+    handler가 돌려준 HttpError(또는 404, 405 등 Router가 만든 에러)를 보여줄 페이지
+        router.error_page(404, ErrorPage::file("public/404.html"))
+              .error_page(500, ErrorPage::template("<h1>{status} {reason}</h1>"));
+    */
+    pub fn error_page(&mut self, status: u16, page: ErrorPage) -> &mut Router {
+        self.error_pages.set(status, page);
+        self
+    }
+
+    // error_page()로 등록하지 않은 모든 상태 코드에 쓸 페이지
+    pub fn fallback_error_page(&mut self, page: ErrorPage) -> &mut Router {
+        self.error_pages.set_fallback(page);
+        self
+    }
+
+    // 에러를 이 Router의 에러 페이지로 바꾼다. 서버가 요청을 파싱하지 못했을 때도 쓴다
+    pub fn render_error(&self, error: &HttpError) -> Response {
+        self.error_pages.render(error)
+    }
+
+    // 모든 요청을 감쌀 middleware를 추가한다. 먼저 추가한 것이 바깥쪽에 놓인다
+    pub fn wrap<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Router {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /*
+    middleware를 거쳐 요청을 처리한다. 서버는 이 함수를 호출한다
+    어떤 middleware가 before에서 응답을 돌려주면 그보다 안쪽 단계와 handler는 건너뛰고,
+    이미 before를 통과한 바깥쪽 단계들의 after만 호출된다
+    middleware가 panic해도 handler처럼 500 에러 페이지로 응답한다
+    */
+    pub fn respond(&self, request: &mut Request) -> Response {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.run_middleware(request))) {
+            Ok(response) => response,
+            Err(payload) => {
+                let error = HttpError::internal(&format!("middleware panicked: {}", panic_message(&*payload)));
+                println!("Error handling {} {}: {}", request.method, request.target, error);
+                self.render_error(&error)
+            },
+        }
+    }
+
+    fn run_middleware(&self, request: &mut Request) -> Response {
+        let mut entered = 0;
+        let mut response = None;
+        for middleware in &self.middleware {
+            response = middleware.before(request);
+            if response.is_some() {
+                break;
+            }
+            entered += 1;
+        }
+
+        let mut response = response.unwrap_or_else(|| self.handle(request));
+        for middleware in self.middleware[..entered].iter().rev() {
+            middleware.after(request, &mut response);
+        }
+        response
+    }
+
+    /*
+    경로와 메소드가 모두 맞는 route의 handler를 호출한다 (middleware는 거치지 않는다)
+    handler가 에러를 돌려주거나 panic하면 에러 페이지로 응답한다. panic해도 worker는 죽지 않고 500을 보낸다
+    5xx 에러는 원인을 로그로 남긴다
+    */
+    pub fn handle(&self, request: &Request) -> Response {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.dispatch(request)))
+            .unwrap_or_else(|payload| Err(HttpError::internal(&format!("handler panicked: {}", panic_message(&*payload)))));
+        match result {
+            Ok(response) => response,
+            Err(error) => {
+                if error.status >= 500 {
+                    println!("Error handling {} {}: {}", request.method, request.target, error);
+                }
+                self.render_error(&error)
+            },
+        }
+    }
+
+    // 요청을 처리할 route가 본문을 직접 읽는지. 서버가 본문을 읽기 전에 헤더만 보고 묻는다
+    pub(super) fn streams_body(&self, request: &Request) -> bool {
+        let path = split_path(request.path());
+        self.routes
+            .iter()
+            .filter(|route| route.method == request.method || (request.method == Method::Head && route.method == Method::Get))
+            .find(|route| match_segments(&route.segments, &path).is_some())
+            .is_some_and(|route| route.stream_body)
+    }
+
+    // 경로는 맞지만 메소드가 다르면 405와 함께 허용되는 메소드를 Allow 헤더로 알려준다
+    fn dispatch(&self, request: &Request) -> Result<Response, HttpError> {
+        let path = split_path(request.path());
+        let mut allowed: Vec<&Method> = Vec::new();
+
+        for route in &self.routes {
+            let params = match match_segments(&route.segments, &path) {
+                Some(params) => params,
+                None => continue,
+            };
+            // HEAD는 본문만 빼고 GET과 같은 응답을 돌려주면 되므로 GET route로 처리한다
+            let head_as_get = request.method == Method::Head && route.method == Method::Get;
+            if route.method == request.method || head_as_get {
+                let mut response = (route.handler)(request, &params)?;
+                if let Some(value) = &route.cache_control {
+                    if !response.headers.contains("Cache-Control") {
+                        response.headers.insert("Cache-Control", value);
+                    }
+                }
+                return Ok(response);
+            }
+            if !allowed.contains(&&route.method) {
+                allowed.push(&route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return (self.not_found)(request, &Params::new());
+        }
+
+        let allow: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
+        Err(HttpError::status(405).with_header("Allow", &allow.join(", ")))
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|s| !s.is_empty()).collect()
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let segments: Vec<Segment> = split_path(pattern)
+        .into_iter()
+        .map(|s| {
+            if let Some(name) = s.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = s.strip_prefix('*') {
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(s.to_string())
+            }
+        })
+        .collect();
+
+    // wildcard 뒤의 segment는 절대 매칭될 수 없으므로 등록 시점에 잘못을 알려준다
+    if let Some(i) = segments.iter().position(|s| matches!(s, Segment::Wildcard(_))) {
+        assert!(i == segments.len() - 1, "wildcard must be the last segment: {}", pattern);
+    }
+    segments
+}
+
+fn match_segments(segments: &[Segment], path: &[&str]) -> Option<Params> {
+    let mut params = Params::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Wildcard(name) => {
+                params.insert(name.clone(), path[i.min(path.len())..].join("/"));
+                return Some(params);
+            },
+            Segment::Literal(literal) => {
+                if path.get(i) != Some(&literal.as_str()) {
+                    return None;
+                }
+            },
+            Segment::Param(name) => {
+                params.insert(name.clone(), path.get(i)?.to_string());
+            },
+        }
+    }
+
+    if segments.len() == path.len() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/", |_, _| Response::text(200, "index"))
+            .get("/users/:id", |_, params| Response::text(200, format!("user {}", params["id"])))
+            .delete("/users/:id", |_, _| Response::new(204))
+            .get("/static/*path", |_, params| Response::text(200, params["path"].clone()));
+        router
+    }
+
+    fn body(response: Response) -> String {
+        String::from_utf8(response.body.as_bytes().unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn dispatches_with_params() {
+        let router = router();
+
+        assert_eq!("index", body(router.handle(&Request::new(Method::Get, "/"))));
+        assert_eq!("user 42", body(router.handle(&Request::new(Method::Get, "/users/42?x=1"))));
+        assert_eq!(204, router.handle(&Request::new(Method::Delete, "/users/42")).status);
+    }
+
+    #[test]
+    fn renders_handler_errors_and_panics_as_error_pages() {
+        let mut router = Router::new();
+        router
+            .get("/items/:id", |_, params| {
+                let id: u32 = params["id"].parse().map_err(|_| HttpError::bad_request("id must be a number"))?;
+                Ok(Response::text(200, format!("item {}", id)))
+            })
+            .get("/panic", |_, _| -> Response { panic!("boom") })
+            .error_page(404, ErrorPage::template("<h1>{status}: {reason}</h1>"));
+
+        assert_eq!("item 7", body(router.handle(&Request::new(Method::Get, "/items/7"))));
+        let bad = router.handle(&Request::new(Method::Get, "/items/x"));
+        assert_eq!(400, bad.status);
+        assert_eq!("400 Bad Request\nid must be a number\n", body(bad));
+        assert_eq!("<h1>404: Not Found</h1>", body(router.handle(&Request::new(Method::Get, "/missing"))));
+
+        // panic해도 호출한 쓰레드는 죽지 않고 500을 받는다
+        let panicked = router.handle(&Request::new(Method::Get, "/panic"));
+        assert_eq!(500, panicked.status);
+        assert_eq!("500 Internal Server Error\n", body(panicked));
+    }
+
+    #[test]
+    fn wildcard_captures_rest_of_path() {
+        let router = router();
+
+        assert_eq!("css/site.css", body(router.handle(&Request::new(Method::Get, "/static/css/site.css"))));
+        assert_eq!("", body(router.handle(&Request::new(Method::Get, "/static/"))));
+    }
+
+    #[test]
+    fn reports_not_found_and_method_not_allowed() {
+        let router = router();
+
+        assert_eq!(404, router.handle(&Request::new(Method::Get, "/users/1/posts")).status);
+
+        let response = router.handle(&Request::new(Method::Post, "/users/1"));
+        assert_eq!(405, response.status);
+        assert_eq!(Some("GET, DELETE"), response.headers.get("Allow"));
+    }
+
+    #[test]
+    fn applies_route_cache_control() {
+        let mut router = router();
+        router
+            .get("/cached", |_, _| Response::new(200))
+            .cache_control("public, max-age=60")
+            .get("/custom", |_, _| Response::new(200).with_header("Cache-Control", "no-store"))
+            .cache_control("public, max-age=60");
+
+        let cached = router.handle(&Request::new(Method::Get, "/cached"));
+        assert_eq!(Some("public, max-age=60"), cached.headers.get("Cache-Control"));
+        let custom = router.handle(&Request::new(Method::Get, "/custom"));
+        assert_eq!(Some("no-store"), custom.headers.get("Cache-Control"));
+        assert_eq!(None, router.handle(&Request::new(Method::Get, "/")).headers.get("Cache-Control"));
+    }
+
+    // before/after가 불린 순서를 X-Trace 헤더에 남기는 middleware
+    struct Trace(&'static str, bool);
+
+    impl Middleware for Trace {
+        fn before(&self, request: &mut Request) -> Option<Response> {
+            request.headers.append("X-Trace", self.0);
+            if self.1 {
+                return Some(Response::text(403, "denied"));
+            }
+            None
+        }
+
+        fn after(&self, _request: &Request, response: &mut Response) {
+            response.headers.append("X-Trace", self.0);
+        }
+    }
+
+    fn traces(response: &Response) -> Vec<&str> {
+        response.headers.get_all("X-Trace").collect()
+    }
+
+    #[test]
+    fn runs_middleware_around_handler() {
+        let mut router = Router::new();
+        router
+            .get("/", |request, _| {
+                let seen: Vec<&str> = request.headers.get_all("X-Trace").collect();
+                Response::text(200, seen.join(","))
+            })
+            .wrap(Trace("outer", false))
+            .wrap(Trace("inner", false));
+
+        let response = router.respond(&mut Request::new(Method::Get, "/"));
+        assert_eq!(vec!["inner", "outer"], traces(&response));
+        assert_eq!("outer,inner", body(response));
+    }
+
+    #[test]
+    fn middleware_can_short_circuit() {
+        let mut router = router();
+        router
+            .wrap(Trace("outer", false))
+            .wrap(Trace("auth", true))
+            .wrap(Trace("inner", false));
+
+        let mut request = Request::new(Method::Get, "/");
+        let response = router.respond(&mut request);
+        assert_eq!(403, response.status);
+        assert_eq!(vec!["outer"], traces(&response));
+        assert_eq!(vec!["outer", "auth"], request.headers.get_all("X-Trace").collect::<Vec<_>>());
+    }
+
+    // before나 after에서 panic하는 middleware
+    struct Panics(bool);
+
+    impl Middleware for Panics {
+        fn before(&self, _request: &mut Request) -> Option<Response> {
+            assert!(!self.0, "before failed");
+            None
+        }
+
+        fn after(&self, _request: &Request, _response: &mut Response) {
+            panic!("after failed");
+        }
+    }
+
+    #[test]
+    fn renders_middleware_panics_as_error_pages() {
+        for panics_in_before in [true, false] {
+            let mut router = router();
+            router
+                .fallback_error_page(ErrorPage::template("<h1>{status}</h1>"))
+                .wrap(Trace("outer", false))
+                .wrap(Panics(panics_in_before));
+
+            let response = router.respond(&mut Request::new(Method::Get, "/"));
+            assert_eq!(500, response.status);
+            assert_eq!("<h1>500</h1>", body(response));
+        }
+    }
+
+    #[test]
+    fn reports_routes_that_stream_body() {
+        let mut router = router();
+        router.post("/upload/:name", |_, _| Response::new(204)).stream_body();
+
+        assert!(router.streams_body(&Request::new(Method::Post, "/upload/a.txt")));
+        assert!(!router.streams_body(&Request::new(Method::Put, "/upload/a.txt")));
+        assert!(!router.streams_body(&Request::new(Method::Get, "/users/7")));
+        assert!(!router.streams_body(&Request::new(Method::Post, "/missing")));
+
+        // any()로 등록한 route에는 모든 메소드에 적용된다
+        router.any("/proxy/*path", |_, _| Response::new(204)).stream_body().cache_control("no-store");
+        for method in [Method::Get, Method::Post, Method::Put, Method::Patch] {
+            assert!(router.streams_body(&Request::new(method, "/proxy/a")));
+        }
+        let response = router.handle(&Request::new(Method::Post, "/proxy/a"));
+        assert_eq!(Some("no-store"), response.headers.get("Cache-Control"));
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be the last segment")]
+    fn rejects_wildcard_in_the_middle() {
+        Router::new().get("/static/*path/edit", |_, _| Response::new(200));
+    }
+}
@@ -0,0 +1,1125 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::mem;
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::access_log::{AccessLog, LogEntry};
+use super::error::HttpError;
+use super::rate_limit::{retry_after, TokenBuckets};
+use super::request::{Method, ParseError, Request, Version};
+use super::response::{GoingAway, Response};
+use super::router::{Params, Router};
+use super::signal;
+use super::vhost::VirtualHosts;
+use super::metrics::Metrics;
+use super::{PoolConfig, ThreadPool};
+
+// accept 루프가 종료 요청을 확인하는 주기
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// upgrade된 연결에서 이만큼 아무것도 오지 않으면 끊는다. WebSocket은 이보다 짧게 ping을 보내 연결을 확인한다
+const UPGRADE_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+// stream_body route의 handler가 다 읽지 않고 남긴 본문을 연결을 닫기 전에 이만큼까지 읽어 버린다
+const MAX_DRAIN_LEN: u64 = 1024 * 1024;
+
+// 다른 쓰레드에서 서버를 멈출 때 사용하는 핸들. clone해서 여러 곳에 나눠줄 수 있다
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+pub struct Server {
+    listeners: Vec<TcpListener>,
+    pool: ThreadPool,
+    sites: VirtualHosts,
+    shutdown: ShutdownHandle,
+    drain_timeout: Duration,
+    watch_signals: bool,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    access_log: Option<AccessLog>,
+    timeouts: Timeouts,
+    max_connections_per_ip: Option<usize>,
+    connection_rate: Option<TokenBuckets>,
+    metrics: Arc<Metrics>,
+    max_upgraded: usize,
+}
+
+// 요청을 읽고 응답을 쓰는 데 허용하는 시간
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    header: Duration,   // 요청의 첫 바이트가 온 뒤 헤더를 다 받을 때까지
+    body: Duration,     // 헤더를 받은 뒤 본문을 다 받을 때까지
+    write: Duration,    // 응답을 쓰는 write() 한 번이 끝날 때까지
+}
+
+// 연결을 처리하는 worker들이 함께 보는 설정과 상태
+struct Context {
+    sites: VirtualHosts,
+    shutdown: ShutdownHandle,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    access_log: Option<AccessLog>,
+    timeouts: Timeouts,
+    metrics: Arc<Metrics>,
+    upgraded: Arc<UpgradedConnections>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, pool_size: usize, router: Router) -> io::Result<Server> {
+        Server::bind_with_pool(addr, PoolConfig::new(pool_size), router)
+    }
+
+    /*
+    큐 용량 등 pool 설정을 직접 지정한다. 큐가 가득 차면 새 연결에는 503을 돌려준다
+    router는 기본 사이트로, add_host()로 등록한 어느 호스트에도 맞지 않는 요청을 처리한다
+    */
+    pub fn bind_with_pool<A: ToSocketAddrs>(addr: A, pool: PoolConfig, router: Router) -> io::Result<Server> {
+        Ok(Server {
+            listeners: vec![bind_listener(addr)?],
+            pool: ThreadPool::with_config(pool),
+            sites: VirtualHosts::new(router),
+            shutdown: ShutdownHandle { flag: Arc::new(AtomicBool::new(false)) },
+            drain_timeout: Duration::from_secs(30),
+            watch_signals: false,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            access_log: None,
+            timeouts: Timeouts {
+                header: Duration::from_secs(10),
+                body: Duration::from_secs(30),
+                write: Duration::from_secs(30),
+            },
+            max_connections_per_ip: None,
+            connection_rate: None,
+            metrics: Arc::new(Metrics::new()),
+            max_upgraded: 1024,
+        })
+    }
+
+    /*
+    주소를 하나 더 열어 같은 pool과 사이트들로 처리한다
+        server.listen("0.0.0.0:8080")?;
+    실제로 열린 주소를 돌려준다 (포트 0을 주면 OS가 고른 포트)
+    */
+    pub fn listen<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<SocketAddr> {
+        let listener = bind_listener(addr)?;
+        let local_addr = listener.local_addr()?;
+        self.listeners.push(listener);
+        Ok(local_addr)
+    }
+
+    // 처음 bind한 주소
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners[0].local_addr()
+    }
+
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(|l| l.local_addr()).collect()
+    }
+
+    /*
+    Host 헤더가 pattern에 맞는 요청은 router로 처리한다 (가상 호스트)
+        server.add_host("docs.example.com", docs_router);
+        server.add_host("*.internal.example.com", internal_router);
+        server.add_host("admin.example.com:9090", admin_router);   // 9090 포트로 들어온 연결만
+    사이트마다 문서 루트, route, 에러 페이지를 따로 가진 Router를 쓰면 된다
+    등록한 순서대로 비교하므로 구체적인 패턴을 먼저 등록한다
+    */
+    pub fn add_host(&mut self, pattern: &str, router: Router) {
+        self.sites.add(pattern, router);
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    // 종료 요청 후 처리 중인 요청이 끝나기를 기다리는 최대 시간
+    pub fn set_drain_timeout(&mut self, timeout: Duration) {
+        self.drain_timeout = timeout;
+    }
+
+    /*
+    HTTP/1.1 연결 유지(keep-alive) 설정
+    timeout: 응답을 보낸 뒤 다음 요청을 기다리는 시간. 그 동안 아무 요청이 없으면 연결을 닫는다
+    max_requests: 연결 하나에서 처리할 최대 요청 수. 1이면 매 요청마다 연결을 닫는다
+    */
+    pub fn set_keep_alive(&mut self, timeout: Duration, max_requests: usize) {
+        assert!(max_requests > 0);
+        self.keep_alive_timeout = timeout;
+        self.max_requests = max_requests;
+    }
+
+    /*
+    요청을 읽는 시간 제한
+    header: 요청의 첫 바이트가 온 뒤 헤더를 모두 받을 때까지의 시간
+    body: 헤더를 받은 뒤 본문을 모두 받을 때까지의 시간
+    read()마다 걸리는 timeout이 아니라 전체 기한이므로, 몇 초에 한 바이트씩 보내며 worker를 붙잡는
+    클라이언트(slowloris)도 기한이 지나면 408을 받고 연결이 끊긴다
+    */
+    pub fn set_request_timeouts(&mut self, header: Duration, body: Duration) {
+        self.timeouts.header = header;
+        self.timeouts.body = body;
+    }
+
+    // 응답을 받지 않는 클라이언트 때문에 worker가 write()에서 멈춰 있지 않게 한다
+    pub fn set_write_timeout(&mut self, timeout: Duration) {
+        self.timeouts.write = timeout;
+    }
+
+    // 클라이언트 IP 하나가 동시에 열 수 있는 연결 수. 넘으면 요청을 읽지 않고 429로 응답한다
+    pub fn set_max_connections_per_ip(&mut self, max: usize) {
+        assert!(max > 0);
+        self.max_connections_per_ip = Some(max);
+    }
+
+    /*
+    클라이언트 IP 하나가 새 연결을 여는 속도. 초당 per_second개씩 차는 token bucket으로 세고, burst개까지는 한 번에 받는다
+    넘으면 pool에 넘기지 않고 429와 Retry-After로 응답한다. 요청 단위로 제한하려면 RateLimit middleware를 쓴다
+    */
+    pub fn set_connection_rate_per_ip(&mut self, per_second: f64, burst: u32) {
+        self.connection_rate = Some(TokenBuckets::new(per_second, burst));
+    }
+
+    /*
+    pool 상태와 요청 통계를 Prometheus text 형식으로 응답하는 handler. 통계는 등록하지 않아도 늘 모은다
+    보여줄 사이트의 Router에 route로 등록하므로 그 사이트의 Host에서만 응답하고 middleware(RateLimit 등)도 거친다
+        status.get("/metrics", server.metrics_handler());
+        server.add_host("status.example.com", status);
+    */
+    pub fn metrics_handler(&self) -> impl Fn(&Request, &Params) -> Response + Send + Sync + 'static {
+        let metrics = Arc::clone(&self.metrics);
+        let pool = self.pool.monitor();
+        move |_, _| metrics.response(&pool.stats())
+    }
+
+    /*
+    동시에 열어 둘 수 있는 upgrade된 연결(WebSocket 등)의 수. 기본은 1024
+    upgrade된 연결은 worker 대신 연결마다 쓰레드를 하나씩 쓰므로, 넘으면 101 대신 503으로 응답한다
+    */
+    pub fn set_max_upgraded_connections(&mut self, max: usize) {
+        assert!(max > 0);
+        self.max_upgraded = max;
+    }
+
+    // 요청마다 한 줄씩 access log를 남긴다. 설정하지 않으면 기록하지 않는다
+    pub fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(access_log);
+    }
+
+    // SIGINT/SIGTERM을 받으면 shutdown()을 호출한 것과 같이 종료한다
+    pub fn handle_signals(&mut self) {
+        signal::install();
+        self.watch_signals = true;
+    }
+
+    /*
+    종료 요청이 올 때까지 연결을 받아 pool에 넘긴다
+    종료 요청이 오면 listener를 닫아 새 연결을 거절하고,
+    이미 받은 연결은 drain_timeout 안에서 마저 처리한 뒤 worker들을 종료시킨다
+    upgrade된 연결에는 종료를 알리고(WebSocket은 close 1001) 같은 기한 안에 끝나지 않으면 소켓을 닫는다
+    */
+    pub fn run(self) {
+        let Server {
+            listeners, pool, sites, shutdown, drain_timeout, watch_signals, keep_alive_timeout, max_requests, access_log,
+            timeouts, max_connections_per_ip, connection_rate, metrics, max_upgraded,
+        } = self;
+        let context = Arc::new(Context {
+            sites, shutdown: shutdown.clone(), keep_alive_timeout, max_requests, access_log, timeouts, metrics,
+            upgraded: Arc::new(UpgradedConnections::new(max_upgraded)),
+        });
+        let limiter = max_connections_per_ip.map(|max| Arc::new(ConnectionLimiter::new(max)));
+
+        while !shutdown.is_shutdown() {
+            if watch_signals && signal::received() {
+                println!("Received shutdown signal.");
+                // keep-alive 중인 연결들도 종료 요청을 볼 수 있도록 플래그를 세운다
+                shutdown.shutdown();
+                break;
+            }
+
+            // listener들을 차례로 확인하고, 어디에도 새 연결이 없을 때만 잠시 쉰다
+            let mut idle = true;
+            for listener in &listeners {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        idle = false;
+                        dispatch(stream, addr, &pool, &context, limiter.as_ref(), connection_rate.as_ref());
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                    Err(e) => println!("Failed to accept connection: {}", e),
+                }
+            }
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        drop(listeners);
+        println!("Shutting down. Waiting up to {:?} for in-flight requests.", drain_timeout);
+        let deadline = Instant::now() + drain_timeout;
+        context.upgraded.going_away();
+        pool.shutdown_timeout(drain_timeout);
+        context.upgraded.close_remaining(deadline);
+    }
+}
+
+fn bind_listener<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr)?;
+    // accept()에서 영원히 block되지 않고 주기적으로 종료 요청을 확인하기 위해 non-blocking으로 설정
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+// 받은 연결을 pool에 넘긴다. 여유가 없으면 요청을 읽지 않고 바로 거절한다
+fn dispatch(
+    stream: TcpStream,
+    addr: SocketAddr,
+    pool: &ThreadPool,
+    context: &Arc<Context>,
+    limiter: Option<&Arc<ConnectionLimiter>>,
+    connection_rate: Option<&TokenBuckets>,
+) {
+    if let Err(e) = stream.set_nonblocking(false) {
+        println!("Failed to configure connection: {}", e);
+        return;
+    }
+    context.metrics.record_connection();
+    if let Some(Err(wait)) = connection_rate.map(|buckets| buckets.take(&addr.ip().to_string())) {
+        context.metrics.record_rejected(429);
+        reject(&stream, 429, &retry_after(wait), "Connection rate limit exceeded");
+        return;
+    }
+    // 연결이 끝나 slot이 drop될 때 IP별 연결 수가 줄어든다
+    let slot = match limiter {
+        Some(limiter) => match ConnectionLimiter::acquire(limiter, addr.ip()) {
+            Some(slot) => Some(slot),
+            None => {
+                context.metrics.record_rejected(429);
+                reject(&stream, 429, "1", "Too many connections from one client");
+                return;
+            },
+        },
+        None => None,
+    };
+    // pool이 Job을 거절하면 Job과 함께 stream도 버려지므로, 503을 보낼 수 있도록 Arc로 나눠 갖는다
+    let stream = Arc::new(stream);
+    let job_stream = Arc::clone(&stream);
+    let job_context = Arc::clone(context);
+    let queued = pool.try_execute(move || handle_connection(&job_stream, &job_context, slot));
+    if queued.is_err() {
+        context.metrics.record_rejected(503);
+        reject(&stream, 503, "1", "Job queue full");
+    }
+}
+
+/*
+IP별 동시 연결 수를 센다
+한 클라이언트가 연결을 잔뜩 열어 worker를 모두 차지하지 못하게 막는다
+*/
+struct ConnectionLimiter {
+    max: usize,
+    counts: Mutex<HashMap<IpAddr, usize>>,
+}
+
+// 연결 하나가 차지한 자리. drop되면 자리를 돌려준다
+struct ConnectionSlot {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    fn new(max: usize) -> ConnectionLimiter {
+        ConnectionLimiter { max, counts: Mutex::new(HashMap::new()) }
+    }
+
+    fn acquire(limiter: &Arc<ConnectionLimiter>, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut counts = limiter.counts.lock().unwrap();
+        let count = counts.entry(ip).or_insert(0);
+        if *count >= limiter.max {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionSlot { limiter: Arc::clone(limiter), ip })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.limiter.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            // 연결이 없는 IP는 지워서 map이 계속 커지지 않게 한다
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/*
+upgrade되어 worker 밖의 쓰레드에서 처리 중인 연결들
+개수를 제한하고, 서버가 종료될 때 상대에게 종료를 알린 뒤 끝나지 않은 연결의 소켓을 닫는다
+*/
+struct UpgradedConnections {
+    max: usize,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Upgraded>>,
+}
+
+struct Upgraded {
+    stream: TcpStream,
+    going_away: Option<GoingAway>,
+}
+
+// upgrade된 연결 하나의 등록. 연결을 처리하는 쓰레드가 갖고 있다가 끝나면 drop되어 목록에서 빠진다
+struct UpgradeSlot {
+    connections: Arc<UpgradedConnections>,
+    id: u64,
+}
+
+impl UpgradedConnections {
+    fn new(max: usize) -> UpgradedConnections {
+        UpgradedConnections { max, next_id: AtomicU64::new(0), connections: Mutex::new(HashMap::new()) }
+    }
+
+    // 자리가 없으면 None. stream은 종료할 때 닫기 위해 clone해 둔 것이다
+    fn register(upgraded: &Arc<UpgradedConnections>, stream: TcpStream, going_away: Option<GoingAway>) -> Option<UpgradeSlot> {
+        let mut connections = upgraded.connections.lock().unwrap();
+        if connections.len() >= upgraded.max {
+            return None;
+        }
+        let id = upgraded.next_id.fetch_add(1, Ordering::SeqCst);
+        connections.insert(id, Upgraded { stream, going_away });
+        Some(UpgradeSlot { connections: Arc::clone(upgraded), id })
+    }
+
+    // 열려 있는 연결마다 종료 hook을 부른다. hook이 상대에게 쓰다가 멈출 수 있으므로 lock 밖에서 부른다
+    fn going_away(&self) {
+        let hooks: Vec<GoingAway> = {
+            let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+            connections.values_mut().filter_map(|c| c.going_away.take()).collect()
+        };
+        for going_away in hooks {
+            going_away();
+        }
+    }
+
+    // deadline까지 연결들이 끝나기를 기다리고, 남은 연결은 소켓을 닫아 upgrade 함수의 read가 끝나게 한다
+    fn close_remaining(&self, deadline: Instant) {
+        while Instant::now() < deadline && !self.connections.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        if !connections.is_empty() {
+            println!("Closing {} upgraded connection(s).", connections.len());
+        }
+        for connection in connections.values() {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for UpgradeSlot {
+    fn drop(&mut self) {
+        self.connections.connections.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.id);
+    }
+}
+
+/*
+기한(deadline)이 있는 읽기
+TcpStream의 read timeout은 read() 한 번에만 걸리므로, 매번 남은 시간으로 다시 설정해서
+전체 기한이 지나면 데이터가 조금씩 오고 있더라도 TimedOut 에러를 낸다
+handler가 읽는 본문(stream_body)에 넘길 수 있도록 clone한 stream을 직접 갖는다
+*/
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+    idle: Option<Duration>, // 있으면 읽을 때마다 기한을 지금부터 idle 뒤로 미룬다 (크기를 알 수 없는 긴 업로드)
+}
+
+impl DeadlineReader {
+    fn new(stream: &TcpStream) -> io::Result<DeadlineReader> {
+        Ok(DeadlineReader { stream: stream.try_clone()?, deadline: Instant::now(), idle: None })
+    }
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request deadline exceeded"));
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        let n = self.stream.read(buf)?;
+        if let Some(idle) = self.idle {
+            self.deadline = Instant::now() + idle;
+        }
+        Ok(n)
+    }
+}
+
+/*
+연결 하나에서 요청을 차례로 읽어 응답한다
+BufReader가 한 번에 읽어 둔 바이트 중 다음 요청(pipelining)이 남아 있으면 추가로 읽지 않고 바로 처리되고,
+없으면 keep_alive_timeout 동안 다음 요청을 기다린다
+첫 바이트가 오면 그때부터 헤더와 본문에 각각 정해진 기한 안에 요청을 다 받아야 한다
+*/
+fn handle_connection(stream: &TcpStream, context: &Context, slot: Option<ConnectionSlot>) {
+    // 요청을 줄 단위로 읽기 위해 BufReader로 감싼다. 응답은 stream에 바로 쓴다
+    let mut reader = match DeadlineReader::new(stream) {
+        Ok(reader) => BufReader::new(reader),
+        Err(e) => {
+            println!("Failed to configure connection: {}", e);
+            return;
+        },
+    };
+    let mut served = 0;
+    let remote_addr = stream.peer_addr().ok();
+    // 가상 호스트 패턴의 포트는 연결을 받은 listener의 포트와 비교한다
+    let local_port = stream.local_addr().ok().map(|a| a.port());
+
+    if let Err(e) = stream.set_write_timeout(Some(context.timeouts.write)) {
+        println!("Failed to configure connection: {}", e);
+        return;
+    }
+
+    loop {
+        // 다음 요청의 첫 바이트를 기다린다. 그 동안 아무것도 오지 않으면 조용히 연결을 닫는다
+        reader.get_mut().deadline = Instant::now() + context.keep_alive_timeout;
+        match reader.fill_buf() {
+            Ok([]) => return,
+            Ok(_) => {},
+            Err(ref e) if is_timeout(e) => return,
+            Err(e) => {
+                println!("Failed to read request: {}", e);
+                return;
+            },
+        }
+
+        reader.get_mut().deadline = Instant::now() + context.timeouts.header;
+        let read = Request::read_head(&mut reader).and_then(|mut request| {
+            reader.get_mut().deadline = Instant::now() + context.timeouts.body;
+            if context.sites.streams_body(&request, local_port) {
+                // 본문은 handler가 읽는다. 남은 바이트와 함께 reader를 통째로 넘기므로 이 요청을 끝으로 연결을 닫는다
+                // 업로드가 길어질 수 있어 body timeout은 전체 기한 대신 읽기 사이의 간격에 건다
+                reader.get_mut().idle = Some(context.timeouts.body);
+                let rest = BufReader::new(DeadlineReader::new(stream)?);
+                request.stream_body(mem::replace(&mut reader, rest))?;
+            } else {
+                request.read_body(&mut reader)?;
+            }
+            Ok(request)
+        });
+        let mut request = match read {
+            Ok(request) => request,
+            Err(ParseError::Eof) => return,
+            Err(e) => {
+                println!("Rejecting request: {}", e);
+                let started = Instant::now();
+                // Host를 알기 전이므로 기본 사이트의 에러 페이지를 쓴다
+                let response = context.sites.default_site().render_error(&HttpError::from(e)).with_header("Connection", "close");
+                let status = response.status;
+                let bytes = response.write_to(&mut &*stream).unwrap_or(0);
+                record_request(context, remote_addr, None, status, bytes, started);
+                return;
+            },
+        };
+        request.remote_addr = remote_addr;
+        let started = Instant::now();
+        served += 1;
+
+        let keep_alive = wants_keep_alive(&request)
+            && request.body_stream.is_none()
+            && served < context.max_requests
+            && !context.shutdown.is_shutdown();
+
+        let mut response = context.sites.respond(&mut request, local_port);
+        if response.status == 101 && response.upgrade.is_some() {
+            hand_over(reader, stream, context, slot, &request, response, started);
+            return;
+        }
+        // HTTP/1.0은 chunked를 모르므로 길이를 알 수 있게 본문을 먼저 모두 만든다
+        if request.version == Version::Http10 {
+            if let Err(e) = response.buffer_stream() {
+                println!("Failed to generate response: {}", e);
+                response = context.sites.default_site().render_error(&HttpError::status(500));
+            }
+        }
+        if keep_alive {
+            response.headers.insert("Connection", "keep-alive");
+            let remaining = context.max_requests - served;
+            // 초 단위로만 알릴 수 있으므로 1초 미만이면 0(바로 닫힘) 대신 1로 알린다
+            let seconds = context.keep_alive_timeout.as_secs().max(1);
+            let hint = format!("timeout={}, max={}", seconds, remaining);
+            response.headers.insert("Keep-Alive", &hint);
+        } else {
+            response.headers.insert("Connection", "close");
+        }
+
+        let status = response.status;
+        let written = if request.method == Method::Head {
+            response.write_head(&mut &*stream).map(|_| 0)
+        } else {
+            response.write_to(&mut &*stream)
+        };
+        let bytes = match written {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Failed to write response: {}", e);
+                record_request(context, remote_addr, Some(&request), status, 0, started);
+                return;
+            },
+        };
+        record_request(context, remote_addr, Some(&request), status, bytes, started);
+        if !keep_alive {
+            // handler가 읽지 않은 본문이 남은 채로 닫으면 RST가 가서 클라이언트가 응답을 못 받을 수 있으므로 조금은 비운다
+            if let Some(body) = request.body_stream.take() {
+                let _ = io::copy(&mut body.take(MAX_DRAIN_LEN), &mut io::sink());
+            }
+            return;
+        }
+    }
+}
+
+/*
+101 응답을 보내고 연결을 upgrade 함수에 넘긴다
+BufReader에 남아 있는 바이트는 이미 새 프로토콜의 데이터이므로 함께 넘겨준다
+upgrade된 연결은 오래 유지되므로 별도의 쓰레드에서 처리하고 worker는 바로 pool로 돌아간다
+(worker가 맡으면 열어 두기만 한 연결 몇 개가 pool을 다 차지하고, 종료할 때도 drain_timeout을 다 기다리게 된다)
+IP별 연결 수 slot은 그 쓰레드가 가져가므로 upgrade된 연결도 계속 센다
+upgrade된 연결이 이미 최대 개수만큼 있거나 종료 중이면 101 대신 503으로 응답한다
+*/
+fn hand_over(
+    reader: BufReader<DeadlineReader>,
+    stream: &TcpStream,
+    context: &Context,
+    slot: Option<ConnectionSlot>,
+    request: &Request,
+    mut response: Response,
+    started: Instant,
+) {
+    let mut upgrade = match response.upgrade.take() {
+        Some(upgrade) => upgrade,
+        None => return,
+    };
+    let tracked = match stream.try_clone() {
+        Ok(tracked) => tracked,
+        Err(e) => {
+            println!("Failed to upgrade connection: {}", e);
+            return;
+        },
+    };
+    let registered = if context.shutdown.is_shutdown() {
+        None
+    } else {
+        UpgradedConnections::register(&context.upgraded, tracked, upgrade.take_going_away())
+    };
+    let registration = match registered {
+        Some(registration) => registration,
+        None => {
+            println!("Cannot take more upgraded connections; rejecting upgrade with 503.");
+            let response = context.sites.default_site().render_error(&HttpError::status(503))
+                .with_header("Retry-After", "1")
+                .with_header("Connection", "close");
+            let bytes = response.write_to(&mut &*stream).unwrap_or(0);
+            record_request(context, request.remote_addr, Some(request), 503, bytes, started);
+            return;
+        },
+    };
+
+    let buffered = reader.buffer().to_vec();
+    let written = response.write_head(&mut &*stream);
+    record_request(context, request.remote_addr, Some(request), 101, 0, started);
+    if let Err(e) = written {
+        println!("Failed to write response: {}", e);
+        return;
+    }
+
+    let spawned = stream.set_read_timeout(Some(UPGRADE_IDLE_TIMEOUT)).and_then(|_| stream.try_clone()).and_then(|upgraded| {
+        thread::Builder::new().name(String::from("upgraded connection")).spawn(move || {
+            let _slot = slot;
+            let _registration = registration;
+            upgrade.run(upgraded, buffered);
+        })
+    });
+    // 쓰레드를 만들 수 없으면 이미 101을 보냈으므로 연결을 닫는 수밖에 없다
+    if let Err(e) = spawned {
+        println!("Failed to upgrade connection: {}", e);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+// access log과 metrics에 요청 하나를 기록한다
+fn record_request(
+    context: &Context,
+    remote_addr: Option<SocketAddr>,
+    request: Option<&Request>,
+    status: u16,
+    bytes: u64,
+    started: Instant,
+) {
+    let duration = started.elapsed();
+    context.metrics.record_request(status, bytes, duration);
+    if let Some(access_log) = &context.access_log {
+        access_log.log(&LogEntry {
+            remote_addr,
+            request,
+            status,
+            bytes,
+            duration,
+            time: SystemTime::now(),
+        });
+    }
+}
+
+// HTTP/1.1은 기본이 keep-alive, HTTP/1.0은 명시적으로 요청했을 때만 연결을 유지한다
+fn wants_keep_alive(request: &Request) -> bool {
+    match request.version {
+        Version::Http11 => !request.headers.has_token("Connection", "close"),
+        Version::Http10 => request.headers.has_token("Connection", "keep-alive"),
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+// 처리할 여유가 없을 때 요청을 읽지 않고 바로 503(또는 429)으로 응답한다
+// accept 루프에서 호출되므로 느린 클라이언트 때문에 루프가 멈추지 않게 쓰기 timeout을 짧게 둔다
+fn reject(stream: &TcpStream, status: u16, retry_after: &str, reason: &str) {
+    println!("{}; rejecting connection with {}.", reason, status);
+    let response = Response::status_page(status)
+        .with_header("Retry-After", retry_after)
+        .with_header("Connection", "close");
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+    let _ = response.write_to(&mut &*stream);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{RateLimit, RateLimitKey};
+
+    #[test]
+    fn serves_until_shutdown_and_drains() {
+        let mut router = Router::new();
+        router.get("/slow", |_, _| {
+            thread::sleep(Duration::from_millis(200));
+            Response::text(200, "done")
+        });
+
+        let server = Server::bind("127.0.0.1:0", 2, router).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /slow HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+        // 요청이 처리되는 도중에 종료를 요청해도 응답은 끝까지 전달되어야 한다
+        thread::sleep(Duration::from_millis(50));
+        handle.shutdown();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("done"));
+
+        running.join().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn sheds_load_with_503_when_queue_is_full() {
+        let (release, blocker) = std::sync::mpsc::channel::<()>();
+        let blocker = std::sync::Mutex::new(blocker);
+        let mut router = Router::new();
+        router.get("/block", move |_, _| {
+            blocker.lock().unwrap().recv().unwrap();
+            Response::text(200, "released")
+        });
+
+        let mut pool = PoolConfig::new(1);
+        pool.queue_capacity = Some(1);
+        pool.overflow = crate::webserver::OverflowPolicy::Reject;
+        let server = Server::bind_with_pool("127.0.0.1:0", pool, router).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let request = b"GET /block HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n";
+        // 첫 연결은 worker를 붙잡고, 두번째는 큐에서 기다리고, 세번째는 거절된다
+        let mut streams = Vec::new();
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request).unwrap();
+            streams.push(stream);
+            thread::sleep(Duration::from_millis(50));
+        }
+        let mut rejected = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        rejected.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains("Retry-After: 1\r\n"));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        for mut stream in streams {
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.ends_with("released"));
+        }
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    fn start(router: Router, keep_alive: Duration, max_requests: usize) -> (SocketAddr, ShutdownHandle, thread::JoinHandle<()>) {
+        let mut server = Server::bind("127.0.0.1:0", 2, router).unwrap();
+        server.set_keep_alive(keep_alive, max_requests);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        (addr, handle, thread::spawn(move || server.run()))
+    }
+
+    fn echo_router() -> Router {
+        let mut router = Router::new();
+        router.get("/:name", |_, params| Response::text(200, params["name"].clone()));
+        router
+    }
+
+    // 연결에서 응답 하나를 읽는다 (Content-Length만큼 본문을 읽음)
+    fn read_response(reader: &mut BufReader<TcpStream>) -> (String, String) {
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let length: usize = head
+            .lines()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (head, String::from_utf8(body).unwrap())
+    }
+
+    #[test]
+    fn keeps_connection_alive_and_handles_pipelined_requests() {
+        let (addr, handle, running) = start(echo_router(), Duration::from_secs(5), 100);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        stream.write_all(b"GET /one HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+        let (head, body) = read_response(&mut reader);
+        assert!(head.contains("Connection: keep-alive\r\n"));
+        assert_eq!("one", body);
+
+        // 두 요청을 한 번에 보내도 순서대로 응답이 와야 한다
+        stream.write_all(b"GET /two HTTP/1.1\r\nHost: test\r\n\r\nGET /three HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+        assert_eq!("two", read_response(&mut reader).1);
+        let (head, body) = read_response(&mut reader);
+        assert!(head.contains("Connection: close\r\n"));
+        assert_eq!("three", body);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    // 테스트에서 access log 내용을 읽을 수 있도록 버퍼를 공유하는 sink
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn serves_virtual_hosts_on_several_listeners() {
+        let mut docs = Router::new();
+        docs.get("/:name", |_, params| Response::text(200, format!("docs {}", params["name"])));
+        let mut admin = Router::new();
+        admin.get("/:name", |_, params| Response::text(200, format!("admin {}", params["name"])));
+
+        let mut server = Server::bind("127.0.0.1:0", 2, echo_router()).unwrap();
+        let second = server.listen("127.0.0.1:0").unwrap();
+        server.add_host(&format!("admin.test:{}", second.port()), admin);
+        server.add_host("docs.test", docs);
+        let first = server.local_addr().unwrap();
+        assert_eq!(vec![first, second], server.local_addrs().unwrap());
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let get = |addr: SocketAddr, host: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET /page HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", host).unwrap();
+            read_response(&mut BufReader::new(stream)).1
+        };
+        assert_eq!("docs page", get(first, "docs.test"));
+        assert_eq!("docs page", get(second, "DOCS.test:1234"));
+        assert_eq!("admin page", get(second, "admin.test"));
+        // admin 사이트는 두 번째 listener에서만 보인다
+        assert_eq!("page", get(first, "admin.test"));
+        assert_eq!("page", get(first, "other.test"));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn writes_access_log_for_each_request() {
+        let buffer = SharedBuffer::default();
+        let mut server = Server::bind("127.0.0.1:0", 2, echo_router()).unwrap();
+        server.set_access_log(AccessLog::new(crate::webserver::LogFormat::Common, buffer.clone()));
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\nBROKEN\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        handle.shutdown();
+        running.join().unwrap();
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("127.0.0.1 - - ["));
+        assert!(lines[0].contains("] \"GET /hello HTTP/1.1\" 200 5 "));
+        assert!(lines[1].contains("] \"-\" 400 "));
+    }
+
+    #[test]
+    fn times_out_slow_requests_with_408() {
+        let mut server = Server::bind("127.0.0.1:0", 2, echo_router()).unwrap();
+        server.set_request_timeouts(Duration::from_millis(300), Duration::from_millis(300));
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        // 헤더를 한 바이트씩 천천히 보내면 read()마다의 timeout에는 걸리지 않지만 전체 기한에 걸린다
+        let mut slow = TcpStream::connect(addr).unwrap();
+        let started = Instant::now();
+        for byte in b"GET /slow HTTP/1.1\r\nHost: test\r\nX-Padding: aaaaaaaaaaaaaaaaaaaa".iter() {
+            if slow.write_all(&[*byte]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let mut response = String::new();
+        let _ = slow.read_to_string(&mut response);
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // 본문을 약속한 만큼 보내지 않아도 마찬가지
+        let mut partial = TcpStream::connect(addr).unwrap();
+        partial.write_all(b"POST /body HTTP/1.1\r\nHost: test\r\nContent-Length: 10\r\n\r\nabc").unwrap();
+        let mut response = String::new();
+        partial.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn limits_concurrent_connections_per_ip() {
+        let mut server = Server::bind("127.0.0.1:0", 4, echo_router()).unwrap();
+        server.set_max_connections_per_ip(2);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let first = TcpStream::connect(addr).unwrap();
+        let second = TcpStream::connect(addr).unwrap();
+        let mut third = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        third.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+
+        // 연결을 닫으면 자리가 비어 다시 받아준다
+        drop(first);
+        drop(second);
+        thread::sleep(Duration::from_millis(100));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /again HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("again"));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn limits_connection_rate_per_ip() {
+        let mut server = Server::bind("127.0.0.1:0", 2, echo_router()).unwrap();
+        server.set_connection_rate_per_ip(0.5, 2);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let get = || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET /hi HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        assert!(get().ends_with("hi"));
+        assert!(get().ends_with("hi"));
+        // 거절된 연결은 요청을 읽지 않고 바로 응답한 뒤 닫힌다
+        let mut rejected = String::new();
+        TcpStream::connect(addr).unwrap().read_to_string(&mut rejected).unwrap();
+        assert!(rejected.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+        assert!(rejected.contains("Retry-After: 2\r\n"));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn exposes_metrics() {
+        let mut server = Server::bind("127.0.0.1:0", 2, echo_router()).unwrap();
+        // metrics는 status 사이트에만 등록한다. 그 사이트의 middleware도 거친다
+        let mut status = Router::new();
+        status
+            .get("/metrics", server.metrics_handler())
+            .wrap(RateLimit::new(RateLimitKey::Ip, 0.001, 1));
+        server.add_host("status.test", status);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let get = |host: &str, path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host).unwrap();
+            read_response(&mut BufReader::new(stream))
+        };
+        assert_eq!("one", get("test", "/one").1);
+        // 다른 호스트에서는 기본 사이트의 route가 처리한다
+        assert_eq!("metrics", get("test", "/metrics").1);
+
+        let (head, text) = get("status.test", "/metrics");
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n"), "{}", head);
+        // 지금 /metrics를 처리하는 worker 하나가 바쁘다
+        assert!(text.contains("\nwebserver_pool_workers 2\n"));
+        assert!(text.contains("\nwebserver_pool_busy_workers 1\n"));
+        assert!(text.contains("\nwebserver_requests_total{status=\"200\"} 2\n"));
+        assert!(text.contains("\nwebserver_response_bytes_total 10\n"));
+        assert!(text.contains("\nwebserver_request_duration_seconds_count 2\n"));
+        assert!(get("status.test", "/metrics").0.starts_with("HTTP/1.1 429 "));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn streams_large_uploads_to_disk() {
+        let mut router = Router::new();
+        router
+            .post("/upload", |request, _| {
+                let form = request.multipart(&std::env::temp_dir())?;
+                let file = form.file("data").ok_or(HttpError::bad_request("missing file"))?;
+                Ok(Response::text(200, format!("{} {}", file.filename, file.size)))
+            })
+            .stream_body()
+            .post("/buffered", |request, _| Response::text(200, request.body.len().to_string()));
+        let (addr, handle, running) = start(router, Duration::from_secs(5), 100);
+
+        // 메모리로 읽는 본문의 상한(10 MiB)보다 큰 파일
+        let size = 11 * 1024 * 1024;
+        let upload = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let head = format!(
+                "POST {} HTTP/1.1\r\nHost: test\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}\r\n\r\n",
+                path,
+                size + 150
+            );
+            let mut body = b"--XyZ\r\nContent-Disposition: form-data; name=\"data\"; filename=\"big.bin\"\r\n\r\n".to_vec();
+            body.resize(body.len() + size, b'x');
+            body.extend_from_slice(b"\r\n--XyZ--\r\n");
+            body.resize(size + 150, b'\n');
+            let mut writer = stream.try_clone().unwrap();
+            let sending = thread::spawn(move || {
+                // 서버가 먼저 거절하고 닫으면 쓰기가 실패할 수 있다
+                let _ = writer.write_all(head.as_bytes()).and_then(|_| writer.write_all(&body));
+            });
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            sending.join().unwrap();
+            response
+        };
+
+        let response = upload("/upload");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with(&format!("big.bin {}", size)), "{}", response);
+        assert!(upload("/buffered").starts_with("HTTP/1.1 413 "));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn closes_after_max_requests_and_idle_timeout() {
+        let (addr, handle, running) = start(echo_router(), Duration::from_millis(100), 2);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream.write_all(b"GET /a HTTP/1.1\r\nHost: test\r\n\r\nGET /b HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+        // 100ms는 timeout=0이 아니라 1로 알린다
+        assert!(read_response(&mut reader).0.contains("Keep-Alive: timeout=1, max=1\r\n"));
+        assert!(read_response(&mut reader).0.contains("Connection: close\r\n"));
+
+        // 요청을 보내지 않고 기다리면 timeout 후에 서버가 연결을 닫는다
+        let idle = TcpStream::connect(addr).unwrap();
+        let mut rest = Vec::new();
+        BufReader::new(idle).read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        // HTTP/1.0은 keep-alive를 요청하지 않으면 바로 닫는다
+        let mut old = TcpStream::connect(addr).unwrap();
+        old.write_all(b"GET /c HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        old.read_to_string(&mut response).unwrap();
+        assert!(response.contains("Connection: close\r\n"));
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+// SIGINT(Ctrl+C) / SIGTERM 을 받았는지 기록해 두는 플래그
+// signal handler 안에서는 async-signal-safe한 작업만 해야 하므로 atomic 변수에 표시만 하고,
+// 실제 종료 처리는 서버의 accept 루프가 이 플래그를 보고 수행한다
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+pub fn received() -> bool {
+    RECEIVED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+pub fn install() {
+    use std::os::raw::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    // libc의 signal()을 직접 선언해서 사용 (19. 고급 기능들의 extern "C" 참고)
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_signal(_signum: c_int) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+}
+
+// unix가 아닌 환경에서는 signal을 처리하지 않고 ShutdownHandle로만 종료한다
+#[cfg(not(unix))]
+pub fn install() {}
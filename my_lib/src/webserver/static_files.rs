@@ -0,0 +1,415 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::date::{format_http_date, parse_http_date};
+use super::range::{parse_range, MultipartRanges, RangeError};
+use super::request::{Method, Request};
+use super::response::Response;
+use super::url::percent_decode;
+
+// 확장자로 Content-Type을 정한다. 모르는 확장자는 그냥 바이너리로 취급
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("mp4") => "video/mp4",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+/*
+root 디렉토리 아래의 파일을 내려주는 handler
+  - 요청 경로는 퍼센트 디코딩한 뒤 root 기준 상대 경로로 해석한다
+  - ".."으로 root 밖으로 나가려 하거나, 심볼릭 링크가 root 밖을 가리키면 403
+  - 디렉토리면 그 안의 index 파일(기본값 index.html)을 내려준다
+  - 파일은 메모리에 모두 읽지 않고 Body::Reader로 흘려 보낸다
+  - ETag/Last-Modified를 붙이고, 브라우저가 가진 사본이 최신이면 본문 없이 304로 응답한다
+  - Range 요청에는 요청한 부분만 206으로 내려준다 (이어받기, 동영상 탐색 등)
+*/
+pub struct StaticFiles {
+    root: PathBuf,
+    index: String,
+    cache_control: Option<String>,
+}
+
+impl StaticFiles {
+    pub fn new<P: AsRef<Path>>(root: P) -> StaticFiles {
+        StaticFiles {
+            root: root.as_ref().to_path_buf(),
+            index: String::from("index.html"),
+            cache_control: None,
+        }
+    }
+
+    // 내려주는 파일에 붙일 Cache-Control 값 (예: "public, max-age=3600")
+    pub fn with_cache_control(mut self, value: &str) -> StaticFiles {
+        self.cache_control = Some(value.to_string());
+        self
+    }
+
+    pub fn with_index(mut self, index: &str) -> StaticFiles {
+        self.index = index.to_string();
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /*
+    path는 root 기준 상대 경로 (보통 wildcard route에서 뽑은 파라미터)
+    request는 디렉토리 주소 끝에 '/'가 없을 때 redirect할 주소를 만드는 데 쓴다
+    */
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        let relative = match sanitize(path) {
+            Some(relative) => relative,
+            None => return Response::status_page(403),
+        };
+
+        let mut full_path = self.root.join(&relative);
+        let metadata = match fs::metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(e) => return error_response(&e),
+        };
+
+        if metadata.is_dir() {
+            // 디렉토리 주소가 '/'로 끝나야 index.html 안의 상대 경로 링크가 올바르게 동작한다
+            if !request.path().ends_with('/') {
+                return Response::redirect(301, &format!("{}/", request.path()));
+            }
+            full_path.push(&self.index);
+        }
+
+        let (file, metadata) = match self.open(&full_path) {
+            Ok(opened) => opened,
+            Err(e) => return error_response(&e),
+        };
+
+        let modified = metadata.modified().ok();
+        let etag = entity_tag(&metadata);
+        let mut response = if is_not_modified(request, &etag, modified) {
+            Response::new(304)
+        } else {
+            file_response(request, file, metadata.len(), mime_type(&full_path), &etag, modified)
+        };
+
+        response.headers.insert("ETag", &etag);
+        if let Some(modified) = modified {
+            response.headers.insert("Last-Modified", &format_http_date(modified));
+        }
+        if let Some(cache_control) = &self.cache_control {
+            response.headers.insert("Cache-Control", cache_control);
+        }
+        response
+    }
+
+    fn open(&self, path: &Path) -> io::Result<(File, fs::Metadata)> {
+        // 심볼릭 링크를 따라간 실제 경로가 root 안에 있는지 한 번 더 확인한다
+        let root = fs::canonicalize(&self.root)?;
+        let real = fs::canonicalize(path)?;
+        if !real.starts_with(&root) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path escapes document root"));
+        }
+
+        let file = File::open(&real)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a regular file"));
+        }
+        Ok((file, metadata))
+    }
+}
+
+// 파일 크기와 수정 시각으로 만든 ETag. 내용이 바뀌면 둘 중 하나는 거의 확실히 바뀐다
+fn entity_tag(metadata: &fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}-{:x}\"", metadata.len(), modified.as_secs(), modified.subsec_nanos())
+}
+
+/*
+조건부 GET 판단 (RFC 7232)
+If-None-Match가 있으면 그것만 보고, 없을 때만 If-Modified-Since를 본다
+Last-Modified는 초 단위로 보내므로 비교도 초 단위로 한다
+*/
+fn is_not_modified(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if request.method != Method::Get && request.method != Method::Head {
+        return false;
+    }
+
+    if let Some(candidates) = request.header("If-None-Match") {
+        // 약한 비교: W/ 접두사는 무시하고 태그 값만 비교한다
+        let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return candidates.trim() == "*" || candidates.split(',').any(|tag| strip(tag) == strip(etag));
+    }
+
+    let since = request.header("If-Modified-Since").and_then(parse_http_date);
+    match (since, modified) {
+        (Some(since), Some(modified)) => {
+            let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            secs(modified) <= secs(since)
+        },
+        _ => false,
+    }
+}
+
+// 파일 본문 응답. Range 헤더가 없거나 무시해야 하면 200으로 전체를 보낸다
+//   - range 하나: 206, Content-Range, 파일을 해당 위치로 seek해서 그 부분만 보낸다
+//   - range 여러 개: 206, multipart/byteranges
+//   - 파일 안에 해당하는 부분이 없음: 416, Content-Range: bytes */길이
+fn file_response(
+    request: &Request,
+    mut file: File,
+    len: u64,
+    content_type: &str,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> Response {
+    let full = |file: File| {
+        Response::new(200)
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("Content-Type", content_type)
+            .with_reader(file, len)
+    };
+
+    // Range는 GET에만 적용하고, If-Range가 현재 파일과 맞지 않으면 전체를 보낸다
+    let range = match request.header("Range") {
+        Some(range) if request.method == Method::Get && if_range_matches(request, etag, modified) => range,
+        _ => return full(file),
+    };
+
+    match parse_range(range, len) {
+        Ok(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            if let Err(e) = file.seek(SeekFrom::Start(range.start)) {
+                return error_response(&e);
+            }
+            Response::new(206)
+                .with_header("Accept-Ranges", "bytes")
+                .with_header("Content-Type", content_type)
+                .with_header("Content-Range", &range.content_range(len))
+                .with_reader(file, range.len())
+        },
+        Ok(ranges) => {
+            let (multipart_type, body) = MultipartRanges::new(file, &ranges, content_type, len);
+            let body_len = body.len();
+            Response::new(206)
+                .with_header("Accept-Ranges", "bytes")
+                .with_header("Content-Type", &multipart_type)
+                .with_reader(body, body_len)
+        },
+        Err(RangeError::Unsatisfiable) => {
+            Response::status_page(416).with_header("Content-Range", &format!("bytes */{}", len))
+        },
+        Err(RangeError::Invalid) => full(file),
+    }
+}
+
+/*
+If-Range에는 ETag나 Last-Modified 값이 온다
+클라이언트가 가진 조각이 지금 파일과 같은 버전일 때만 나머지 부분을 보내야 하므로 강한 비교를 한다
+*/
+fn if_range_matches(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    let value = match request.header("If-Range") {
+        Some(value) => value.trim(),
+        None => return true,
+    };
+    if value.starts_with('"') || value.starts_with("W/") {
+        return value == etag;
+    }
+    match (parse_http_date(value), modified) {
+        (Some(date), Some(modified)) => format_http_date(date) == format_http_date(modified),
+        _ => false,
+    }
+}
+
+// 요청 경로를 root 밑의 안전한 상대 경로로 바꾼다. root 밖을 가리킬 수 있으면 None
+fn sanitize(path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(path)?;
+    let mut relative = PathBuf::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            // 윈도우의 경로 구분자나 NUL 문자로 검사를 우회하지 못하게 막는다
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s => relative.push(s),
+        }
+    }
+
+    // "C:" 같은 prefix나 절대 경로가 섞여 들어오지 않았는지 확인
+    if relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        Some(relative)
+    } else {
+        None
+    }
+}
+
+fn error_response(e: &io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => Response::status_page(404),
+        io::ErrorKind::PermissionDenied => Response::status_page(403),
+        _ => {
+            println!("Failed to serve file: {}", e);
+            Response::status_page(500)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::request::Method;
+    use crate::webserver::test_util::TempDir;
+    use std::io::Read;
+
+    // 테스트마다 임시 디렉토리를 새로 만든다. 돌려받은 TempDir이 살아 있는 동안만 남아 있다
+    fn document_root(name: &str) -> TempDir {
+        let root = TempDir::new(&format!("static_{}", name));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+        fs::write(root.join("docs").join("index.html"), "<h1>docs</h1>").unwrap();
+        fs::write(root.join("style.css"), "body {}").unwrap();
+        root
+    }
+
+    fn read_body(response: Response) -> String {
+        let mut body = String::new();
+        match response.body {
+            crate::webserver::Body::Reader(reader, len) => reader.take(len).read_to_string(&mut body).unwrap(),
+            crate::webserver::Body::Bytes(bytes) => return String::from_utf8(bytes).unwrap(),
+            crate::webserver::Body::Stream(_) => panic!("static files are never streamed"),
+        };
+        body
+    }
+
+    #[test]
+    fn serves_files_with_content_type() {
+        let root = document_root("types");
+        let files = StaticFiles::new(root.path());
+        let response = files.serve(&Request::new(Method::Get, "/style.css"), "style.css");
+
+        assert_eq!(200, response.status);
+        assert_eq!(Some("text/css; charset=utf-8"), response.headers.get("Content-Type"));
+        assert_eq!(7, response.body.len());
+        assert_eq!("body {}", read_body(response));
+    }
+
+    #[test]
+    fn serves_directory_index() {
+        let root = document_root("index");
+        let files = StaticFiles::new(root.path());
+
+        assert_eq!("<h1>home</h1>", read_body(files.serve(&Request::new(Method::Get, "/"), "")));
+        assert_eq!("<h1>docs</h1>", read_body(files.serve(&Request::new(Method::Get, "/docs/"), "docs")));
+
+        let redirect = files.serve(&Request::new(Method::Get, "/docs"), "docs");
+        assert_eq!(301, redirect.status);
+        assert_eq!(Some("/docs/"), redirect.headers.get("Location"));
+    }
+
+    #[test]
+    fn answers_conditional_requests_with_304() {
+        let root = document_root("conditional");
+        let files = StaticFiles::new(root.path()).with_cache_control("public, max-age=60");
+        let first = files.serve(&Request::new(Method::Get, "/style.css"), "style.css");
+        let etag = first.headers.get("ETag").unwrap().to_string();
+        let modified = first.headers.get("Last-Modified").unwrap().to_string();
+        assert_eq!(Some("public, max-age=60"), first.headers.get("Cache-Control"));
+
+        let mut request = Request::new(Method::Get, "/style.css");
+        request.headers.insert("If-None-Match", &format!("\"other\", W/{}", etag));
+        let response = files.serve(&request, "style.css");
+        assert_eq!(304, response.status);
+        assert!(response.body.is_empty());
+        assert_eq!(Some(etag.as_str()), response.headers.get("ETag"));
+
+        let mut request = Request::new(Method::Get, "/style.css");
+        request.headers.insert("If-Modified-Since", &modified);
+        assert_eq!(304, files.serve(&request, "style.css").status);
+
+        let mut request = Request::new(Method::Get, "/style.css");
+        request.headers.insert("If-None-Match", "\"stale\"");
+        request.headers.insert("If-Modified-Since", &modified);
+        assert_eq!(200, files.serve(&request, "style.css").status);
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let root = document_root("range");
+        let files = StaticFiles::new(root.path());
+        let range = |value: &str| {
+            let mut request = Request::new(Method::Get, "/style.css");
+            request.headers.insert("Range", value);
+            files.serve(&request, "style.css")
+        };
+
+        let partial = range("bytes=2-5");
+        assert_eq!(206, partial.status);
+        assert_eq!(Some("bytes 2-5/7"), partial.headers.get("Content-Range"));
+        assert_eq!("dy {", read_body(partial));
+        assert_eq!("{}", read_body(range("bytes=-2")));
+
+        let multi = range("bytes=0-0,5-");
+        assert_eq!(206, multi.status);
+        assert!(multi.headers.get("Content-Type").unwrap().starts_with("multipart/byteranges; boundary="));
+        assert!(read_body(multi).contains("Content-Range: bytes 5-6/7\r\n\r\n{}"));
+
+        let unsatisfiable = range("bytes=7-");
+        assert_eq!(416, unsatisfiable.status);
+        assert_eq!(Some("bytes */7"), unsatisfiable.headers.get("Content-Range"));
+        assert_eq!(200, range("lines=1-2").status);
+
+        // If-Range가 현재 ETag와 다르면 Range를 무시하고 전체를 보낸다
+        let mut request = Request::new(Method::Get, "/style.css");
+        request.headers.insert("Range", "bytes=0-1");
+        request.headers.insert("If-Range", "\"old\"");
+        let full = files.serve(&request, "style.css");
+        assert_eq!(200, full.status);
+        assert_eq!(Some("bytes"), full.headers.get("Accept-Ranges"));
+    }
+
+    #[test]
+    fn rejects_traversal_and_reports_missing_files() {
+        let root = document_root("traversal");
+        let files = StaticFiles::new(root.join("docs"));
+        let request = Request::new(Method::Get, "/");
+
+        assert_eq!(403, files.serve(&request, "../index.html").status);
+        assert_eq!(403, files.serve(&request, "%2e%2e/index.html").status);
+        assert_eq!(403, files.serve(&request, "..%2Findex.html").status);
+        assert_eq!(404, files.serve(&request, "missing.html").status);
+    }
+}
@@ -0,0 +1,32 @@
+// 여러 모듈의 테스트가 함께 쓰는 도구
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// 테스트마다 따로 쓰는 임시 디렉토리. 이름과 프로세스 ID로 겹치지 않게 만들고 drop될 때 지운다
+pub(super) struct TempDir(PathBuf);
+
+impl TempDir {
+    // 지난 실행에서 남은 것이 있으면 지우고 빈 디렉토리로 시작한다
+    pub(super) fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("webserver_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub(super) fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
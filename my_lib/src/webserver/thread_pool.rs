@@ -0,0 +1,843 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// FnBox 트레잇은 함수 포인터를 가지고 있으며, call_box를 호출하면 가지고 있는 함수 포인터에 해당하는 함수를 호출함
+trait FnBox {
+    fn call_box(self: Box<Self>);
+}
+
+// F는 FnOnce() 타입이므로 F는 파라미터들의 소유권을 빼앗아오기때문에 한번만 호출할 수 있음
+impl<F: FnOnce()> FnBox for F {
+    fn call_box(self: Box<F>) {
+        (*self)()
+    }
+}
+
+// Job은 threadsafe 하게 소유권을 이동시킬 수 있고(Send) + 전역 lifetime을 갖는('static) + FnBox 타입의 포인터다
+type Job = Box<dyn FnBox + Send + 'static>;
+
+// Job이 panic을 일으켰을 때 호출되는 hook. worker ID와 panic payload를 넘겨받는다
+type PanicHandler = Box<dyn Fn(usize, &(dyn Any + Send)) + Send + Sync>;
+
+// 메세지는 Job 생성과 종료
+enum Message {
+    NewJob(Job),
+    Terminate,
+}
+
+// 큐가 가득 찼을 때 새 Job을 어떻게 처리할지
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Block,      // 자리가 날 때까지 execute()를 호출한 쪽을 기다리게 한다
+    Reject,     // 새 Job을 거절한다
+    DropOldest, // 가장 오래 기다린 Job을 버리고 새 Job을 넣는다
+}
+
+/*
+ThreadPool 설정. PoolConfig::new(size)로 만든 뒤 필요한 필드만 바꿔서 사용한다
+min_threads < max_threads 이면 pool이 부하에 따라 늘었다 줄었다 한다
+  - 큐에 쌓인 Job을 가져갈 놀고 있는 worker가 없으면 max_threads까지 새 worker를 띄우고
+  - keep_alive 동안 Job을 받지 못한 worker는 min_threads까지 스스로 종료한다
+*/
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub min_threads: usize,
+    pub max_threads: usize,
+    pub keep_alive: Duration,
+    pub queue_capacity: Option<usize>,  // None이면 제한 없음
+    pub overflow: OverflowPolicy,
+}
+
+impl PoolConfig {
+    // worker 개수가 size로 고정된 설정
+    pub fn new(size: usize) -> PoolConfig {
+        PoolConfig {
+            min_threads: size,
+            max_threads: size,
+            keep_alive: Duration::from_secs(60),
+            queue_capacity: None,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+
+    pub fn elastic(min_threads: usize, max_threads: usize, keep_alive: Duration) -> PoolConfig {
+        PoolConfig {
+            min_threads,
+            max_threads,
+            keep_alive,
+            ..PoolConfig::new(min_threads)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecuteError {
+    QueueFull,
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::QueueFull => f.write_str("job queue is full"),
+        }
+    }
+}
+
+impl Error for ExecuteError {}
+
+// panic!()에 넘긴 메세지를 꺼낸다. panic!("...")은 &str, panic!("{}", x)는 String payload를 만든다
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+// spawn()으로 실행한 Job의 결과를 받지 못한 이유
+pub enum JoinError {
+    Panicked(Box<dyn Any + Send>),  // Job이 panic함. panic payload를 그대로 돌려준다
+    Cancelled,                      // Job이 실행되지 못하고 버려짐 (pool 종료 등)
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => write!(f, "Panicked({:?})", panic_message(payload.as_ref())),
+            JoinError::Cancelled => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => write!(f, "job panicked: {}", panic_message(payload.as_ref())),
+            JoinError::Cancelled => f.write_str("job was cancelled"),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+// spawn()이 돌려주는 핸들. Job이 끝나면 반환값(또는 panic payload)을 꺼낼 수 있다
+pub struct JobHandle<T> {
+    receiver: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    // Job이 끝날 때까지 기다린다
+    pub fn join(self) -> Result<T, JoinError> {
+        match self.receiver.recv() {
+            Ok(result) => result.map_err(JoinError::Panicked),
+            Err(_) => Err(JoinError::Cancelled),
+        }
+    }
+
+    // 기다리지 않고 확인만 한다. 아직 끝나지 않았으면 None
+    // 결과는 한 번만 꺼낼 수 있으며 그 다음부터는 Cancelled를 돌려준다
+    pub fn try_join(&mut self) -> Option<Result<T, JoinError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result.map_err(JoinError::Panicked)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(JoinError::Cancelled)),
+        }
+    }
+}
+
+/*
+용량 제한이 있는 Job 큐
+mpsc 채널은 보내는 쪽에서 이미 들어간 메세지를 다시 꺼낼 수 없어서 DropOldest를 만들 수 없으므로
+VecDeque를 Mutex로 보호하고, 기다림은 Condvar로 처리한다
+*/
+struct JobQueue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,     // worker가 Job이 들어오기를 기다림
+    not_full: Condvar,      // Block 정책에서 execute()가 빈 자리를 기다림
+    capacity: Option<usize>,
+}
+
+struct QueueState {
+    messages: VecDeque<Message>,
+    idle: usize,    // pop()에서 Job을 기다리고 있는 worker 수
+}
+
+impl JobQueue {
+    fn new(capacity: Option<usize>) -> JobQueue {
+        JobQueue {
+            state: Mutex::new(QueueState { messages: VecDeque::new(), idle: 0 }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    // Job은 lock 밖에서 실행되므로 poison될 일은 거의 없지만, 되더라도 큐 자체는 멀쩡하므로 계속 사용한다
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // wait가 false면 Block 정책이라도 기다리지 않고 QueueFull을 돌려준다
+    fn push(&self, job: Job, policy: OverflowPolicy, wait: bool) -> Result<(), ExecuteError> {
+        // 버린 Job은 lock을 놓은 뒤에 drop되도록 messages보다 먼저 선언한다
+        let mut evicted = Vec::new();
+        let mut state = self.lock();
+
+        if let Some(capacity) = self.capacity {
+            while state.messages.len() >= capacity {
+                match policy {
+                    OverflowPolicy::Block if wait => {
+                        state = self.not_full.wait(state).unwrap_or_else(PoisonError::into_inner);
+                    },
+                    OverflowPolicy::DropOldest => {
+                        match state.messages.iter().position(|m| matches!(m, Message::NewJob(_))) {
+                            Some(i) => evicted.extend(state.messages.remove(i)),
+                            None => break,
+                        }
+                    },
+                    _ => return Err(ExecuteError::QueueFull),
+                }
+            }
+        }
+        if !evicted.is_empty() {
+            println!("Job queue full; dropped {} oldest job(s).", evicted.len());
+        }
+
+        state.messages.push_back(Message::NewJob(job));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    // 종료 메세지는 용량과 상관없이 항상 넣는다
+    fn push_terminate(&self, count: usize) {
+        let mut state = self.lock();
+        for _ in 0..count {
+            state.messages.push_back(Message::Terminate);
+        }
+        self.not_empty.notify_all();
+    }
+
+    // timeout 동안 아무 메세지도 오지 않으면 None
+    fn pop_timeout(&self, timeout: Duration) -> Option<Message> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                self.not_full.notify_one();
+                return Some(message);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            state.idle += 1;
+            state = self.not_empty.wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            state.idle -= 1;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lock().messages.iter().filter(|m| matches!(m, Message::NewJob(_))).count()
+    }
+
+    // 새 Job을 넣었을 때 바로 가져갈 worker가 없는지
+    fn is_backed_up(&self) -> bool {
+        let state = self.lock();
+        state.messages.len() >= state.idle
+    }
+}
+
+/*
+pool과 worker 쓰레드들이 함께 사용하는 상태
+worker가 죽으면 스스로 새 worker를 만들어 workers에 등록해야 하므로 pool이 아닌 여기에 둔다
+*/
+struct Shared {
+    queue: JobQueue,
+    workers: Mutex<Vec<Worker>>,
+    panic_handler: RwLock<Option<PanicHandler>>,
+    terminating: AtomicBool,
+    live: AtomicUsize,      // 종료하지 않은 worker 수 (panic 후 다시 띄운 worker 포함)
+    busy: AtomicUsize,      // Job을 실행하고 있는 worker 수
+    completed: AtomicUsize, // 끝난 Job 수 (panic한 Job 포함)
+    next_id: AtomicUsize,
+    min_threads: usize,
+    max_threads: usize,
+    keep_alive: Duration,
+}
+
+// Worker는 Worker ID와 thread handle을 가지고 있음
+struct Worker {
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    // Worker ID와 공유 상태의 소유권을 안전하게 전달 받는다
+    fn new(id: usize, shared: Arc<Shared>) -> Worker {
+        let thread = thread::spawn(move || {
+            // 이 쓰레드가 panic으로 끝나면 Sentinel의 drop에서 대신할 worker를 만든다
+            let _sentinel = Sentinel { id, shared: Arc::clone(&shared) };
+
+            loop {
+                let message = match shared.queue.pop_timeout(shared.keep_alive) {
+                    Some(message) => message,
+                    None if shared.retire() => {
+                        println!("Worker {} was idle for {:?}; exiting.", id, shared.keep_alive);
+
+                        break;
+                    },
+                    None => continue,
+                };
+
+                match message {
+                    Message::NewJob(job) => {
+                        println!("Worker {} got a job; executing.", id);
+
+                        // Message에 묻어온 Job에 해당하는 함수를 실행시킴(Job이 가진 FnBox의 함수 포인터 호출)
+                        // Job이 panic해도 worker 쓰레드는 죽지 않도록 catch_unwind로 감싼다
+                        shared.busy.fetch_add(1, Ordering::Relaxed);
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| job.call_box()));
+                        shared.busy.fetch_sub(1, Ordering::Relaxed);
+                        shared.completed.fetch_add(1, Ordering::Relaxed);
+                        if let Err(payload) = result {
+                            shared.report_panic(id, payload.as_ref());
+                        }
+                    },
+                    Message::Terminate => {
+                        println!("Worker {} was told to terminate.", id);
+
+                        break;
+                    },
+                }
+            }
+        });
+
+        Worker {
+            id,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Shared {
+    // 놀고 있는 worker가 스스로 종료해도 되는지. min_threads 밑으로는 줄어들지 않는다
+    fn retire(&self) -> bool {
+        self.live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| if n > self.min_threads { Some(n - 1) } else { None })
+            .is_ok()
+    }
+
+    // max_threads를 넘지 않는 선에서 worker를 하나 더 띄운다
+    fn grow(shared: &Arc<Shared>) -> bool {
+        let reserved = shared.live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| if n < shared.max_threads { Some(n + 1) } else { None })
+            .is_ok();
+        if !reserved {
+            return false;
+        }
+
+        let id = shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let worker = Worker::new(id, Arc::clone(shared));
+        let mut workers = shared.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        // 이미 종료한 worker들은 여기서 join해서 정리한다
+        workers.retain_mut(|w| match w.thread.take() {
+            Some(thread) if thread.is_finished() => {
+                let _ = thread.join();
+                false
+            },
+            thread => {
+                w.thread = thread;
+                true
+            },
+        });
+        workers.push(worker);
+        true
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            workers: self.live.load(Ordering::Relaxed),
+            max_workers: self.max_threads,
+            busy: self.busy.load(Ordering::Relaxed),
+            queued: self.queue.len(),
+            completed: self.completed.load(Ordering::Relaxed),
+        }
+    }
+
+    fn report_panic(&self, id: usize, payload: &(dyn Any + Send)) {
+        let handler = self.panic_handler.read().unwrap_or_else(PoisonError::into_inner);
+        match handler.as_ref() {
+            Some(handler) => handler(id, payload),
+            None => println!("Worker {} job panicked: {}", id, panic_message(payload)),
+        }
+    }
+}
+
+// worker 쓰레드가 panic으로 unwind될 때 drop되면서 같은 ID의 worker를 새로 띄운다
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+
+        // terminate()는 이 lock을 잡고 종료 표시를 하므로, 종료가 시작된 뒤에는 새 worker를 등록하지 않는다
+        // (등록하더라도 이미 workers를 가져간 terminate()는 그 worker를 join하지 못한다)
+        let mut workers = self.shared.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.shared.terminating.load(Ordering::SeqCst) {
+            self.shared.live.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+
+        println!("Worker {} died; respawning.", self.id);
+        let worker = Worker::new(self.id, Arc::clone(&self.shared));
+        match workers.iter_mut().find(|w| w.id == self.id) {
+            // 죽은 쓰레드의 handle은 버리고(detach) 새 쓰레드로 교체
+            Some(slot) => *slot = worker,
+            None => workers.push(worker),
+        }
+    }
+}
+
+// pool 상태를 한 순간 찍은 값. 각 값은 따로 읽으므로 서로 정확히 맞지 않을 수 있다
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub workers: usize,
+    pub max_workers: usize,
+    pub busy: usize,
+    pub queued: usize,
+    pub completed: usize,
+}
+
+// pool을 소유하지 않고 상태만 볼 수 있는 handle (metrics 수집 등)
+#[derive(Clone)]
+pub struct PoolMonitor {
+    shared: Arc<Shared>,
+}
+
+impl PoolMonitor {
+    pub fn stats(&self) -> PoolStats {
+        self.shared.stats()
+    }
+}
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    overflow: OverflowPolicy,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::with_config(PoolConfig::new(size))
+    }
+
+    pub fn with_config(config: PoolConfig) -> ThreadPool {
+        assert!(config.min_threads > 0);
+        assert!(config.min_threads <= config.max_threads, "min_threads must not exceed max_threads");
+        assert!(config.queue_capacity != Some(0), "queue capacity must be greater than 0");
+
+        // Job 큐는 worker들이 공유하는 상태에 넣고, threadsafe하게 소유권을 이동시킬 수 있도록 Arc로 감싼다
+        let shared = Arc::new(Shared {
+            queue: JobQueue::new(config.queue_capacity),
+            workers: Mutex::new(Vec::with_capacity(config.max_threads)),
+            panic_handler: RwLock::new(None),
+            terminating: AtomicBool::new(false),
+            live: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            completed: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(0),
+            min_threads: config.min_threads,
+            max_threads: config.max_threads,
+            keep_alive: config.keep_alive,
+        });
+        /*
+        정수 ID값과 공유 상태의 참조자를 복사해서 갖고 있는 Worker를 만들어 Vector에 차례로 넣는다
+        참조자를 threadsafe하게 복사해서 넘겼으므로 Worker 내부에서 Job 큐를 문제없이 사용할 수 있다
+        */
+        for _ in 0..config.min_threads {
+            Shared::grow(&shared);
+        }
+        ThreadPool {
+            shared,
+            overflow: config.overflow,
+        }
+    }
+
+    // F는 한 번만 호출 될 수 있고(FnOnce : 캡쳐한 파라미터의 소유권을 한 번만 가져올 수 있으므로 호출도 한 번만 할 수 있음), 쓰레드 간 소유권을 이동시킬 수 있고(Send), 그리고 전역의 lifetime을 갖음('static)
+    pub fn execute<F>(&self, f: F) where F: FnOnce() + Send + 'static
+    {
+        /*
+        ThreadPool.execute() 하게 되면
+        파라미터 넘어온 F타입의 함수에 포인터를 씌워 Job 타입으로 만들고, 만든 Job을 Message에 담아 큐에 넣는다
+        해당 메세지는 Worker들 중 하나가 안전하게(mutex+arc) 꺼내서 처리할 것이고
+        결국 Message > Job > F로 감싸져 있는 구성에서, 실제 수행 대상인 F의 함수포인터가 실행된다
+
+        큐가 가득 찼을 때 Block이면 기다리고, DropOldest면 오래된 Job을 버리고, Reject면 이 Job을 버린다
+        */
+        let job = Box::new(f);
+        self.grow_if_backed_up();
+        if let Err(e) = self.shared.queue.push(job, self.overflow, true) {
+            println!("Job rejected: {}", e);
+        }
+    }
+
+    // execute()와 같지만 절대 기다리지 않는다. 큐가 가득 차서 넣지 못했으면 Err (DropOldest 정책은 항상 Ok)
+    pub fn try_execute<F>(&self, f: F) -> Result<(), ExecuteError> where F: FnOnce() + Send + 'static
+    {
+        self.grow_if_backed_up();
+        self.shared.queue.push(Box::new(f), self.overflow, false)
+    }
+
+    // 넣으려는 Job을 바로 가져갈 worker가 없으면 worker를 늘린다
+    fn grow_if_backed_up(&self) {
+        if self.shared.queue.is_backed_up() && Shared::grow(&self.shared) {
+            println!("Job queue backed up; grew pool to {} workers.", self.shared.live.load(Ordering::SeqCst));
+        }
+    }
+
+    /*
+    execute()와 같이 Job을 보내되, 클로저의 반환값을 돌려받을 수 있는 JobHandle을 준다
+    결과는 Job마다 만드는 일회용 채널로 전달되며,
+    panic도 여기서 잡아 JobHandle로 넘기므로 panic handler는 호출되지 않는다
+    */
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.execute(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            // JobHandle을 이미 버렸다면 결과도 그냥 버린다
+            let _ = sender.send(result);
+        });
+        JobHandle { receiver }
+    }
+
+    // Job이 panic하면 기본 출력 대신 handler를 호출한다
+    pub fn set_panic_handler<F>(&self, handler: F)
+        where F: Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static
+    {
+        *self.shared.panic_handler.write().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(handler));
+    }
+
+    // 아직 worker가 가져가지 않은 Job 개수
+    pub fn queued_jobs(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    // 실제로 살아있는 worker 쓰레드 개수
+    pub fn live_workers(&self) -> usize {
+        self.shared.workers.lock().unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|w| w.thread.as_ref().is_some_and(|t| !t.is_finished()))
+            .count()
+    }
+
+    // worker 수, 바쁜 worker 수, 큐 길이 등. 바쁜 worker와 큐 길이를 보고 pool 크기를 정할 수 있다
+    pub fn stats(&self) -> PoolStats {
+        self.shared.stats()
+    }
+
+    pub fn monitor(&self) -> PoolMonitor {
+        PoolMonitor { shared: Arc::clone(&self.shared) }
+    }
+
+    fn terminate(&self) -> Vec<Worker> {
+        // 종료 중에 죽은 worker는 다시 띄우지 않는다. Sentinel과 같은 lock 안에서 표시해야 빠지는 worker가 없다
+        let workers: Vec<Worker> = {
+            let mut workers = self.shared.workers.lock().unwrap_or_else(PoisonError::into_inner);
+            self.shared.terminating.store(true, Ordering::SeqCst);
+            workers.drain(..).collect()
+        };
+        self.shared.queue.push_terminate(workers.len());
+        workers
+    }
+
+    /*
+    모든 worker에게 Terminate를 보내고 최대 timeout 동안 종료를 기다린다
+    Terminate는 이미 큐에 들어있는 Job들 뒤에 쌓이므로, 받아둔 Job은 모두 처리된 뒤에 종료된다
+    timeout 안에 끝나지 않은 worker는 join하지 않고 그대로 둔다(detach)
+    */
+    pub fn shutdown_timeout(self, timeout: Duration) {
+        let mut workers = self.terminate();
+
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            let all_finished = workers.iter()
+                .all(|w| w.thread.as_ref().is_none_or(|t| t.is_finished()));
+            if all_finished {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for worker in &mut workers {
+            if let Some(thread) = worker.thread.take() {
+                if thread.is_finished() {
+                    let _ = thread.join();
+                } else {
+                    println!("Worker {} did not finish in time; detaching.", worker.id);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // shutdown_timeout()으로 이미 종료된 경우
+        if self.shared.terminating.load(Ordering::SeqCst) {
+            return;
+        }
+
+        println!("Sending terminate message to all workers.");
+        let mut workers = self.terminate();
+
+        println!("Shutting down all workers.");
+
+        for worker in &mut workers {
+            println!("Shutting down worker {}", worker.id);
+
+            if let Some(thread) = worker.thread.take() {
+                // worker 쓰레드가 panic으로 끝났더라도 pool의 drop까지 panic시키지는 않는다
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_panic_is_reported_and_worker_survives() {
+        let pool = ThreadPool::new(2);
+        let panics = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&panics);
+        pool.set_panic_handler(move |_, payload| {
+            assert_eq!("boom", panic_message(payload));
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        for _ in 0..4 {
+            pool.execute(|| panic!("boom"));
+        }
+        let (tx, rx) = mpsc::channel();
+        for i in 0..4 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+
+        let mut results: Vec<i32> = rx.iter().take(4).collect();
+        results.sort();
+        assert_eq!(vec![0, 1, 2, 3], results);
+        // 다른 worker가 아직 panic handler를 실행 중일 수 있으므로 잠시 기다린다
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while panics.load(Ordering::SeqCst) < 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(4, panics.load(Ordering::SeqCst));
+        assert_eq!(2, pool.live_workers());
+    }
+
+    #[test]
+    fn spawn_returns_value_or_panic_payload() {
+        let pool = ThreadPool::new(2);
+
+        let sum = pool.spawn(|| (1..=10).sum::<i32>());
+        let failed = pool.spawn(|| -> i32 { panic!("bad input {}", 3) });
+
+        assert_eq!(55, sum.join().unwrap());
+        match failed.join() {
+            Err(JoinError::Panicked(payload)) => assert_eq!("bad input 3", panic_message(payload.as_ref())),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_join_does_not_block() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let mut handle = pool.spawn(move || {
+            rx.recv().unwrap();
+            "finished"
+        });
+        assert!(handle.try_join().is_none());
+
+        tx.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(result) = handle.try_join() {
+                assert_eq!("finished", result.unwrap());
+                break;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    // 하나뿐인 worker를 막아두고 큐를 채운다. 막은 Job을 풀어주는 sender를 돌려준다
+    fn blocked_pool(policy: OverflowPolicy) -> (ThreadPool, mpsc::Sender<()>) {
+        let mut config = PoolConfig::new(1);
+        config.queue_capacity = Some(2);
+        config.overflow = policy;
+        let pool = ThreadPool::with_config(config);
+
+        let (release, blocker) = mpsc::channel::<()>();
+        let (started_tx, started_rx) = mpsc::channel();
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            blocker.recv().unwrap();
+        });
+        started_rx.recv().unwrap();
+        (pool, release)
+    }
+
+    #[test]
+    fn reject_policy_returns_error_when_full() {
+        let (pool, release) = blocked_pool(OverflowPolicy::Reject);
+
+        assert!(pool.try_execute(|| ()).is_ok());
+        assert!(pool.try_execute(|| ()).is_ok());
+        assert_eq!(Err(ExecuteError::QueueFull), pool.try_execute(|| ()));
+        assert_eq!(2, pool.queued_jobs());
+        let stats = pool.stats();
+        assert_eq!((1, 1, 2, 0), (stats.workers, stats.busy, stats.queued, stats.completed));
+
+        release.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.stats().completed < 3 {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(0, pool.monitor().stats().busy);
+    }
+
+    #[test]
+    fn drop_oldest_policy_evicts_queued_job() {
+        let (pool, release) = blocked_pool(OverflowPolicy::DropOldest);
+
+        let oldest = pool.spawn(|| 1);
+        let middle = pool.spawn(|| 2);
+        let newest = pool.spawn(|| 3);
+        release.send(()).unwrap();
+
+        assert!(matches!(oldest.join(), Err(JoinError::Cancelled)));
+        assert_eq!(2, middle.join().unwrap());
+        assert_eq!(3, newest.join().unwrap());
+    }
+
+    #[test]
+    fn block_policy_waits_for_space() {
+        let (pool, release) = blocked_pool(OverflowPolicy::Block);
+        pool.execute(|| ());
+        pool.execute(|| ());
+        assert_eq!(Err(ExecuteError::QueueFull), pool.try_execute(|| ()));
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            release.send(()).unwrap();
+        });
+        // 자리가 날 때까지 기다렸다가 들어간다
+        let last = pool.spawn(|| "queued");
+        assert_eq!("queued", last.join().unwrap());
+        releaser.join().unwrap();
+    }
+
+    fn wait_for_workers(pool: &ThreadPool, expected: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.live_workers() != expected && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(expected, pool.live_workers());
+    }
+
+    #[test]
+    fn elastic_pool_grows_under_load_and_shrinks_when_idle() {
+        let pool = ThreadPool::with_config(PoolConfig::elastic(1, 3, Duration::from_millis(100)));
+        assert_eq!(1, pool.live_workers());
+
+        let (release, blocker) = mpsc::channel::<()>();
+        let blocker = Arc::new(Mutex::new(blocker));
+        let handles: Vec<JobHandle<()>> = (0..3)
+            .map(|_| {
+                let blocker = Arc::clone(&blocker);
+                pool.spawn(move || blocker.lock().unwrap().recv().unwrap())
+            })
+            .collect();
+        // 세 Job이 동시에 실행될 수 있도록 worker가 max_threads까지 늘어난다
+        wait_for_workers(&pool, 3);
+
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        wait_for_workers(&pool, 1);
+    }
+
+    #[test]
+    fn dead_worker_is_respawned() {
+        let pool = ThreadPool::new(2);
+        // hook 자체가 panic하면 worker 쓰레드가 죽는다
+        pool.set_panic_handler(|_, _| panic!("hook failed"));
+        pool.execute(|| panic!("boom"));
+
+        thread::sleep(Duration::from_millis(50));
+        wait_for_workers(&pool, 2);
+
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(7).unwrap());
+        assert_eq!(7, rx.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+
+    #[test]
+    fn worker_dying_during_shutdown_is_not_respawned() {
+        let pool = ThreadPool::new(1);
+        let monitor = pool.monitor();
+        // hook은 pool이 종료를 시작한 뒤에 panic해서 worker 쓰레드를 죽인다
+        let (release, blocker) = mpsc::channel::<()>();
+        let blocker = Mutex::new(blocker);
+        pool.set_panic_handler(move |_, _| {
+            blocker.lock().unwrap().recv().unwrap();
+            panic!("hook failed");
+        });
+        pool.execute(|| panic!("boom"));
+
+        let shared = Arc::clone(&monitor.shared);
+        let releaser = thread::spawn(move || {
+            while !shared.terminating.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            release.send(()).unwrap();
+        });
+        // drop은 죽어가는 worker까지 join하고 돌아온다
+        drop(pool);
+        releaser.join().unwrap();
+
+        assert!(monitor.shared.workers.lock().unwrap().is_empty());
+        assert_eq!(0, monitor.stats().workers);
+    }
+}
@@ -0,0 +1,119 @@
+// URL 구성 요소를 다루는 함수들
+
+// "%2F" 같은 퍼센트 인코딩을 원래 바이트로 되돌린다
+// 잘못된 인코딩이거나 결과가 UTF-8이 아니면 None
+pub fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let hex = std::str::from_utf8(hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// application/x-www-form-urlencoded 값 디코딩. 쿼리 스트링과 form 본문은 공백을 '+'로 인코딩한다
+pub fn form_decode(s: &str) -> Option<String> {
+    percent_decode(&s.replace('+', " "))
+}
+
+/*
+쿼리 스트링이나 urlencoded form의 (이름, 값) 모음
+    ?tag=a&tag=b&page=2
+같은 이름이 여러 번 올 수 있으므로 HashMap 대신 순서를 보존하는 Vec으로 저장한다 (Headers와 같은 방식)
+이름은 대소문자를 구분한다
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryMap {
+    entries: Vec<(String, String)>,
+}
+
+impl QueryMap {
+    pub fn new() -> QueryMap {
+        QueryMap { entries: Vec::new() }
+    }
+
+    /*
+    "a=1&b=2&b=3" 형식을 파싱한다
+    '='가 없는 항목은 값이 빈 문자열이고, 디코딩할 수 없는 항목은 버린다
+    */
+    pub fn parse(s: &str) -> QueryMap {
+        let entries = s
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Some((form_decode(name)?, form_decode(value)?))
+            })
+            .collect();
+        QueryMap { entries }
+    }
+
+    // 같은 이름이 여러 개면 첫번째 값을 돌려준다
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries.iter().filter(move |(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_and_plus() {
+        assert_eq!(Some(String::from("a b/c")), percent_decode("a%20b%2Fc"));
+        assert_eq!(Some(String::from("a+b")), percent_decode("a+b"));
+        assert_eq!(Some(String::from("a b+c")), form_decode("a+b%2Bc"));
+        assert_eq!(None, percent_decode("%zz"));
+        assert_eq!(None, percent_decode("%ff"));
+    }
+
+    #[test]
+    fn parses_query_into_multimap() {
+        let query = QueryMap::parse("tag=rust&tag=http&q=hello+world&empty&bad=%zz&&x=%26");
+        assert_eq!(Some("rust"), query.get("tag"));
+        assert_eq!(vec!["rust", "http"], query.get_all("tag").collect::<Vec<_>>());
+        assert_eq!(Some("hello world"), query.get("q"));
+        assert_eq!(Some(""), query.get("empty"));
+        assert_eq!(Some("&"), query.get("x"));
+        assert!(!query.contains("bad"));
+        assert_eq!(5, query.len());
+    }
+}
@@ -0,0 +1,175 @@
+use super::request::Request;
+use super::response::Response;
+use super::router::Router;
+
+/*
+가상 호스트 패턴
+    example.com         Host가 정확히 같을 때
+    *.example.com       example.com의 하위 도메인 (example.com 자체는 아님)
+    *                   모든 호스트
+뒤에 ":8080"을 붙이면 그 포트로 들어온 연결에만 해당한다. 포트는 Host 헤더가 아니라 연결을 받은 listener의 포트로 비교한다
+*/
+#[derive(Debug, Clone, PartialEq)]
+struct HostPattern {
+    host: String,   // 소문자. "*" 또는 "*.suffix" 가능
+    port: Option<u16>,
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> HostPattern {
+        let (host, port) = split_port(pattern);
+        let port = port.map(|p| p.parse().unwrap_or_else(|_| panic!("invalid port in host pattern: {}", pattern)));
+        HostPattern { host: normalize(host), port }
+    }
+
+    fn matches(&self, host: &str, port: Option<u16>) -> bool {
+        if self.port.is_some() && self.port != port {
+            return false;
+        }
+        match self.host.strip_prefix('*') {
+            Some("") => true,
+            Some(suffix) => host.len() > suffix.len() && host.ends_with(suffix),
+            None => self.host == host,
+        }
+    }
+}
+
+// "example.com:8080" -> ("example.com", Some("8080")). IPv6 주소는 "[::1]:8080" 형식이다
+fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => (&host[..i], Some(&host[i + 1..])),
+        _ => (host, None),
+    }
+}
+
+// 호스트 이름은 대소문자를 구분하지 않고, 끝의 '.'(FQDN 표기)는 없는 것과 같다
+fn normalize(host: &str) -> String {
+    host.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/*
+Host 헤더로 사이트(Router)를 고른다
+등록한 순서대로 패턴을 비교해 처음 맞는 사이트를 쓰고, 맞는 것이 없거나 Host가 없으면(HTTP/1.0) 기본 사이트를 쓴다
+그러므로 구체적인 패턴을 와일드카드보다 먼저 등록해야 한다
+*/
+pub(super) struct VirtualHosts {
+    default: Router,
+    sites: Vec<(HostPattern, Router)>,
+}
+
+impl VirtualHosts {
+    pub(super) fn new(default: Router) -> VirtualHosts {
+        VirtualHosts { default, sites: Vec::new() }
+    }
+
+    pub(super) fn add(&mut self, pattern: &str, router: Router) {
+        self.sites.push((HostPattern::parse(pattern), router));
+    }
+
+    pub(super) fn default_site(&self) -> &Router {
+        &self.default
+    }
+
+    pub(super) fn select(&self, host: Option<&str>, local_port: Option<u16>) -> &Router {
+        let host = match host {
+            Some(host) => normalize(split_port(host).0),
+            None => return &self.default,
+        };
+        self.sites
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&host, local_port))
+            .map_or(&self.default, |(_, router)| router)
+    }
+
+    pub(super) fn streams_body(&self, request: &Request, local_port: Option<u16>) -> bool {
+        self.select(request.header("Host"), local_port).streams_body(request)
+    }
+
+    pub(super) fn respond(&self, request: &mut Request, local_port: Option<u16>) -> Response {
+        let router = self.select(request.header("Host"), local_port);
+        router.respond(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::Method;
+
+    fn site(name: &'static str) -> Router {
+        let mut router = Router::new();
+        router.get("/", move |_, _| Response::text(200, name));
+        router
+    }
+
+    fn served_by(hosts: &VirtualHosts, host: Option<&str>, port: u16) -> String {
+        let mut request = Request::new(Method::Get, "/");
+        if let Some(host) = host {
+            request.headers.insert("Host", host);
+        }
+        let response = hosts.respond(&mut request, Some(port));
+        String::from_utf8(response.body.as_bytes().unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn selects_site_by_host_and_port() {
+        let mut hosts = VirtualHosts::new(site("default"));
+        hosts.add("docs.example.com", site("docs"));
+        hosts.add("*.example.com:8080", site("internal"));
+        hosts.add("*.example.com", site("wildcard"));
+
+        assert_eq!("docs", served_by(&hosts, Some("Docs.Example.com."), 80));
+        assert_eq!("docs", served_by(&hosts, Some("docs.example.com:8080"), 8080));
+        assert_eq!("internal", served_by(&hosts, Some("logs.example.com:8080"), 8080));
+        assert_eq!("wildcard", served_by(&hosts, Some("logs.example.com"), 80));
+        assert_eq!("default", served_by(&hosts, Some("example.com"), 80));
+        assert_eq!("default", served_by(&hosts, Some("[::1]:8080"), 8080));
+        assert_eq!("default", served_by(&hosts, None, 80));
+    }
+
+    #[test]
+    fn parses_host_patterns() {
+        assert_eq!(HostPattern { host: String::from("example.com"), port: Some(8080) }, HostPattern::parse("Example.COM.:8080"));
+        assert_eq!(HostPattern { host: String::from("[::1]"), port: Some(8080) }, HostPattern::parse("[::1]:8080"));
+        assert_eq!(HostPattern { host: String::from("[::1]"), port: None }, HostPattern::parse("[::1]"));
+        assert_eq!(("example.com", None), split_port("example.com"));
+        assert_eq!(("example.com", Some("")), split_port("example.com:"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid port in host pattern: example.com:http")]
+    fn rejects_invalid_port_in_pattern() {
+        HostPattern::parse("example.com:http");
+    }
+
+    #[test]
+    fn matches_wildcards_only_on_label_boundaries() {
+        let wildcard = HostPattern::parse("*.example.com");
+        assert!(wildcard.matches("a.b.example.com", None));
+        assert!(!wildcard.matches("example.com", None));
+        assert!(!wildcard.matches("badexample.com", None));
+        assert!(!wildcard.matches(".example.com", None));
+
+        let any = HostPattern::parse("*");
+        assert!(any.matches("anything", Some(1)));
+        assert!(any.matches("", None));
+        // 포트가 있는 패턴은 포트를 모르는 연결에는 맞지 않는다
+        assert!(!HostPattern::parse("*:8080").matches("anything", None));
+    }
+
+    #[test]
+    fn selects_ipv6_site_and_delegates_body_streaming() {
+        let mut upload = site("upload");
+        upload.post("/upload", |_, _| Response::text(200, "ok")).stream_body();
+        let mut hosts = VirtualHosts::new(site("default"));
+        hosts.add("[::1]", upload);
+
+        assert_eq!("upload", served_by(&hosts, Some("[::1]:8080"), 8080));
+        assert_eq!("default", served_by(&hosts, Some("[::2]"), 80));
+
+        let mut request = Request::new(Method::Post, "/upload");
+        assert!(!hosts.streams_body(&request, Some(80)));
+        request.headers.insert("Host", "[::1]");
+        assert!(hosts.streams_body(&request, Some(80)));
+    }
+}
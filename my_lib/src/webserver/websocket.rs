@@ -0,0 +1,710 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, Cursor};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::base64::{base64_decode, base64_encode};
+use super::request::{Method, Request, Version};
+use super::response::{Response, Upgrade};
+use super::sha1::sha1;
+
+// Sec-WebSocket-Key 뒤에 붙여 해시하는 고정 문자열 (RFC 6455 1.3)
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// 조각난 프레임을 합친 메시지 하나의 최대 크기
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+// 이만큼 아무 프레임도 오지 않으면 ping을 보내고, 다시 이만큼 지나도 답이 없으면 끊는다
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+// close 프레임의 상태 코드 (RFC 6455 7.4.1)
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const CLOSE_NO_STATUS: u16 = 1005;     // close 프레임에 코드가 없었음 (프레임으로 보내지는 않는다)
+const CLOSE_ABNORMAL: u16 = 1006;      // close 프레임 없이 연결이 끊김 (프레임으로 보내지는 않는다)
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+// handler가 받는 메시지. ping/pong과 close는 이 모듈이 알아서 처리한다
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/*
+연결 하나마다 하나씩 만들어지는 WebSocket handler
+메시지가 올 때마다 on_message가 불리고, 답장이나 서버 쪽에서 먼저 보내는 메시지는 WebSocket으로 보낸다
+WebSocket은 clone해서 다른 쓰레드에 넘길 수 있으므로, on_open에서 어딘가에 등록해 두고 실시간 알림을 보낼 수 있다
+메시지만 받으면 되는 경우 클로저 |socket, message| { ... } 도 handler로 쓸 수 있다
+*/
+pub trait WebSocketHandler: Send + 'static {
+    fn on_open(&mut self, _socket: &WebSocket) {}
+
+    fn on_message(&mut self, socket: &WebSocket, message: WebSocketMessage);
+
+    // code는 상대가 보낸 close 코드. 코드 없이 닫혔으면 1005, close 없이 연결이 그냥 끊겼으면 1006
+    fn on_close(&mut self, _code: u16, _reason: &str) {}
+}
+
+impl<F> WebSocketHandler for F
+    where F: FnMut(&WebSocket, WebSocketMessage) + Send + 'static
+{
+    fn on_message(&mut self, socket: &WebSocket, message: WebSocketMessage) {
+        self(socket, message)
+    }
+}
+
+/*
+WebSocket handshake 요청이면 101 응답을 만들고, 연결을 handler에 넘기도록 예약한다
+    router.get("/ws", |request, _| upgrade_websocket(request, |socket: &WebSocket, message| {
+        let _ = socket.send(message);   // echo
+    }));
+handshake가 아니면 400, 지원하지 않는 버전이면 426으로 응답한다
+*/
+pub fn upgrade_websocket<H: WebSocketHandler>(request: &Request, handler: H) -> Response {
+    let key = match handshake_key(request) {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    Response::new(101)
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", &accept_key(key))
+        .with_upgrade(upgrade(handler))
+}
+
+fn upgrade<H: WebSocketHandler>(handler: H) -> Upgrade {
+    let opened = Arc::new(Mutex::new(Opened::default()));
+    let closing = Arc::clone(&opened);
+    Upgrade::new(move |stream, buffered| {
+        if let Err(e) = run(stream, buffered, handler, PING_INTERVAL, &opened) {
+            println!("WebSocket connection failed: {}", e);
+        }
+    })
+    .on_shutdown(move || {
+        let mut opened = closing.lock().unwrap_or_else(|e| e.into_inner());
+        opened.going_away = true;
+        if let Some(socket) = &opened.socket {
+            let _ = socket.close(CLOSE_GOING_AWAY, "going away");
+        }
+    })
+}
+
+/*
+upgrade 함수와 서버 종료 hook이 함께 보는 연결 상태
+hook이 연결이 열리기 전에 불렸으면 going_away만 남겨 두고, 연결을 열자마자 close를 보낸다
+*/
+#[derive(Default)]
+struct Opened {
+    socket: Option<WebSocket>,
+    going_away: bool,
+}
+
+fn handshake_key(request: &Request) -> Result<&str, Response> {
+    let is_upgrade = request.method == Method::Get
+        && request.version == Version::Http11
+        && request.headers.has_token("Upgrade", "websocket")
+        && request.headers.has_token("Connection", "upgrade");
+    if !is_upgrade {
+        return Err(Response::status_page(400));
+    }
+    if request.header("Sec-WebSocket-Version") != Some("13") {
+        return Err(Response::status_page(426).with_header("Sec-WebSocket-Version", "13"));
+    }
+
+    // key는 임의의 16바이트를 base64로 인코딩한 값이어야 한다
+    match request.header("Sec-WebSocket-Key") {
+        Some(key) if base64_decode(key).is_some_and(|k| k.len() == 16) => Ok(key),
+        _ => Err(Response::status_page(400)),
+    }
+}
+
+// Sec-WebSocket-Accept = base64(sha1(key + GUID))
+pub fn accept_key(key: &str) -> String {
+    base64_encode(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+/*
+WebSocket 연결에 메시지를 보내는 핸들
+여러 쓰레드가 동시에 보내도 프레임이 섞이지 않게 writer를 Mutex로 감싼다
+close 프레임을 보낸 뒤에는 더 이상 보낼 수 없다
+*/
+#[derive(Clone)]
+pub struct WebSocket {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    writer: Mutex<Box<dyn Write + Send>>,
+    closed: AtomicBool,
+}
+
+impl WebSocket {
+    fn new<W: Write + Send + 'static>(writer: W) -> WebSocket {
+        WebSocket {
+            inner: Arc::new(Inner { writer: Mutex::new(Box::new(writer)), closed: AtomicBool::new(false) }),
+        }
+    }
+
+    pub fn send(&self, message: WebSocketMessage) -> io::Result<()> {
+        match message {
+            WebSocketMessage::Text(text) => self.send_frame(OP_TEXT, text.as_bytes()),
+            WebSocketMessage::Binary(data) => self.send_frame(OP_BINARY, &data),
+        }
+    }
+
+    pub fn send_text(&self, text: &str) -> io::Result<()> {
+        self.send_frame(OP_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&self, data: &[u8]) -> io::Result<()> {
+        self.send_frame(OP_BINARY, data)
+    }
+
+    // 상대가 살아 있는지 확인한다. 응답(pong)은 자동으로 무시된다
+    pub fn ping(&self, data: &[u8]) -> io::Result<()> {
+        self.send_frame(OP_PING, data)
+    }
+
+    // 연결을 닫자고 알린다. 상대가 close로 답하면 연결이 닫히고 on_close가 불린다
+    pub fn close(&self, code: u16, reason: &str) -> io::Result<()> {
+        let mut writer = self.inner.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if self.inner.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        payload.truncate(125);
+        write_frame(&mut *writer, OP_CLOSE, &payload, None)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::SeqCst)
+    }
+
+    fn send_frame(&self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut writer = self.inner.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_closed() {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "websocket is closed"));
+        }
+        write_frame(&mut *writer, opcode, payload, None)
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+// 프레임을 읽다가 생긴 문제. Close면 해당 코드로 연결을 닫는다
+enum FrameError {
+    Disconnected,   // 읽는 도중 연결이 끊김. close를 보낼 수 없다
+    Close(u16, &'static str),
+}
+
+impl From<io::Error> for FrameError {
+    fn from(_: io::Error) -> FrameError {
+        FrameError::Disconnected
+    }
+}
+
+/*
+프레임 하나를 읽는다 (RFC 6455 5.2)
+  byte 0: FIN(1) RSV(3) opcode(4)
+  byte 1: MASK(1) 길이(7). 126이면 뒤의 2바이트, 127이면 뒤의 8바이트가 길이
+  MASK가 1이면 4바이트 masking key, 그 뒤로 payload
+클라이언트가 보내는 프레임은 반드시 mask되어 있어야 한다
+*/
+fn read_frame<R: Read>(reader: &mut R, max_len: usize) -> Result<Frame, FrameError> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+
+    // 확장을 협상하지 않았으므로 RSV 비트는 모두 0이어야 한다
+    if head[0] & 0x70 != 0 {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR, "reserved bits set"));
+    }
+    if !masked {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR, "client frame not masked"));
+    }
+
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        },
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        },
+        len => len as u64,
+    };
+
+    // control 프레임은 조각낼 수 없고 payload가 125바이트 이하여야 한다
+    if opcode >= OP_CLOSE && (!fin || len > 125) {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR, "invalid control frame"));
+    }
+    if len > max_len as u64 {
+        return Err(FrameError::Close(CLOSE_TOO_BIG, "message too big"));
+    }
+
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+
+    Ok(Frame { fin, opcode, payload })
+}
+
+// 서버가 보내는 프레임은 mask하지 않는다. mask는 클라이언트 쪽 구현(테스트 등)에서 쓴다
+fn write_frame(writer: &mut dyn Write, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        },
+    }
+
+    match mask {
+        Some(mask) => {
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        },
+        None => frame.extend_from_slice(payload),
+    }
+
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/*
+연결이 끝날 때까지 프레임을 읽어 handler에 전달한다
+  - 조각난 메시지(FIN=0 + continuation)는 모두 모아서 한 번에 전달한다
+  - ping에는 같은 payload로 pong을 보낸다
+  - close를 받으면 close로 답하고 연결을 닫는다
+  - 규약을 어기면 해당 코드로 close를 보내고 연결을 닫는다
+  - ping_interval 동안 아무 프레임도 오지 않으면 ping을 보내고, 그 뒤로도 조용하면 죽은 연결로 보고 닫는다
+  - 서버가 종료되면 close 1001(going away)을 보내고 상대의 답을 기다린다. opened로 종료 hook과 연결을 나눠 갖는다
+*/
+fn run<H: WebSocketHandler>(
+    stream: TcpStream,
+    buffered: Vec<u8>,
+    mut handler: H,
+    ping_interval: Duration,
+    opened: &Mutex<Opened>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(ping_interval))?;
+    let socket = WebSocket::new(stream.try_clone()?);
+    let mut reader = BufReader::new(Cursor::new(buffered).chain(stream.try_clone()?));
+    {
+        let mut opened = opened.lock().unwrap_or_else(|e| e.into_inner());
+        if opened.going_away {
+            let _ = socket.close(CLOSE_GOING_AWAY, "going away");
+        }
+        opened.socket = Some(socket.clone());
+    }
+    handler.on_open(&socket);
+
+    // 모으고 있는 조각난 메시지의 opcode와 지금까지의 payload
+    let mut partial: Option<(u8, Vec<u8>)> = None;
+    // 마지막 프레임 이후에 ping을 보냈는지
+    let mut pinged = false;
+
+    let (code, reason) = loop {
+        // 다음 프레임의 첫 바이트를 기다린다. 프레임을 읽는 도중의 timeout은 연결이 끊긴 것으로 본다
+        match reader.fill_buf() {
+            Ok([]) => break (CLOSE_ABNORMAL, String::new()),
+            Ok(_) => pinged = false,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                // 이쪽에서 close를 보냈는데 답이 없으면 더 기다리지 않는다
+                if socket.is_closed() {
+                    break (CLOSE_ABNORMAL, String::new());
+                }
+                if pinged {
+                    let _ = socket.close(CLOSE_GOING_AWAY, "ping timeout");
+                    break (CLOSE_ABNORMAL, String::from("ping timeout"));
+                }
+                pinged = socket.ping(b"").is_ok();
+                continue;
+            },
+            Err(_) => break (CLOSE_ABNORMAL, String::new()),
+        }
+
+        let frame = match read_frame(&mut reader, MAX_MESSAGE_LEN) {
+            Ok(frame) => frame,
+            Err(FrameError::Disconnected) => break (CLOSE_ABNORMAL, String::new()),
+            Err(FrameError::Close(code, reason)) => {
+                let _ = socket.close(code, reason);
+                break (code, reason.to_string());
+            },
+        };
+
+        let message = match (frame.opcode, partial.take()) {
+            (OP_PING, partial_message) => {
+                partial = partial_message;
+                let _ = socket.send_frame(OP_PONG, &frame.payload);
+                continue;
+            },
+            (OP_PONG, partial_message) => {
+                partial = partial_message;
+                continue;
+            },
+            (OP_CLOSE, _) => {
+                let (code, reason) = match parse_close(&frame.payload) {
+                    Ok(close) => close,
+                    Err(reason) => {
+                        let _ = socket.close(CLOSE_PROTOCOL_ERROR, reason);
+                        break (CLOSE_PROTOCOL_ERROR, reason.to_string());
+                    },
+                };
+                // 이미 이쪽에서 close를 보냈다면 이것이 그 답장이므로 다시 보내지 않는다
+                let echo = if code == CLOSE_NO_STATUS { CLOSE_NORMAL } else { code };
+                let _ = socket.close(echo, "");
+                break (code, reason);
+            },
+            (OP_TEXT, None) | (OP_BINARY, None) => (frame.opcode, frame.payload),
+            (OP_CONTINUATION, Some((opcode, mut payload))) => {
+                if payload.len() + frame.payload.len() > MAX_MESSAGE_LEN {
+                    let _ = socket.close(CLOSE_TOO_BIG, "message too big");
+                    break (CLOSE_TOO_BIG, String::from("message too big"));
+                }
+                payload.extend_from_slice(&frame.payload);
+                (opcode, payload)
+            },
+            _ => {
+                let _ = socket.close(CLOSE_PROTOCOL_ERROR, "unexpected frame");
+                break (CLOSE_PROTOCOL_ERROR, String::from("unexpected frame"));
+            },
+        };
+
+        if !frame.fin {
+            partial = Some(message);
+            continue;
+        }
+        let message = match message {
+            (OP_TEXT, payload) => match String::from_utf8(payload) {
+                Ok(text) => WebSocketMessage::Text(text),
+                Err(_) => {
+                    let _ = socket.close(CLOSE_INVALID_DATA, "invalid utf-8");
+                    break (CLOSE_INVALID_DATA, String::from("invalid utf-8"));
+                },
+            },
+            (_, payload) => WebSocketMessage::Binary(payload),
+        };
+        handler.on_message(&socket, message);
+    };
+
+    // close를 주고받았으면 TCP 연결은 서버가 먼저 닫는다 (RFC 6455 7.1.1)
+    socket.inner.closed.store(true, Ordering::SeqCst);
+    let _ = stream.shutdown(Shutdown::Both);
+    handler.on_close(code, &reason);
+    Ok(())
+}
+
+/*
+close 프레임 payload: 비어 있거나, 2바이트 코드 + UTF-8 이유
+코드는 RFC 6455 7.4와 IANA에 등록된 것(1000~1003, 1007~1014)이나 라이브러리/응용용(3000~4999)만 받는다
+1005, 1006, 1015는 프레임으로 보내면 안 되는 코드다
+*/
+fn parse_close(payload: &[u8]) -> Result<(u16, String), &'static str> {
+    match payload.len() {
+        0 => Ok((CLOSE_NO_STATUS, String::new())),
+        1 => Err("invalid close payload"),
+        _ => {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+                return Err("invalid close code");
+            }
+            let reason = String::from_utf8(payload[2..].to_vec()).map_err(|_| "invalid close reason")?;
+            Ok((code, reason))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webserver::{Router, Server};
+    use std::thread;
+
+    #[test]
+    fn computes_accept_key() {
+        // RFC 6455 1.3의 예제
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+    }
+
+    #[test]
+    fn rejects_invalid_handshakes() {
+        let handshake = |version: &str, key: &str| {
+            let mut request = Request::new(Method::Get, "/ws");
+            request.headers.insert("Upgrade", "websocket");
+            request.headers.insert("Connection", "keep-alive, Upgrade");
+            request.headers.insert("Sec-WebSocket-Version", version);
+            request.headers.insert("Sec-WebSocket-Key", key);
+            upgrade_websocket(&request, |_: &WebSocket, _| {})
+        };
+
+        let accepted = handshake("13", "dGhlIHNhbXBsZSBub25jZQ==");
+        assert_eq!(101, accepted.status);
+        assert!(accepted.upgrade.is_some());
+        assert_eq!(426, handshake("8", "dGhlIHNhbXBsZSBub25jZQ==").status);
+        assert_eq!(400, handshake("13", "c2hvcnQ=").status);
+        assert_eq!(400, upgrade_websocket(&Request::new(Method::Get, "/ws"), |_: &WebSocket, _| {}).status);
+    }
+
+    #[test]
+    fn reads_masked_and_fragmented_frames() {
+        let mut raw = Vec::new();
+        write_frame(&mut raw, OP_TEXT, b"hello", Some([1, 2, 3, 4])).unwrap();
+        let frame = read_frame(&mut raw.as_slice(), MAX_MESSAGE_LEN).ok().unwrap();
+        assert!(frame.fin);
+        assert_eq!(b"hello".to_vec(), frame.payload);
+
+        let mut long = Vec::new();
+        write_frame(&mut long, OP_BINARY, &[7; 70000], Some([9, 9, 9, 9])).unwrap();
+        assert_eq!(70000, read_frame(&mut long.as_slice(), MAX_MESSAGE_LEN).ok().unwrap().payload.len());
+        assert!(matches!(read_frame(&mut long.as_slice(), 1000), Err(FrameError::Close(CLOSE_TOO_BIG, _))));
+
+        let mut unmasked = Vec::new();
+        write_frame(&mut unmasked, OP_TEXT, b"hi", None).unwrap();
+        assert!(matches!(read_frame(&mut unmasked.as_slice(), 100), Err(FrameError::Close(CLOSE_PROTOCOL_ERROR, _))));
+    }
+
+    #[test]
+    fn validates_close_codes() {
+        let close = |code: u16| parse_close(&code.to_be_bytes());
+        assert_eq!(Ok((1000, String::new())), close(1000));
+        assert_eq!(Ok((4000, String::new())), close(4000));
+        assert_eq!(Ok((CLOSE_NO_STATUS, String::new())), parse_close(&[]));
+        for code in [0, 999, 1004, 1005, 1006, 1015, 2000, 5000] {
+            assert_eq!(Err("invalid close code"), close(code), "code {}", code);
+        }
+        assert_eq!(Err("invalid close payload"), parse_close(&[3]));
+    }
+
+    // 연결된 TcpStream 한 쌍 (서버 쪽, 클라이언트 쪽)
+    fn stream_pair() -> (TcpStream, TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (listener.accept().unwrap().0, client)
+    }
+
+    #[test]
+    fn pings_idle_peer_and_closes_without_pong() {
+        let (server, mut client) = stream_pair();
+        let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+        struct Idle(std::sync::mpsc::Sender<(u16, String)>);
+        impl WebSocketHandler for Idle {
+            fn on_message(&mut self, _socket: &WebSocket, _message: WebSocketMessage) {}
+            fn on_close(&mut self, code: u16, reason: &str) {
+                self.0.send((code, reason.to_string())).unwrap();
+            }
+        }
+        let running = thread::spawn(move || run(server, Vec::new(), Idle(closed_tx), Duration::from_millis(100), &Mutex::default()));
+
+        // pong으로 답하면 연결이 유지되고, 다시 조용해지면 또 ping이 온다
+        assert_eq!((OP_PING, Vec::new()), read_server_frame(&mut client));
+        let mut pong = Vec::new();
+        write_frame(&mut pong, OP_PONG, b"", Some([1, 2, 3, 4])).unwrap();
+        client.write_all(&pong).unwrap();
+        assert_eq!((OP_PING, Vec::new()), read_server_frame(&mut client));
+
+        // 이번에는 답하지 않는다
+        let (opcode, payload) = read_server_frame(&mut client);
+        assert_eq!((OP_CLOSE, CLOSE_GOING_AWAY.to_be_bytes().to_vec()), (opcode, payload[..2].to_vec()));
+        assert_eq!((CLOSE_ABNORMAL, String::from("ping timeout")), closed_rx.recv().unwrap());
+        running.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_close_code_with_protocol_error() {
+        let (server, mut client) = stream_pair();
+        let running = thread::spawn(move || run(server, Vec::new(), |_: &WebSocket, _| {}, PING_INTERVAL, &Mutex::default()));
+
+        let mut close = Vec::new();
+        write_frame(&mut close, OP_CLOSE, &CLOSE_ABNORMAL.to_be_bytes(), Some([1, 1, 1, 1])).unwrap();
+        client.write_all(&close).unwrap();
+        let (opcode, payload) = read_server_frame(&mut client);
+        assert_eq!((OP_CLOSE, CLOSE_PROTOCOL_ERROR.to_be_bytes().to_vec()), (opcode, payload[..2].to_vec()));
+        running.join().unwrap().unwrap();
+    }
+
+    // 서버가 보낸(mask하지 않은) 프레임을 읽는다
+    fn read_server_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(0, head[1] & 0x80);
+        let mut payload = vec![0; (head[1] & 0x7f) as usize];
+        stream.read_exact(&mut payload).unwrap();
+        (head[0] & 0x0f, payload)
+    }
+
+    #[test]
+    fn echoes_messages_over_a_real_connection() {
+        let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+        let closed_tx = Mutex::new(closed_tx);
+        let mut router = Router::new();
+        router.get("/echo", move |request, _| {
+            struct Echo(std::sync::mpsc::Sender<u16>);
+            impl WebSocketHandler for Echo {
+                fn on_open(&mut self, socket: &WebSocket) {
+                    socket.send_text("welcome").unwrap();
+                }
+                fn on_message(&mut self, socket: &WebSocket, message: WebSocketMessage) {
+                    socket.send(message).unwrap();
+                }
+                fn on_close(&mut self, code: u16, _reason: &str) {
+                    self.0.send(code).unwrap();
+                }
+            }
+            upgrade_websocket(request, Echo(closed_tx.lock().unwrap().clone()))
+        });
+
+        let server = Server::bind("127.0.0.1:0", 2, router).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut handshake = String::from("GET /echo HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n");
+        handshake.push_str("Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n");
+        // handshake 바로 뒤에 첫 프레임을 붙여 보내도 처리되어야 한다
+        let mut first = handshake.into_bytes();
+        write_frame(&mut first, OP_PING, b"p", Some([5, 6, 7, 8])).unwrap();
+        stream.write_all(&first).unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(head.contains("Connection: Upgrade\r\n"));
+
+        assert_eq!((OP_TEXT, b"welcome".to_vec()), read_server_frame(&mut stream));
+        assert_eq!((OP_PONG, b"p".to_vec()), read_server_frame(&mut stream));
+
+        // 두 조각으로 나눈 메시지는 합쳐서 한 번에 돌아온다
+        stream.write_all(&[0x01, 0x83, 0, 0, 0, 0, b'a', b'b', b'c']).unwrap();
+        stream.write_all(&[0x80, 0x83, 0, 0, 0, 0, b'd', b'e', b'f']).unwrap();
+        assert_eq!((OP_TEXT, b"abcdef".to_vec()), read_server_frame(&mut stream));
+
+        let mut close = Vec::new();
+        write_frame(&mut close, OP_CLOSE, &1001u16.to_be_bytes(), Some([1, 1, 1, 1])).unwrap();
+        stream.write_all(&close).unwrap();
+        assert_eq!((OP_CLOSE, 1001u16.to_be_bytes().to_vec()), read_server_frame(&mut stream));
+        assert_eq!(1001u16, closed_rx.recv().unwrap());
+
+        handle.shutdown();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn upgraded_connections_do_not_hold_workers() {
+        let mut router = Router::new();
+        router
+            .get("/ws", |request, _| upgrade_websocket(request, |_: &WebSocket, _| {}))
+            .get("/", |_, _| Response::text(200, "ok"));
+        // worker가 하나뿐이어도 열려 있는 WebSocket 때문에 다른 요청이 막히지 않는다
+        let server = Server::bind("127.0.0.1:0", 1, router).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let (mut socket, head) = open_websocket(addr);
+        assert!(head.starts_with("HTTP/1.1 101 "), "{}", head);
+
+        let mut http = TcpStream::connect(addr).unwrap();
+        http.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        http.write_all(b"GET / HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        http.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+        // 종료할 때 열린 WebSocket에는 close 1001을 보내고, 답이 오면 기다리지 않고 끝난다
+        let started = std::time::Instant::now();
+        handle.shutdown();
+        let (opcode, payload) = read_server_frame(&mut socket);
+        assert_eq!((OP_CLOSE, &CLOSE_GOING_AWAY.to_be_bytes()[..], &b"going away"[..]), (opcode, &payload[..2], &payload[2..]));
+        let mut close = Vec::new();
+        write_frame(&mut close, OP_CLOSE, &CLOSE_GOING_AWAY.to_be_bytes(), Some([1, 2, 3, 4])).unwrap();
+        socket.write_all(&close).unwrap();
+        running.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn limits_upgraded_connections_and_closes_silent_ones_on_shutdown() {
+        let mut router = Router::new();
+        router.get("/ws", |request, _| upgrade_websocket(request, |_: &WebSocket, _| {}));
+        let mut server = Server::bind("127.0.0.1:0", 1, router).unwrap();
+        server.set_max_upgraded_connections(1);
+        server.set_drain_timeout(Duration::from_millis(300));
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let (mut socket, head) = open_websocket(addr);
+        assert!(head.starts_with("HTTP/1.1 101 "), "{}", head);
+        let (_, head) = open_websocket(addr);
+        assert!(head.starts_with("HTTP/1.1 503 "), "{}", head);
+        assert!(head.contains("Retry-After: 1\r\n"), "{}", head);
+
+        // close에 답하지 않는 연결은 drain_timeout이 지나면 서버가 끊는다
+        handle.shutdown();
+        assert_eq!(OP_CLOSE, read_server_frame(&mut socket).0);
+        let mut rest = Vec::new();
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        assert_eq!(0, socket.read_to_end(&mut rest).unwrap());
+        running.join().unwrap();
+    }
+
+    // handshake를 보내고 응답 헤더까지 읽는다
+    fn open_websocket(addr: std::net::SocketAddr) -> (TcpStream, String) {
+        let mut socket = TcpStream::connect(addr).unwrap();
+        socket
+            .write_all(b"GET /ws HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                         Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n")
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            socket.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        (socket, String::from_utf8(head).unwrap())
+    }
+}